@@ -0,0 +1,169 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use model::{Color, COLORS, PatternKind, PATTERN_KINDS};
+use game::{Game, Status};
+use record::Record;
+use tournament;
+use paths;
+
+// Every finished game played in the window or the terminal is added to one
+// file of records, one after another. Won games are tagged with the kind of
+// pattern that decided them, like [Pattern "three-of-same"].
+pub fn default_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("archive.txt"))
+}
+
+pub fn record(game: &Game) -> Record {
+    let mut record = Record::from_game(game);
+    if let Status::Won(_) = game.status() {
+        if let Some(pattern) = game.board().winning_pattern() {
+            record.set_tag("Pattern", pattern.kind.name());
+        }
+    }
+    record
+}
+
+// Adds a game to the end of the archive, creating it on first use
+pub fn add<P: AsRef<Path>>(path: P, record: &Record) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    record.write(&mut writer)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+// The archived games, none before the first one has been added
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Record>> {
+    match Record::load(path) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        result => result
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Statistics {
+    pub games: u32,
+    pub draws: u32,
+    // Results by turn order, the first seat moves first
+    pub seats: Vec<tournament::Record>,
+    pub colors: Vec<(Color, tournament::Record)>,
+    // How many games each kind of pattern decided, the most common first
+    pub patterns: Vec<(PatternKind, u32)>,
+    pub average_length: f64
+}
+
+impl Statistics {
+    // Unfinished games don't count
+    pub fn new(records: &[Record]) -> Statistics {
+        let mut seats = vec![tournament::Record::default(); COLORS.len()];
+        let mut colors: Vec<(Color, tournament::Record)> = COLORS.iter()
+            .map(|c| (*c, tournament::Record::default()))
+            .collect();
+        let mut patterns: Vec<(PatternKind, u32)> = PATTERN_KINDS.iter().map(|k| (*k, 0)).collect();
+        let mut games = 0;
+        let mut draws = 0;
+        let mut plies = 0;
+
+        for record in records.iter() {
+            let winner = match record.result {
+                Some(Status::Won(color)) => Some(color),
+                Some(Status::Draw) => None,
+                _ => continue
+            };
+            games += 1;
+            plies += record.moves.len();
+            if winner.is_none() {
+                draws += 1;
+            }
+            for (seat, &color) in record.players.iter().enumerate().take(COLORS.len()) {
+                let result = winner.map(|winner| winner == color);
+                seats[seat].add(result);
+                colors[color.index()].1.add(result);
+            }
+            if let Some(kind) = record.tag("Pattern").and_then(|kind| kind.parse().ok()) {
+                patterns[PATTERN_KINDS.iter().position(|k| *k == kind).unwrap()].1 += 1;
+            }
+        }
+
+        seats.retain(|record| record.games > 0);
+        colors.retain(|&(_, ref record)| record.games > 0);
+        patterns.retain(|&(_, count)| count > 0);
+        patterns.sort_by(|a, b| b.1.cmp(&a.1));
+        Statistics {
+            games: games,
+            draws: draws,
+            seats: seats,
+            colors: colors,
+            patterns: patterns,
+            average_length: if games == 0 { 0. } else { plies as f64 / games as f64 }
+        }
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.games == 0 {
+            return writeln!(f, "No finished games yet");
+        }
+        writeln!(f, "{} games, {:.1}% drawn, {:.1} plies on average",
+                 self.games, 100. * self.draws as f64 / self.games as f64, self.average_length)?;
+        writeln!(f)?;
+        writeln!(f, "{:<16} {:>6} {:>6} {:>6}", "Seat", "Games", "Won", "Drawn")?;
+        for (n, record) in self.seats.iter().enumerate() {
+            writeln!(f, "{:<16} {:>6} {:>5.1}% {:>5.1}%", format!("{}.", n + 1),
+                     record.games, 100. * record.win_rate(), 100. * record.draw_rate())?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<16} {:>6} {:>6} {:>6}", "Color", "Games", "Won", "Drawn")?;
+        for &(color, ref record) in self.colors.iter() {
+            writeln!(f, "{:<16} {:>6} {:>5.1}% {:>5.1}%", color.name(),
+                     record.games, 100. * record.win_rate(), 100. * record.draw_rate())?;
+        }
+        if !self.patterns.is_empty() {
+            let won = (self.games - self.draws) as f64;
+            writeln!(f)?;
+            writeln!(f, "{:<16} {:>6} {:>6}", "Pattern", "Games", "Share")?;
+            for &(kind, count) in self.patterns.iter() {
+                writeln!(f, "{:<16} {:>6} {:>5.1}%", kind.name(), count, 100. * count as f64 / won)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Tests
+
+#[test]
+fn test_statistics() {
+    let mut game = Game::two_player();
+    for mv in "a1S a2S b1S b2S c1S".split(' ') {
+        game.place(mv.parse().unwrap()).unwrap();
+    }
+    let won = record(&game);
+    assert_eq!(won.tag("Pattern"), Some("three-of-same"));
+
+    let mut drawn = Record::new(&[Color::Red, Color::Blue, Color::Green]);
+    drawn.moves = vec!["b2M".parse().unwrap(); 4];
+    drawn.result = Some(Status::Draw);
+    let unfinished = Record::from_game(&Game::two_player());
+
+    let stats = Statistics::new(&[won.clone(), drawn, won, unfinished]);
+    assert_eq!(stats.games, 3);
+    assert_eq!(stats.draws, 1);
+    assert_eq!(stats.average_length, 14. / 3.);
+    assert_eq!(stats.seats.len(), 3);
+    assert_eq!((stats.seats[0].wins, stats.seats[0].draws, stats.seats[1].losses), (2, 1, 2));
+    assert_eq!(stats.colors.iter().map(|&(color, _)| color).collect::<Vec<_>>(),
+               vec![Color::Blue, Color::Green, Color::Red]);
+    assert_eq!(stats.colors[0].1.games, 3);
+    assert_eq!(stats.patterns, vec![(PatternKind::ThreeOfSame, 2)]);
+    assert!(stats.to_string().contains("three-of-same         2 100.0%"));
+    assert_eq!(Statistics::new(&[]).to_string(), "No finished games yet\n");
+}
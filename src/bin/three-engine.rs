@@ -0,0 +1,16 @@
+extern crate three;
+
+use std::io;
+
+use three::engine::Knowledge;
+
+// Plays with the built-in bot over the engine protocol on stdin and stdout
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let knowledge = Knowledge::load();
+    if let Err(err) = three::protocol::run_engine(stdin.lock(), stdout.lock(), &knowledge) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
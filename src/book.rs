@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use model::{Color, location_to_index};
+use game::{Game, Move, Status};
+use engine::{Bot, Level, Knowledge};
+use random::Random;
+use paths;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct BookMove {
+    pub mv: Move,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32
+}
+
+impl BookMove {
+    fn new(mv: Move) -> BookMove {
+        BookMove {
+            mv: mv,
+            games: 0,
+            wins: 0,
+            draws: 0,
+            losses: 0
+        }
+    }
+
+    // Average result for the player making the move, from 0 to 1
+    pub fn score(&self) -> f64 {
+        if self.games == 0 {
+            return 0.;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.) / self.games as f64
+    }
+}
+
+// Bots only trust moves that have been played at least this many times
+pub const MIN_GAMES: u32 = 5;
+
+// How many players the games in the book have
+const BOOK_PLAYERS: usize = 2;
+
+const BOOK_HEADER: &'static str = "# three opening book";
+
+// Statistics for moves played in early positions of two player games. The
+// hash doesn't tell how many play, so games with more players are neither
+// added nor looked up. Positions are keyed by their canonical hash and moves
+// are stored in the canonical orientation.
+#[derive(Debug,Clone,Default)]
+pub struct Book {
+    positions: HashMap<u64, Vec<BookMove>>
+}
+
+impl Book {
+    pub fn new() -> Book {
+        Book {
+            positions: HashMap::new()
+        }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("book.txt"))
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    // Known moves for the position, best first. In symmetric positions every
+    // move equivalent to a known one is listed with the same statistics.
+    pub fn moves(&self, game: &Game) -> Vec<BookMove> {
+        if game.players().len() != BOOK_PLAYERS {
+            return vec![];
+        }
+        let (key, transforms) = game.canonical_transforms();
+        let mut moves: Vec<BookMove> = vec![];
+        for entry in self.positions.get(&key).map(|moves| &moves[..]).unwrap_or(&[]) {
+            for transform in transforms.iter() {
+                let mv = transform.restore_move(entry.mv);
+                if !moves.iter().any(|known| known.mv == mv) {
+                    moves.push(BookMove { mv: mv, ..*entry });
+                }
+            }
+        }
+        moves.sort_by(|a, b| b.score().partial_cmp(&a.score()).unwrap()
+                      .then(b.games.cmp(&a.games)));
+        moves
+    }
+
+    pub fn best(&self, game: &Game) -> Option<Move> {
+        self.moves(game).into_iter()
+            .find(|entry| entry.games >= MIN_GAMES)
+            .map(|entry| entry.mv)
+    }
+
+    // Adds the result of a finished game for the first plies of it
+    pub fn add_game(&mut self, players: &[Color], moves: &[Move], plies: usize) {
+        if players.len() != BOOK_PLAYERS {
+            return;
+        }
+        let mut game = Game::new(players);
+        for mv in moves {
+            if game.place(*mv).is_err() {
+                return;
+            }
+        }
+        let result = game.status();
+        if !game.is_over() {
+            return;
+        }
+        let mut game = Game::new(players);
+        for mv in moves.iter().take(plies) {
+            let mover = game.current();
+            let (key, transforms) = game.canonical_transforms();
+            let canonical = transforms.iter()
+                .map(|transform| transform.apply_move(*mv))
+                .min_by_key(|mv| (location_to_index(3, mv.location), mv.ring.index()))
+                .unwrap();
+            let entries = self.positions.entry(key).or_insert_with(Vec::new);
+            let index = match entries.iter().position(|entry| entry.mv == canonical) {
+                Some(index) => index,
+                None => {
+                    entries.push(BookMove::new(canonical));
+                    entries.len() - 1
+                }
+            };
+            let entry = &mut entries[index];
+            entry.games += 1;
+            match result {
+                Status::Won(color) if color == mover => entry.wins += 1,
+                Status::Won(_) => entry.losses += 1,
+                _ => entry.draws += 1
+            }
+            game.place(*mv).unwrap();
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Book> {
+        let file = File::open(path)?;
+        Book::read(BufReader::new(file))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Book> {
+        let mut book = Book::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let entry = if fields.len() == 6 {
+                parse_entry(&fields)
+            } else {
+                None
+            };
+            match entry {
+                Some((key, entry)) => book.positions.entry(key).or_insert_with(Vec::new).push(entry),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                  format!("invalid book entry: {}", line)))
+            }
+        }
+        Ok(book)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", BOOK_HEADER)?;
+        writeln!(writer, "# position move games wins draws losses")?;
+        let mut keys: Vec<&u64> = self.positions.keys().collect();
+        keys.sort();
+        for key in keys {
+            for entry in self.positions[key].iter() {
+                writeln!(writer, "{:016x} {} {} {} {} {}",
+                         key, entry.mv, entry.games, entry.wins, entry.draws, entry.losses)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+fn parse_entry(fields: &[&str]) -> Option<(u64, BookMove)> {
+    let key = u64::from_str_radix(fields[0], 16).ok()?;
+    let mv = fields[1].parse().ok()?;
+    Some((key, BookMove {
+        mv: mv,
+        games: fields[2].parse().ok()?,
+        wins: fields[3].parse().ok()?,
+        draws: fields[4].parse().ok()?,
+        losses: fields[5].parse().ok()?
+    }))
+}
+
+// Plays the given number of two player games between bots and collects the
+// statistics for their first plies. The opening move of every game is
+// random so that the book covers more than the bots' favourite line. The
+// bots follow what is already known, so later runs go deeper into it.
+pub fn build(games: u32, plies: usize, level: Level, seed: u64, knowledge: &Knowledge) -> Book {
+    let players = [Color::Blue, Color::Green];
+    let mut random = Random::new(seed);
+    let mut bots = vec![Bot::with_knowledge(level, Random::new(random.next_u64()), knowledge),
+                        Bot::with_knowledge(level, Random::new(random.next_u64()), knowledge)];
+    let mut book = Book::new();
+    for _ in 0..games {
+        let mut game = Game::new(&players);
+        let mut moves = vec![];
+        while !game.is_over() {
+            let mv = if moves.is_empty() {
+                let legal = game.legal_moves();
+                legal[random.below(legal.len())]
+            } else {
+                let seat = if game.current() == players[0] { 0 } else { 1 };
+                bots[seat].choose(&game).unwrap()
+            };
+            game.place(mv).unwrap();
+            moves.push(mv);
+        }
+        book.add_game(&players, &moves, plies);
+    }
+    book
+}
+
+// Tests
+
+#[cfg(test)]
+fn parse_moves(moves: &[&str]) -> Vec<Move> {
+    moves.iter().map(|mv| mv.parse().unwrap()).collect()
+}
+
+#[test]
+fn test_book_statistics() {
+    let players = [Color::Blue, Color::Green];
+    let mut book = Book::new();
+    let won = parse_moves(&["a1S", "a2S", "b1S", "b2S", "c1S"]);
+    // the same game mirrored across the middle row
+    let mirrored = parse_moves(&["a3S", "a2S", "b3S", "b2S", "c3S"]);
+    book.add_game(&players, &won, 2);
+    book.add_game(&players, &mirrored, 2);
+    assert_eq!(book.len(), 2);
+
+    // every corner is the same move on an empty board
+    let game = Game::new(&players);
+    let moves = book.moves(&game);
+    assert_eq!(moves.len(), 4);
+    assert_eq!(moves[0].games, 2);
+    assert_eq!(moves[0].wins, 2);
+    assert_eq!(moves[0].score(), 1.);
+    // not enough games to be trusted yet
+    assert_eq!(book.best(&game), None);
+
+    let mut game = Game::new(&players);
+    game.place("a3S".parse().unwrap()).unwrap();
+    let moves = book.moves(&game);
+    assert_eq!(moves.len(), 2);
+    assert!(moves.iter().any(|entry| entry.mv == "a2S".parse().unwrap()));
+    assert!(moves.iter().all(|entry| entry.losses == 2));
+
+    // nothing is known about games with more players
+    let three = [Color::Blue, Color::Green, Color::Red];
+    assert!(book.moves(&Game::new(&three)).is_empty());
+    book.add_game(&three, &parse_moves(&["a1S", "a2S", "b3S", "b1S", "b2S", "c3S", "c1S"]), 2);
+    assert_eq!(book.moves(&Game::new(&players))[0].games, 2);
+}
+
+#[test]
+fn test_book_round_trip() {
+    let book = build(10, 3, Level::Easy, 7, &Knowledge::default());
+    assert!(!book.is_empty());
+    let mut buffer = vec![];
+    book.write(&mut buffer).unwrap();
+    let loaded = Book::read(&buffer[..]).unwrap();
+    let game = Game::two_player();
+    assert_eq!(loaded.moves(&game), book.moves(&game));
+    let (key, _) = game.canonical_hash();
+    let games: u32 = loaded.positions[&key].iter().map(|entry| entry.games).sum();
+    assert_eq!(games, 10);
+    assert!(Book::read(&b"0123 a1S 1 1 0"[..]).is_err());
+}
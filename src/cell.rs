@@ -10,43 +10,10 @@ use gtk::{Inhibit, DrawingArea, WidgetExt};
 use cairo::Context;
 
 use model::{Ring, Color};
+use render::draw_ring;
 
 pub type Point = (f64, f64);
 
-pub type RGB = (f64, f64, f64);
-
-pub struct RingColor {
-    pub stroke: RGB,
-    pub fill: RGB
-}
-
-impl From<Option<Color>> for RingColor {
-    fn from(cell: Option<Color>) -> RingColor {
-        match cell {
-            Some(Color::Blue) => RingColor {
-                stroke: (0., 0., 0.),
-                fill: (0.118, 0.565, 1.000)
-            },
-            Some(Color::Green) => RingColor {
-                stroke: (0., 0., 0.),
-                fill: (0.196, 0.804, 0.196)
-            },
-            Some(Color::Red) => RingColor {
-                stroke: (0., 0., 0.),
-                fill: (0.698, 0.133, 0.133)
-            },
-            Some(Color::Yellow) => RingColor {
-                stroke: (0., 0., 0.),
-                fill: (0.957, 0.643, 0.376) 
-            },
-            None => RingColor {
-                stroke: (0., 0., 0.),
-                fill: (1., 1., 1.)
-            }
-        }
-    }
-}
-
 // Sadly I had to wrap almost everything inside a RefCell
 // to make Gtk and borrow checker happy
 pub struct Cell {
@@ -55,6 +22,7 @@ pub struct Cell {
     top: RefCell<Option<Color>>,
     middle: RefCell<Option<Color>>,
     bottom: RefCell<Option<Color>>,
+    hints: RefCell<Vec<Ring>>,
     pub callback: RefCell<Option<Box<Fn(Ring)>>>,
     log: Logger
 }
@@ -70,6 +38,7 @@ impl Cell {
             top: RefCell::new(None),
             middle: RefCell::new(None),
             bottom: RefCell::new(None),
+            hints: RefCell::new(vec![]),
             callback: RefCell::new(None),
             log: log.new(None)
         };
@@ -103,23 +72,16 @@ impl Cell {
     pub fn draw(&self, area: &DrawingArea, ctx: &Context) -> Inhibit {
         debug!(self.log, "draw event");
 
-        fn draw_ring(ctx: &Context,
-                     width: f64,
-                     height: f64,
-                     ring: Ring,
-                     color: RingColor) {
+        fn draw_hint(ctx: &Context, width: f64, height: f64, ring: Ring) {
             let (x, y) = (width / 2.0, height / 2.0);
-            let radius = ring.radius(width, height);
+            let radius = ring.radius(width, height) - 4.;
             ctx.save();
-            ctx.set_line_width(2.0);
-            let (r, g, b) = color.stroke;
-            ctx.set_source_rgb(r, g, b);
+            ctx.set_line_width(3.0);
+            ctx.set_source_rgb(0.2, 0.2, 0.2);
+            ctx.set_dash(&[6., 4.], 0.);
             ctx.translate(x, y);
             ctx.arc(0., 0., radius, 0., 2. * f64::consts::PI);
-            ctx.stroke_preserve();
-            let (r, g, b) = color.fill;
-            ctx.set_source_rgb(r, g, b);
-            ctx.fill();
+            ctx.stroke();
             ctx.restore();
         }
 
@@ -135,6 +97,10 @@ impl Cell {
         draw_ring(ctx, width, height, Ring::Middle, (*self.middle.borrow()).into());
         draw_ring(ctx, width, height, Ring::Top, (*self.top.borrow()).into());
 
+        for ring in self.hints.borrow().iter() {
+            draw_hint(ctx, width, height, *ring);
+        }
+
         Inhibit(true)
     }
 
@@ -221,6 +187,12 @@ impl Cell {
         *ring = color;
     }
 
+    // Rings to mark as suggestions, such as moves from the opening book
+    pub fn set_hints(&self, hints: Vec<Ring>) {
+        *self.hints.borrow_mut() = hints;
+        self.drawing_area.borrow().queue_draw();
+    }
+
 }
 
 fn point_inside_circle((circle_x, circle_y): Point, radius: f64, (x, y): Point) -> bool {
@@ -0,0 +1,815 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use model::{Color, Ring, COLORS};
+use game::{Game, Move, MoveError, ParseMoveError, Status};
+use solver::{Solver, Strategy};
+use engine::{Bot, Level, Knowledge};
+use book::{self, Book};
+use random::Random;
+use record::{Record, result_name};
+use external::ExternalEngine;
+use tournament::{self, Entrant};
+use server::{Server, DEFAULT_HTTP_PORT};
+use correspondence::Correspondence;
+use clock::{Clock, TimeControl, format_time};
+use series::{Length, Start};
+use net::{Connection, Host, Client, Abandoned, DEFAULT_PORT};
+use discovery;
+use archive::{self, Statistics};
+use render::{self, Marks};
+use tui;
+
+const USAGE: &'static str = "Usage: three [COMMAND]
+
+Commands:
+  gui [--engine COLOR=PROGRAM]... [--time-control CONTROL] [--best-of N | --first-to N]
+      [--start START]
+      [--host PORT [--players COLORS] [--name NAME] [--seat-timeout SECONDS[=LEVEL]]
+       | --join ADDRESS [--token TOKEN] | --watch ADDRESS]
+                                   Open the game window, the default
+  play [--players COLORS] [--start START] [--bot COLOR[=LEVEL]]... [--time-control CONTROL]
+                                   Play in the terminal
+  tui [--players COLORS] [--start START] [--bot COLOR[=LEVEL]]...
+                                   Play full screen in the terminal
+  analyse POSITION [--players COLORS]
+                                   Show a position and the best move in it
+  selfplay [--games N] [--level LEVEL] [--players N] [--start START] [--seed N]
+                                   Print games between bots as records
+  validate RECORD                  Check the moves and results of recorded games
+  stats [RECORD]                   Show win rates, winning patterns and game
+                                   lengths of the archived or recorded games
+  render POSITION OUTPUT [--size PIXELS] [--players COLORS]
+                                   Draw a position as a PNG or SVG image
+  animate GAME OUTPUT [--size PIXELS] [--delay MS] [--players COLORS]
+                                   Draw every move of a game as an animated
+                                   SVG or a directory of PNG frames
+  solve [OUTPUT]                   Solve the two player game and write the
+                                   table perfect bots play from
+  book [--games N] [--plies N] [--level LEVEL] [--seed N] [OUTPUT]
+                                   Build the opening book
+  tournament ENTRANT... [--games N] [--seed N]
+                                   Play bots against each other
+  serve [--port PORT]              Host games for other programs over HTTP
+                                   and WebSockets
+  correspond new GAME --as COLOR [--players COLORS]
+  correspond move GAME MOVE OUTPUT
+  correspond export GAME OUTPUT
+  correspond import GAME REPLY [--as COLOR]
+                                   Play by exchanging files, GAME is your
+                                   copy and OUTPUT the file to send
+
+A position is either a record file or moves separated by commas, like
+b2M,a1S. Colors are given the same way, like blue,green,red. Time controls
+are seconds for the game plus seconds added per move, like 300+5, or
+seconds for every move, like 10/move. The player to start is a color,
+rotate to take turns between games, or random with an optional seed, like
+random:42.";
+
+// Everything except the window, none of this needs a display
+pub fn run(args: &[String]) {
+    let command = &args[0][..];
+    let args = &args[1..];
+    match command {
+        "play" => play(args),
+        "tui" => {
+            let (players, start, bots) = game_options(args);
+            tui::run(&players, start, bots);
+        },
+        "analyse" | "analyze" => analyse(args),
+        "selfplay" => selfplay(args),
+        "validate" => validate(args),
+        "stats" => show_statistics(args),
+        "render" => render_position(args),
+        "animate" => animate(args),
+        "solve" => solve(args.get(0).map(PathBuf::from)),
+        "book" => build_book(args),
+        "tournament" => run_tournament(args),
+        "serve" => serve(args),
+        "correspond" => correspond(args),
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        _ => fail(&format!("Unknown command {}\n\n{}", command, USAGE))
+    }
+}
+
+fn colors_value(name: &str, value: Option<&String>) -> Vec<Color> {
+    let colors = value.map(|value| {
+        value.split(',')
+            .map(|color| color.trim().parse())
+            .collect::<Result<Vec<Color>, String>>()
+    });
+    match colors {
+        Some(Ok(ref colors)) if colors.len() >= 2 && colors.len() <= 4 => colors.clone(),
+        Some(Err(err)) => fail(&err),
+        _ => fail(&format!("Give two to four colors for {}", name))
+    }
+}
+
+// Reads a position from a record file or a list of moves
+fn load_position(position: &str, players: &[Color]) -> Game {
+    let record = if Path::new(position).is_file() {
+        let records = Record::load(position)
+            .unwrap_or_else(|err| fail(&format!("Could not read {}: {}", position, err)));
+        records.into_iter().next()
+            .unwrap_or_else(|| fail(&format!("No games in {}", position)))
+    } else {
+        let mut record = Record::new(players);
+        for mv in position.split(|c: char| c == ',' || c.is_whitespace()) {
+            if !mv.is_empty() {
+                let mv = mv.parse().unwrap_or_else(|err: ParseMoveError| fail(&err.to_string()));
+                record.moves.push(mv);
+            }
+        }
+        record
+    };
+    record.replay().unwrap_or_else(|err| fail(&err.to_string()))
+}
+
+pub fn supply_text(game: &Game, color: Color) -> String {
+    let supply = game.supply(color);
+    let rings: Vec<String> = [(Ring::Bottom, 'L'), (Ring::Middle, 'M'), (Ring::Top, 'S')].iter()
+        .map(|&(ring, size)| format!("{}{}", size, supply.remaining(ring)))
+        .collect();
+    format!("{:?} has {} left", color, rings.join(" "))
+}
+
+pub fn status_text(status: Status) -> String {
+    match status {
+        Status::Playing(color) => format!("{:?}'s turn", color),
+        Status::Won(color) => format!("{:?} wins!", color),
+        Status::Draw => "It's a draw!".to_owned()
+    }
+}
+
+// The time left for everyone still in the game
+pub fn clock_text(clock: &Clock, game: &Game, now: Instant) -> String {
+    let times: Vec<String> = game.players().iter()
+        .filter(|&&color| game.is_active(color))
+        .map(|&color| format!("{:?} {}", color, format_time(clock.remaining(color, now))))
+        .collect();
+    times.join("  ")
+}
+
+const PLAY_HELP: &'static str = "Type a move as column, row and ring size, like b2M for the medium
+ring in the middle. Each cell shows who owns its large, medium and small
+ring. Other commands are undo, resign, draw to offer one, save FILE and
+quit.";
+
+// Players and bots are given the same way for play and tui
+fn game_options(args: &[String]) -> (Vec<Color>, Start, Vec<(Color, Bot)>) {
+    let mut players = vec![Color::Blue, Color::Green];
+    let mut start = Start::Color(Color::Blue);
+    let mut bot_colors: Vec<(Color, Level)> = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--players" => players = colors_value(arg, args.next()),
+            "--start" => start = option_value(arg, args.next()),
+            "--bot" => {
+                let spec = args.next().unwrap_or_else(|| fail("Missing value for --bot"));
+                let mut parts = spec.splitn(2, '=');
+                let color = parts.next().unwrap().parse().unwrap_or_else(|err: String| fail(&err));
+                let level = parts.next()
+                    .map(|level| level.parse().unwrap_or_else(|err: String| fail(&err)))
+                    .unwrap_or(Level::Hard);
+                bot_colors.push((color, level));
+            },
+            _ => fail(&format!("Unknown argument {}", arg))
+        }
+    }
+    let mut random = Random::from_time();
+    let knowledge = Knowledge::load();
+    let bots = bot_colors.into_iter()
+        .map(|(color, level)| {
+            (color, Bot::with_knowledge(level, Random::new(random.next_u64()), &knowledge))
+        })
+        .collect();
+    (players, start, bots)
+}
+
+// Places a move against the clock. Whoever took too long is out of the
+// game instead, which is only noticed once they move.
+fn place_timed(game: &mut Game, clock: &mut Option<Clock>, mv: Move) -> Result<(), MoveError> {
+    game.check(mv)?;
+    let color = game.current();
+    if let Some(ref mut clock) = *clock {
+        let now = Instant::now();
+        if clock.expired(now) == Some(color) {
+            clock.stop();
+            println!("{:?} ran out of time", color);
+            return game.forfeit(color);
+        }
+        clock.moved(now);
+    }
+    game.place(mv)
+}
+
+fn play(args: &[String]) {
+    // Only play has clocks, the other options are shared with tui
+    let mut control = None;
+    let mut rest = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match &arg[..] {
+            "--time-control" => control = Some(option_value::<TimeControl>(arg, iter.next())),
+            _ => rest.push(arg.clone())
+        }
+    }
+    let (players, start, mut bots) = game_options(&rest);
+
+    println!("{}\n", PLAY_HELP);
+    let mut game = Game::new(&start.players(&players, 0));
+    let mut clock = control.map(Clock::new);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let color = match game.status() {
+            Status::Playing(color) => color,
+            status => {
+                println!("{}\n\n{}", game.board(), status_text(status));
+                let mut record = archive::record(&game);
+                record.set_tag("Start", &start.to_string());
+                if let Some(ref clock) = clock {
+                    clock.save(&mut record);
+                }
+                if let Some(err) = archive_game(&record) {
+                    println!("{}", err);
+                }
+                return;
+            }
+        };
+        if let Some(ref mut clock) = clock {
+            clock.start(color, Instant::now());
+        }
+        if let Some(&mut (_, ref mut bot)) = bots.iter_mut().find(|&&mut (c, _)| c == color) {
+            let mv = bot.choose(&game).unwrap();
+            println!("{:?} plays {}", color, mv);
+            place_timed(&mut game, &mut clock, mv).unwrap();
+            continue;
+        }
+
+        println!("{}\n\n{}", game.board(), supply_text(&game, color));
+        if let Some(ref clock) = clock {
+            println!("{}", clock_text(clock, &game, Instant::now()));
+        }
+        print!("{:?}> ", color);
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return
+        };
+        match line.trim() {
+            "" => {},
+            "quit" | "q" => return,
+            "help" | "?" => println!("{}", PLAY_HELP),
+            "undo" => {
+                // Take back the bots' replies too, with the time they took
+                let mut undo = |game: &mut Game| if game.undo().is_some() {
+                    if let Some(ref mut clock) = clock {
+                        clock.undo();
+                    }
+                };
+                undo(&mut game);
+                while !game.history().is_empty() &&
+                    bots.iter().any(|&(c, _)| c == game.current()) {
+                    undo(&mut game);
+                }
+            },
+            "resign" => {
+                game.resign(color).unwrap();
+                println!("{:?} resigns", color);
+            },
+            "draw" => {
+                // Bots play on, everyone else has to agree
+                let mut agreed = true;
+                let others: Vec<Color> = game.players().iter().cloned()
+                    .filter(|&c| c != color && game.is_active(c))
+                    .collect();
+                for other in others {
+                    if bots.iter().any(|&(c, _)| c == other) {
+                        println!("{:?} declines", other);
+                        agreed = false;
+                        break;
+                    }
+                    print!("{:?} offers a draw, does {:?} accept? ", color, other);
+                    io::stdout().flush().unwrap();
+                    match lines.next() {
+                        Some(Ok(ref answer)) if answer.trim() == "yes" || answer.trim() == "y" => {},
+                        _ => {
+                            agreed = false;
+                            break;
+                        }
+                    }
+                }
+                if agreed {
+                    game.agree_draw().unwrap();
+                }
+            },
+            text if text.starts_with("save ") => {
+                let path = text[5..].trim();
+                let mut record = Record::from_game(&game);
+                record.set_tag("Start", &start.to_string());
+                if let Some(ref clock) = clock {
+                    clock.save(&mut record);
+                }
+                match record.save(path) {
+                    Ok(()) => println!("Saved to {}", path),
+                    Err(err) => println!("Could not write {}: {}", path, err)
+                }
+            },
+            text => {
+                let result = text.parse::<Move>()
+                    .map_err(|err| err.to_string())
+                    .and_then(|mv| place_timed(&mut game, &mut clock, mv).map_err(|err| err.to_string()));
+                if let Err(err) = result {
+                    println!("{}", err);
+                }
+            }
+        }
+        println!();
+    }
+}
+
+fn analyse(args: &[String]) {
+    let mut players = vec![Color::Blue, Color::Green];
+    let mut position = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--players" => players = colors_value(arg, args.next()),
+            other => position = Some(other.to_owned())
+        }
+    }
+    let position = position.unwrap_or_else(|| fail("No position given"));
+    let game = load_position(&position, &players);
+
+    println!("{}\n", game.board());
+    println!("{}", status_text(game.status()));
+    if game.is_over() {
+        return;
+    }
+    let color = game.current();
+    println!("{}", supply_text(&game, color));
+    println!("{} legal moves", game.legal_moves().len());
+
+    if let Some(book) = Book::default_path().and_then(|path| Book::load(path).ok()) {
+        for entry in book.moves(&game).iter().take(5) {
+            println!("Book: {} played {} times, scoring {:.0}%",
+                     entry.mv, entry.games, 100. * entry.score());
+        }
+    }
+    if game.players().len() == 2 {
+        let mut solver = Solver::new();
+        let (mv, value) = solver.best_move(&game).unwrap();
+        println!("Best move: {}, {} for {:?}", mv, value, color);
+    } else {
+        let mut bot = Bot::new(Level::Hard, Random::from_time());
+        let mv = bot.choose_within(&game, Duration::from_secs(2)).unwrap();
+        println!("Suggested move: {}", mv);
+    }
+}
+
+fn selfplay(args: &[String]) {
+    let mut games = 1;
+    let mut level = Level::Medium;
+    let mut count = 2;
+    let mut start = Start::Color(Color::Blue);
+    let mut seed = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--games" => games = option_value(arg, args.next()),
+            "--level" => level = option_value(arg, args.next()),
+            "--players" => count = option_value(arg, args.next()),
+            "--start" => start = option_value(arg, args.next()),
+            "--seed" => seed = Some(option_value(arg, args.next())),
+            _ => fail(&format!("Unknown argument {}", arg))
+        }
+    }
+    if count < 2 || count > 4 {
+        fail("Games have two to four players");
+    }
+    let mut random = Random::new(seed.unwrap_or_else(|| Random::from_time().next_u64()));
+    let players = &COLORS[..count];
+    let knowledge = Knowledge::load();
+
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    for n in 0..games {
+        let mut bots: Vec<Bot> = players.iter()
+            .map(|_| Bot::with_knowledge(level, Random::new(random.next_u64()), &knowledge))
+            .collect();
+        let mut game = Game::new(&start.players(players, n));
+        while let Status::Playing(color) = game.status() {
+            let seat = players.iter().position(|c| *c == color).unwrap();
+            let mv = bots[seat].choose(&game).unwrap();
+            game.place(mv).unwrap();
+        }
+        let mut record = Record::from_game(&game);
+        record.set_tag("Level", level.name());
+        record.set_tag("Start", &start.to_string());
+        if n > 0 {
+            writeln!(output).unwrap();
+        }
+        record.write(&mut output).unwrap();
+    }
+}
+
+fn validate(args: &[String]) {
+    let path = args.get(0).unwrap_or_else(|| fail("No record given"));
+    let records = Record::load(path)
+        .unwrap_or_else(|err| fail(&format!("Could not read {}: {}", path, err)));
+    if records.is_empty() {
+        fail(&format!("No games in {}", path));
+    }
+    let mut valid = true;
+    for (n, record) in records.iter().enumerate() {
+        match record.replay() {
+            Ok(game) => {
+                let result = if game.is_over() { Some(game.status()) } else { None };
+                println!("Game {}: {} moves, result {}", n + 1, record.moves.len(), result_name(result));
+            },
+            Err(err) => {
+                println!("Game {}: {}", n + 1, err);
+                valid = false;
+            }
+        }
+    }
+    if !valid {
+        process::exit(1);
+    }
+}
+
+// Adds a finished game to the archive, telling what went wrong if it couldn't
+pub fn archive_game(record: &Record) -> Option<String> {
+    let path = archive::default_path()?;
+    archive::add(&path, record).err()
+        .map(|err| format!("Could not archive the game in {}: {}", path.display(), err))
+}
+
+fn show_statistics(args: &[String]) {
+    let path = args.get(0).map(PathBuf::from).or_else(archive::default_path)
+        .unwrap_or_else(|| fail("No archive found"));
+    let records = archive::load(&path)
+        .unwrap_or_else(|err| fail(&format!("Could not read {}: {}", path.display(), err)));
+    print!("{}", Statistics::new(&records));
+}
+
+fn render_position(args: &[String]) {
+    let mut players = vec![Color::Blue, Color::Green];
+    let mut size = 480;
+    let mut paths = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--players" => players = colors_value(arg, args.next()),
+            "--size" => size = option_value(arg, args.next()),
+            other => paths.push(other.to_owned())
+        }
+    }
+    if paths.len() != 2 {
+        fail("Give a position and an output file");
+    }
+    let game = load_position(&paths[0], &players);
+    if let Err(err) = render::save(game.board(), &Marks::from_game(&game), size, &paths[1]) {
+        fail(&format!("Could not write {}: {}", paths[1], err));
+    }
+}
+
+fn animate(args: &[String]) {
+    let mut players = vec![Color::Blue, Color::Green];
+    let mut size = 480;
+    let mut delay = 1000;
+    let mut paths = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--players" => players = colors_value(arg, args.next()),
+            "--size" => size = option_value(arg, args.next()),
+            "--delay" => delay = option_value(arg, args.next()),
+            other => paths.push(other.to_owned())
+        }
+    }
+    if paths.len() != 2 {
+        fail("Give a game and an output file or directory");
+    }
+    let game = load_position(&paths[0], &players);
+    let delay = Duration::from_millis(delay);
+    if let Err(err) = render::save_animation(&game, size, delay, &paths[1]) {
+        fail(&format!("Could not write {}: {}", paths[1], err));
+    }
+}
+
+// Perfect bots read the table from the default path
+fn solve(output: Option<PathBuf>) {
+    let output = output.or_else(Strategy::default_path)
+        .unwrap_or_else(|| fail("No output file given"));
+    let game = Game::two_player();
+    let mut solver = Solver::new();
+    println!("First player: {}", solver.solve(&game));
+    let strategy = solver.strategy(&game);
+    println!("{} positions in the strategy table, {} searched",
+             strategy.len(), solver.nodes());
+    if let Some(dir) = output.parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            fail(&format!("Could not create {}: {}", dir.display(), err));
+        }
+    }
+    if let Err(err) = strategy.save(&output) {
+        fail(&format!("Could not write {}: {}", output.display(), err));
+    }
+    println!("Wrote the strategy table to {}", output.display());
+}
+
+// Engines are given as --engine COLOR=PROGRAM, one for each color they
+// play. --host PORT opens a game for others to --join or --watch, with the
+// host playing the first of the --players. Hosted games are announced on the
+// local network under --name, or the name of the machine. Seats of players
+// who drop out are kept for them for --seat-timeout seconds, after which a
+// bot of the given level takes over or, without a level, the color forfeits.
+// --token takes a seat back after this end was closed. Clocks and matches of
+// several games are only kept in games played on one machine. Matches rotate
+// the player to --start unless told otherwise.
+pub fn gui_options(args: &[String]) -> (Vec<(Color, ExternalEngine)>, Option<Connection>,
+                                        Option<TimeControl>, Option<Length>, Start) {
+    let mut engines = vec![];
+    let mut time_control = None;
+    let mut series = None;
+    let mut start = None;
+    let mut players = vec![Color::Blue, Color::Green];
+    let mut host = None;
+    let mut name = None;
+    let mut seat_timeout = None;
+    let mut join = None;
+    let mut token = None;
+    let mut watch = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--engine" => {
+                let spec = args.next().unwrap_or_else(|| fail("Missing value for --engine"));
+                let (color, program) = match spec.find('=') {
+                    Some(i) => (&spec[..i], &spec[i + 1..]),
+                    None => fail(&format!("Expected COLOR=PROGRAM, got {}", spec))
+                };
+                let color: Color = color.parse().unwrap_or_else(|err: String| fail(&err));
+                match ExternalEngine::start(program) {
+                    Ok(engine) => engines.push((color, engine)),
+                    Err(err) => fail(&format!("Could not start {}: {}", program, err))
+                }
+            },
+            "--players" => players = colors_value(arg, args.next()),
+            "--time-control" => time_control = Some(option_value::<TimeControl>(arg, args.next())),
+            "--best-of" => series = Some(Length::BestOf(option_value(arg, args.next()))),
+            "--first-to" => series = Some(Length::FirstTo(option_value(arg, args.next()))),
+            "--start" => start = Some(option_value::<Start>(arg, args.next())),
+            "--host" => host = Some(option_value::<u16>(arg, args.next())),
+            "--name" => name = Some(option_value::<String>(arg, args.next())),
+            "--seat-timeout" => {
+                let spec = args.next().unwrap_or_else(|| fail("Missing value for --seat-timeout"));
+                let (seconds, abandoned) = match spec.find('=') {
+                    Some(i) => {
+                        let level = spec[i + 1..].parse().unwrap_or_else(|err: String| fail(&err));
+                        (&spec[..i], Abandoned::Bot(level))
+                    },
+                    None => (&spec[..], Abandoned::Forfeit)
+                };
+                let seconds = seconds.parse()
+                    .unwrap_or_else(|_| fail(&format!("Expected SECONDS[=LEVEL], got {}", spec)));
+                seat_timeout = Some((Duration::from_secs(seconds), abandoned));
+            },
+            "--token" => token = Some(option_value::<String>(arg, args.next())),
+            "--join" | "--watch" => {
+                let address = args.next()
+                    .unwrap_or_else(|| fail(&format!("Missing value for {}", arg)));
+                join = Some(address.clone());
+                watch = arg == "--watch";
+            },
+            _ => fail(&format!("Unknown argument {}", arg))
+        }
+    }
+    if time_control.is_some() && (host.is_some() || join.is_some()) {
+        fail("Clocks are only kept in games played on one machine");
+    }
+    if series.is_some() && (host.is_some() || join.is_some()) {
+        fail("Matches are only played on one machine");
+    }
+    if series == Some(Length::BestOf(0)) || series == Some(Length::FirstTo(0)) {
+        fail("A match has at least one game");
+    }
+    if start.is_some() && join.is_some() {
+        fail("The host of a game decides who starts");
+    }
+    let start = start.unwrap_or(if series.is_some() { Start::Rotate } else { Start::Color(Color::Blue) });
+    let connection = match (host, join) {
+        (Some(_), Some(_)) => fail("Give either --host or --join"),
+        (Some(port), None) => match Host::bind(("0.0.0.0", port), &start.players(&players, 0), &players[..1]) {
+            Ok(mut host) => {
+                if let Some((timeout, abandoned)) = seat_timeout {
+                    host.set_seat_timeout(timeout, abandoned);
+                }
+                // Others can still join by address
+                let name = name.unwrap_or_else(discovery::host_name);
+                if let Err(err) = host.announce(discovery::broadcast_address(), &name) {
+                    eprintln!("Could not announce the game: {}", err);
+                }
+                Some(Connection::Host(host))
+            },
+            Err(err) => fail(&format!("Could not listen on port {}: {}", port, err))
+        },
+        (None, Some(address)) => {
+            let address = if address.contains(':') {
+                address
+            } else {
+                format!("{}:{}", address, DEFAULT_PORT)
+            };
+            let client = match (watch, token) {
+                (true, _) => Client::watch(&address[..]),
+                (false, Some(token)) => Client::resume(&address[..], &token),
+                (false, None) => Client::connect(&address[..])
+            };
+            match client {
+                Ok(client) => {
+                    if let (Some(color), Some(token)) = (client.color(), client.token()) {
+                        println!("Playing {:?}, to take the seat back later use --token {}",
+                                 color, token);
+                    }
+                    Some(Connection::Client(client))
+                },
+                Err(err) => fail(&format!("Could not join {}: {}", address, err))
+            }
+        },
+        (None, None) => None
+    };
+    (engines, connection, time_control, series, start)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn option_value<T: FromStr>(name: &str, value: Option<&String>) -> T {
+    value.and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| fail(&format!("Invalid or missing value for {}", name)))
+}
+
+fn build_book(args: &[String]) {
+    let mut games = 1000;
+    let mut plies = 6;
+    let mut level = Level::Medium;
+    let mut seed = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--games" => games = option_value(arg, args.next()),
+            "--plies" => plies = option_value(arg, args.next()),
+            "--level" => level = option_value(arg, args.next()),
+            "--seed" => seed = Some(option_value(arg, args.next())),
+            path => output = Some(PathBuf::from(path))
+        }
+    }
+    let output = output.or_else(Book::default_path)
+        .unwrap_or_else(|| fail("No output file given"));
+    let seed = seed.unwrap_or_else(|| Random::from_time().next_u64());
+
+    println!("Playing {} games at {} level", games, level);
+    let book = book::build(games, plies, level, seed, &Knowledge::load());
+    if let Some(dir) = output.parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            fail(&format!("Could not create {}: {}", dir.display(), err));
+        }
+    }
+    if let Err(err) = book.save(&output) {
+        fail(&format!("Could not write {}: {}", output.display(), err));
+    }
+    println!("Wrote {} positions to {}", book.len(), output.display());
+}
+
+fn run_tournament(args: &[String]) {
+    let mut games = 100;
+    let mut seed = None;
+    let mut entrants: Vec<Entrant> = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--games" => games = option_value(arg, args.next()),
+            "--seed" => seed = Some(option_value(arg, args.next())),
+            entrant => entrants.push(entrant.parse().unwrap_or_else(|err: String| fail(&err)))
+        }
+    }
+    if entrants.len() < 2 || entrants.len() > 4 {
+        fail("Give two to four entrants, like: three tournament hard medium@200");
+    }
+    let seed = seed.unwrap_or_else(|| Random::from_time().next_u64());
+
+    println!("Playing {} games, seed {}", games, seed);
+    let report = tournament::run(&entrants, games, seed, &Knowledge::load());
+    print!("{}", report);
+}
+
+fn serve(args: &[String]) {
+    let mut port = DEFAULT_HTTP_PORT;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--port" => port = option_value(arg, args.next()),
+            _ => fail(&format!("Unknown argument {}", arg))
+        }
+    }
+    let server = Server::bind(("0.0.0.0", port))
+        .unwrap_or_else(|err| fail(&format!("Could not listen on port {}: {}", port, err)));
+    println!("Serving games on http://localhost:{}/games", port);
+    server.run();
+}
+
+fn show_correspondence(correspondence: &Correspondence) {
+    let game = correspondence.game();
+    println!("{}\n", game.board());
+    println!("{}", status_text(game.status()));
+    if game.current() == correspondence.me() && !game.is_over() {
+        println!("{}", supply_text(game, correspondence.me()));
+    }
+}
+
+fn correspond(args: &[String]) {
+    let mut me = None;
+    let mut players = vec![Color::Blue, Color::Green];
+    let mut positional = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--as" => me = Some(option_value::<Color>(arg, args.next())),
+            "--players" => players = colors_value(arg, args.next()),
+            other => positional.push(other)
+        }
+    }
+    let load = |path: &str| Correspondence::load(path)
+        .unwrap_or_else(|err| fail(&format!("Could not read {}: {}", path, err)));
+    let save = |correspondence: &Correspondence, path: &str| if let Err(err) = correspondence.save(path) {
+        fail(&format!("Could not write {}: {}", path, err));
+    };
+    let correspondence = match &positional[..] {
+        &["new", path] => {
+            let me = me.unwrap_or_else(|| fail("Give the color you play with --as"));
+            if !players.contains(&me) {
+                fail(&format!("{} doesn't play in this game", me.name()));
+            }
+            let correspondence = Correspondence::new(&players, me);
+            save(&correspondence, path);
+            correspondence
+        },
+        &["move", path, mv, output] => {
+            let mut correspondence = load(path);
+            let mv = mv.parse().unwrap_or_else(|err: ParseMoveError| fail(&err.to_string()));
+            if let Err(err) = correspondence.play(mv) {
+                fail(&format!("Can't play {}: {}", mv, err));
+            }
+            if let Err(err) = correspondence.export().save(output) {
+                fail(&format!("Could not write {}: {}", output, err));
+            }
+            save(&correspondence, path);
+            println!("Send {} to the other players", output);
+            correspondence
+        },
+        // For games whose creator doesn't move first, and to send the game
+        // again
+        &["export", path, output] => {
+            let correspondence = load(path);
+            if let Err(err) = correspondence.export().save(output) {
+                fail(&format!("Could not write {}: {}", output, err));
+            }
+            println!("Send {} to the other players", output);
+            correspondence
+        },
+        &["import", path, reply] => {
+            let record = Record::load(reply).ok().and_then(|records| records.into_iter().next())
+                .unwrap_or_else(|| fail(&format!("Could not read {}", reply)));
+            // The first file of a game somebody else started
+            let correspondence = if Path::new(path).exists() {
+                let mut correspondence = load(path);
+                match correspondence.import(&record) {
+                    Ok(moves) => {
+                        let moves: Vec<String> = moves.iter().map(|mv| mv.to_string()).collect();
+                        println!("New moves: {}", moves.join(" "));
+                    },
+                    Err(err) => fail(&format!("Refusing {}: {}", reply, err))
+                }
+                correspondence
+            } else {
+                let me = me.unwrap_or_else(|| fail("Give the color you play with --as"));
+                Correspondence::join(&record, me)
+                    .unwrap_or_else(|err| fail(&format!("Refusing {}: {}", reply, err)))
+            };
+            save(&correspondence, path);
+            correspondence
+        },
+        _ => fail(&format!("Unknown correspondence command\n\n{}", USAGE))
+    };
+    show_correspondence(&correspondence);
+}
@@ -0,0 +1,290 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use model::Color;
+use game::Game;
+use record::Record;
+
+// Time controls are written in seconds, either as the starting time and
+// what every move adds to it, like "300+5" or just "300", or as a fixed
+// time for every move, like "10/move"
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum TimeControl {
+    Increment(Duration, Duration),
+    // Time left over from one move is not carried to the next
+    PerMove(Duration)
+}
+
+impl TimeControl {
+    fn initial(&self) -> Duration {
+        match self {
+            &TimeControl::Increment(base, _) => base,
+            &TimeControl::PerMove(time) => time
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &TimeControl::Increment(base, increment) if increment == Duration::from_secs(0) =>
+                write!(f, "{}", base.as_secs()),
+            &TimeControl::Increment(base, increment) =>
+                write!(f, "{}+{}", base.as_secs(), increment.as_secs()),
+            &TimeControl::PerMove(time) => write!(f, "{}/move", time.as_secs())
+        }
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TimeControl, String> {
+        let seconds = |text: &str| text.trim().parse().map(Duration::from_secs);
+        let control = if s.ends_with("/move") {
+            seconds(&s[..s.len() - 5]).map(TimeControl::PerMove)
+        } else {
+            let mut parts = s.splitn(2, '+');
+            let base = seconds(parts.next().unwrap());
+            let increment = parts.next().map_or(Ok(Duration::from_secs(0)), seconds);
+            base.and_then(|base| increment.map(|increment| TimeControl::Increment(base, increment)))
+        };
+        match control {
+            Ok(control) if control.initial() > Duration::from_secs(0) => Ok(control),
+            _ => Err(format!("invalid time control \"{}\"", s))
+        }
+    }
+}
+
+fn saturating_sub(a: Duration, b: Duration) -> Duration {
+    a.checked_sub(b).unwrap_or(Duration::from_secs(0))
+}
+
+// Counts down the time of whoever is to move. The clock is told when moves
+// are made and taken back, and can be paused in between, for example while
+// a game is being looked at rather than played.
+#[derive(Debug,Clone)]
+pub struct Clock {
+    control: TimeControl,
+    // Time left before the current move, by color
+    remaining: [Duration; 4],
+    // Who the time on the current move is counted for and how much they
+    // used before the last pause
+    turn: Option<Color>,
+    thinking: Duration,
+    // When the clock was last started, None while it's stopped
+    since: Option<Instant>,
+    // Time every move took, so that undo can give it back
+    spent: Vec<(Color, Duration)>
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control: control,
+            remaining: [control.initial(); 4],
+            turn: None,
+            thinking: Duration::from_secs(0),
+            since: None,
+            spent: vec![]
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn spent(&self) -> &[(Color, Duration)] {
+        &self.spent
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.since.is_some()
+    }
+
+    // Runs the color's time. Time already used on the move before a pause
+    // counts, unless it belonged to somebody else.
+    pub fn start(&mut self, color: Color, now: Instant) {
+        if self.turn != Some(color) {
+            self.pause(now);
+            self.turn = Some(color);
+            self.thinking = Duration::from_secs(0);
+        }
+        if self.since.is_none() {
+            self.since = Some(now);
+        }
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        if let Some(since) = self.since.take() {
+            self.thinking += now.duration_since(since);
+        }
+    }
+
+    pub fn remaining(&self, color: Color, now: Instant) -> Duration {
+        let remaining = self.remaining[color.index()];
+        if self.turn != Some(color) {
+            return remaining;
+        }
+        let running = self.since.map_or(Duration::from_secs(0), |since| now.duration_since(since));
+        saturating_sub(remaining, self.thinking + running)
+    }
+
+    // The color whose time ran out on the current move
+    pub fn expired(&self, now: Instant) -> Option<Color> {
+        self.turn.filter(|&color| self.remaining(color, now) == Duration::from_secs(0))
+    }
+
+    // Stops the clock for a move made by whoever's time was running and
+    // returns how long it took
+    pub fn moved(&mut self, now: Instant) -> Option<Duration> {
+        self.pause(now);
+        let color = self.turn.take()?;
+        let spent = self.thinking;
+        self.thinking = Duration::from_secs(0);
+        self.charge(color, spent);
+        Some(spent)
+    }
+
+    fn charge(&mut self, color: Color, spent: Duration) {
+        if let TimeControl::Increment(_, increment) = self.control {
+            let remaining = &mut self.remaining[color.index()];
+            *remaining = saturating_sub(*remaining, spent) + increment;
+        }
+        self.spent.push((color, spent));
+    }
+
+    // Stops the clock without counting the time, when the player to move
+    // is out of the game
+    pub fn stop(&mut self) {
+        self.since = None;
+        self.turn = None;
+        self.thinking = Duration::from_secs(0);
+    }
+
+    // Gives the last move's time back and leaves the clock stopped
+    pub fn undo(&mut self) {
+        self.stop();
+        if let Some((color, spent)) = self.spent.pop() {
+            if let TimeControl::Increment(_, increment) = self.control {
+                let remaining = &mut self.remaining[color.index()];
+                *remaining = saturating_sub(*remaining, increment) + spent;
+            }
+        }
+    }
+
+    // Saved games keep the time control and what every move took in
+    // milliseconds, like [TimeControl "300+5"] and [Times "1200 830"]
+    pub fn save(&self, record: &mut Record) {
+        let times: Vec<String> = self.spent.iter()
+            .map(|&(_, spent)| (spent.as_secs() * 1000 + spent.subsec_millis() as u64).to_string())
+            .collect();
+        record.set_tag("TimeControl", &self.control.to_string());
+        record.set_tag("Times", &times.join(" "));
+    }
+
+    // The clock of a saved game after its moves, if it was timed
+    pub fn load(record: &Record, game: &Game) -> Result<Option<Clock>, String> {
+        let control: TimeControl = match record.tag("TimeControl") {
+            Some(control) => control.parse()?,
+            None => return Ok(None)
+        };
+        let times = record.tag("Times").unwrap_or("").split_whitespace()
+            .map(|time| time.parse().map(Duration::from_millis))
+            .collect::<Result<Vec<Duration>, _>>()
+            .map_err(|_| "invalid move times".to_owned())?;
+        if times.len() != game.history().len() {
+            return Err("the move times don't match the moves".to_owned());
+        }
+        let mut clock = Clock::new(control);
+        for (placement, &spent) in game.history().iter().zip(times.iter()) {
+            clock.charge(placement.color, spent);
+        }
+        Ok(Some(clock))
+    }
+}
+
+// Minutes and seconds, or tenths of a second when time is short
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds < 10 {
+        format!("{}.{}", seconds, time.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+// Tests
+
+#[test]
+fn test_time_control_notation() {
+    let five = Duration::from_secs(5);
+    assert_eq!("300+5".parse(), Ok(TimeControl::Increment(Duration::from_secs(300), five)));
+    assert_eq!("60".parse(), Ok(TimeControl::Increment(Duration::from_secs(60), Duration::from_secs(0))));
+    assert_eq!("5/move".parse(), Ok(TimeControl::PerMove(five)));
+    for text in &["300+5", "60", "5/move"] {
+        assert_eq!(text.parse::<TimeControl>().unwrap().to_string(), *text);
+    }
+    assert!("0".parse::<TimeControl>().is_err());
+    assert!("5+".parse::<TimeControl>().is_err());
+    assert!("fast".parse::<TimeControl>().is_err());
+}
+
+#[test]
+fn test_clock() {
+    let start = Instant::now();
+    let at = |seconds| start + Duration::from_secs(seconds);
+    let mut clock = Clock::new("60+2".parse().unwrap());
+    clock.start(Color::Blue, at(0));
+    assert_eq!(clock.remaining(Color::Blue, at(10)), Duration::from_secs(50));
+    assert_eq!(clock.remaining(Color::Green, at(10)), Duration::from_secs(60));
+
+    // Paused time doesn't count
+    clock.pause(at(10));
+    clock.start(Color::Blue, at(100));
+    assert_eq!(clock.moved(at(105)), Some(Duration::from_secs(15)));
+    assert_eq!(clock.remaining(Color::Blue, at(200)), Duration::from_secs(47));
+
+    clock.start(Color::Green, at(105));
+    assert_eq!(clock.expired(at(164)), None);
+    assert_eq!(clock.expired(at(165)), Some(Color::Green));
+    assert_eq!(clock.remaining(Color::Green, at(300)), Duration::from_secs(0));
+
+    // Taking a move back gives its time back
+    clock.undo();
+    assert!(!clock.is_running());
+    assert_eq!(clock.remaining(Color::Blue, at(300)), Duration::from_secs(60));
+    assert_eq!(clock.remaining(Color::Green, at(300)), Duration::from_secs(60));
+
+    let mut clock = Clock::new("5/move".parse().unwrap());
+    clock.start(Color::Blue, at(0));
+    clock.moved(at(3));
+    assert_eq!(clock.remaining(Color::Blue, at(3)), Duration::from_secs(5));
+    assert_eq!(format_time(Duration::from_millis(4250)), "4.2");
+    assert_eq!(format_time(Duration::from_secs(125)), "2:05");
+}
+
+#[test]
+fn test_clock_record() {
+    let start = Instant::now();
+    let mut game = Game::two_player();
+    let mut clock = Clock::new("60+1".parse().unwrap());
+    for (i, mv) in ["b2M", "a1S", "c3L"].iter().enumerate() {
+        clock.start(game.current(), start);
+        clock.moved(start + Duration::from_millis(1500 * (i as u64 + 1)));
+        game.place(mv.parse().unwrap()).unwrap();
+    }
+    let mut record = Record::from_game(&game);
+    clock.save(&mut record);
+    assert_eq!(record.tag("Times"), Some("1500 3000 4500"));
+
+    let loaded = Clock::load(&record, &game).unwrap().unwrap();
+    assert_eq!(loaded.spent(), clock.spent());
+    for &color in game.players() {
+        assert_eq!(loaded.remaining(color, start), clock.remaining(color, start));
+    }
+    assert!(Clock::load(&Record::from_game(&game), &game).unwrap().is_none());
+    record.set_tag("Times", "1500");
+    assert!(Clock::load(&record, &game).is_err());
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+use std::error::Error;
+use std::io;
+use std::path::Path;
+
+use model::Color;
+use game::{Game, Move, MoveError};
+use record::{Record, RecordError};
+use random::Random;
+use sha1::{sha1, hex};
+
+// Slow games played by sending files back and forth. Every file is a record
+// of the game so far with two extra tags:
+//
+//   [Players "blue green"]
+//   [Result "*"]
+//   [Game "5f0e83b2c1d4a7e9"]
+//   [Chain "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12"]
+//
+//   b2M a1S c1S
+//
+// Game tells games apart and Chain is a hash chained over the moves, each
+// link hashing the one before it with the next move. A reply has to carry
+// on from the moves we already have, so files that were edited, belong to
+// another game or arrive out of order are refused before any of their
+// moves are played. Each player keeps their own copy of the game, which
+// also says which color they play:
+//
+//   [Me "green"]
+#[derive(Debug,Clone)]
+pub struct Correspondence {
+    id: String,
+    me: Color,
+    game: Game
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum CorrespondenceError {
+    NotCorrespondence,
+    Tampered,
+    OtherGame,
+    OutOfDate,
+    // The first ply where the file and our game differ
+    Diverged(usize),
+    NotYourTurn,
+    // The file makes a move for us at the ply
+    OwnMove(usize),
+    IllegalMove(RecordError)
+}
+
+impl CorrespondenceError {
+    fn message(&self) -> &str {
+        match self {
+            &CorrespondenceError::NotCorrespondence => "not a correspondence game",
+            &CorrespondenceError::Tampered => "the moves don't match the chain, the file was changed",
+            &CorrespondenceError::OtherGame => "the file belongs to another game",
+            &CorrespondenceError::OutOfDate => "the file has no moves we don't know about",
+            &CorrespondenceError::Diverged(_) => "the moves differ from ours",
+            &CorrespondenceError::NotYourTurn => "not your turn",
+            &CorrespondenceError::OwnMove(_) => "the file makes a move for us",
+            &CorrespondenceError::IllegalMove(_) => "illegal move"
+        }
+    }
+}
+
+impl fmt::Display for CorrespondenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &CorrespondenceError::Diverged(ply) | &CorrespondenceError::OwnMove(ply) =>
+                write!(f, "{} at ply {}", self.message(), ply),
+            &CorrespondenceError::IllegalMove(ref err) => write!(f, "{}", err),
+            _ => f.write_str(self.message())
+        }
+    }
+}
+
+impl Error for CorrespondenceError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+fn other(err: CorrespondenceError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+// The links of the chain after every move, starting with the empty game
+fn chain(id: &str, players: &[Color], moves: &[Move]) -> Vec<[u8; 20]> {
+    let names: Vec<&str> = players.iter().map(|color| color.name()).collect();
+    let mut link = sha1(format!("three {} {}", id, names.join(" ")).as_bytes());
+    let mut links = vec![link];
+    for mv in moves {
+        let mut data = link.to_vec();
+        data.extend_from_slice(mv.to_string().as_bytes());
+        link = sha1(&data);
+        links.push(link);
+    }
+    links
+}
+
+// The game id and the moves of a record whose chain is intact
+fn verify(record: &Record) -> Result<String, CorrespondenceError> {
+    let (id, recorded) = match (record.tag("Game"), record.tag("Chain")) {
+        (Some(id), Some(recorded)) => (id, recorded),
+        _ => return Err(CorrespondenceError::NotCorrespondence)
+    };
+    let links = chain(id, &record.players, &record.moves);
+    if hex(&links[links.len() - 1]) != recorded {
+        return Err(CorrespondenceError::Tampered);
+    }
+    Ok(id.to_owned())
+}
+
+impl Correspondence {
+    pub fn new(players: &[Color], me: Color) -> Correspondence {
+        let mut random = Random::from_time();
+        Correspondence {
+            id: format!("{:016x}", random.next_u64()),
+            me: me,
+            game: Game::new(players)
+        }
+    }
+
+    // Takes up a game somebody else started from the first file they sent
+    pub fn join(record: &Record, me: Color) -> Result<Correspondence, CorrespondenceError> {
+        let id = verify(record)?;
+        if !record.players.contains(&me) {
+            return Err(CorrespondenceError::OtherGame);
+        }
+        let mut correspondence = Correspondence {
+            id: id,
+            me: me,
+            game: Game::new(&record.players)
+        };
+        // Whoever started the game sends it before anybody moved when
+        // somebody else moves first
+        if !record.moves.is_empty() {
+            correspondence.import(record)?;
+        }
+        Ok(correspondence)
+    }
+
+    pub fn me(&self) -> Color {
+        self.me
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn play(&mut self, mv: Move) -> Result<(), CorrespondenceError> {
+        if self.game.current() != self.me || self.game.is_over() {
+            return Err(CorrespondenceError::NotYourTurn);
+        }
+        self.game.place(mv)
+            .map_err(|err| CorrespondenceError::IllegalMove(
+                RecordError::IllegalMove(self.game.history().len() + 1, mv, err)))
+    }
+
+    // The file to send to the others
+    pub fn export(&self) -> Record {
+        let mut record = Record::from_game(&self.game);
+        let links = chain(&self.id, &record.players, &record.moves);
+        record.set_tag("Game", &self.id);
+        record.set_tag("Chain", &hex(&links[links.len() - 1]));
+        record
+    }
+
+    // Checks everything about a reply before playing its new moves, which
+    // are returned
+    pub fn import(&mut self, record: &Record) -> Result<Vec<Move>, CorrespondenceError> {
+        if verify(record)? != self.id || record.players != self.game.players() {
+            return Err(CorrespondenceError::OtherGame);
+        }
+        let known: Vec<Move> = self.game.history().iter().map(|placement| placement.mv).collect();
+        let ours = chain(&self.id, &record.players, &known);
+        let theirs = chain(&self.id, &record.players, &record.moves);
+        if let Some(ply) = ours.iter().zip(theirs.iter()).position(|(a, b)| a != b) {
+            return Err(CorrespondenceError::Diverged(ply));
+        }
+        if record.moves.len() <= known.len() {
+            return Err(CorrespondenceError::OutOfDate);
+        }
+
+        let mut game = self.game.clone();
+        let new = record.moves[known.len()..].to_vec();
+        for (i, &mv) in new.iter().enumerate() {
+            let ply = known.len() + i + 1;
+            if game.current() == self.me {
+                return Err(CorrespondenceError::OwnMove(ply));
+            }
+            game.place(mv).map_err(|err: MoveError|
+                CorrespondenceError::IllegalMove(RecordError::IllegalMove(ply, mv, err)))?;
+        }
+        match record.result {
+            Some(result) if result != game.status() =>
+                return Err(CorrespondenceError::IllegalMove(
+                    RecordError::WrongResult(result, game.status()))),
+            _ => {}
+        }
+        self.game = game;
+        Ok(new)
+    }
+
+    // Our own copy is checked just like replies
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Correspondence> {
+        let record = match Record::load(path)?.into_iter().next() {
+            Some(record) => record,
+            None => return Err(other(CorrespondenceError::NotCorrespondence))
+        };
+        let me = match record.tag("Me").map(|me| me.parse()) {
+            Some(Ok(me)) => me,
+            _ => return Err(other(CorrespondenceError::NotCorrespondence))
+        };
+        let id = verify(&record).map_err(other)?;
+        let game = record.replay().map_err(|err| other(CorrespondenceError::IllegalMove(err)))?;
+        Ok(Correspondence { id: id, me: me, game: game })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut record = self.export();
+        record.set_tag("Me", self.me.name());
+        record.save(path)
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+fn mv(text: &str) -> Move {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_correspondence_exchange() {
+    let players = [Color::Blue, Color::Green];
+    let mut blue = Correspondence::new(&players, Color::Blue);
+    blue.play(mv("b2M")).unwrap();
+    assert_eq!(blue.play(mv("a1S")), Err(CorrespondenceError::NotYourTurn));
+
+    let mut green = Correspondence::join(&blue.export(), Color::Green).unwrap();
+    green.play(mv("a1S")).unwrap();
+    assert_eq!(blue.import(&green.export()), Ok(vec![mv("a1S")]));
+    assert_eq!(blue.game().board(), green.game().board());
+
+    // The same file twice, or an older one, changes nothing
+    assert_eq!(blue.import(&green.export()), Err(CorrespondenceError::OutOfDate));
+    blue.play(mv("c1S")).unwrap();
+    let old = green.export();
+    green.import(&blue.export()).unwrap();
+    assert_eq!(green.import(&old), Err(CorrespondenceError::OutOfDate));
+}
+
+#[test]
+fn test_correspondence_second_player_starts() {
+    let players = [Color::Blue, Color::Green];
+    let mut green = Correspondence::new(&players, Color::Green);
+    assert_eq!(green.play(mv("b2M")), Err(CorrespondenceError::NotYourTurn));
+
+    let mut blue = Correspondence::join(&green.export(), Color::Blue).unwrap();
+    blue.play(mv("b2M")).unwrap();
+    assert_eq!(green.import(&blue.export()), Ok(vec![mv("b2M")]));
+    green.play(mv("a1S")).unwrap();
+    assert_eq!(blue.import(&green.export()), Ok(vec![mv("a1S")]));
+}
+
+#[test]
+fn test_correspondence_rejects() {
+    let players = [Color::Blue, Color::Green];
+    let mut blue = Correspondence::new(&players, Color::Blue);
+    blue.play(mv("b2M")).unwrap();
+    let mut green = Correspondence::join(&blue.export(), Color::Green).unwrap();
+    green.play(mv("a1S")).unwrap();
+    let reply = green.export();
+
+    let mut edited = reply.clone();
+    edited.moves[1] = mv("a2S");
+    assert_eq!(blue.clone().import(&edited), Err(CorrespondenceError::Tampered));
+
+    let other = Correspondence::new(&players, Color::Green);
+    let mut foreign = reply.clone();
+    foreign.set_tag("Game", &other.export().tag("Game").unwrap());
+    assert!(blue.clone().import(&foreign).is_err());
+
+    // A history rewritten with a fresh chain still differs from ours
+    let mut rewritten = Correspondence::new(&players, Color::Green);
+    rewritten.id = blue.id.clone();
+    rewritten.game.place(mv("c3L")).unwrap();
+    rewritten.game.place(mv("a1S")).unwrap();
+    assert_eq!(blue.clone().import(&rewritten.export()), Err(CorrespondenceError::Diverged(1)));
+
+    // Green may not move for blue as well
+    green.game.place(mv("c1S")).unwrap();
+    assert_eq!(blue.clone().import(&green.export()), Err(CorrespondenceError::OwnMove(3)));
+    assert_eq!(blue.game().history().len(), 1);
+}
@@ -0,0 +1,308 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use model::Color;
+use protocol::ProtocolError;
+
+// Hosts announce their games to this UDP port on the local network
+pub const DISCOVERY_PORT: u16 = 7334;
+
+// The only rules there are so far
+pub const STANDARD_VARIANT: &'static str = "standard";
+
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Hosts that haven't been heard from in this long are forgotten
+const HOST_TIMEOUT: Duration = Duration::from_secs(4);
+
+// How often the browser checks whether it has been dropped
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Hosts send one datagram every second describing their game:
+//
+//   three-game 7333 standard blue,green,red green,red living room
+//
+// That is the TCP port to join on, the variant, the players, the seats
+// still open and the name of the host, which may contain spaces.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Announcement {
+    pub port: u16,
+    pub variant: String,
+    pub players: Vec<Color>,
+    pub open_seats: Vec<Color>,
+    pub name: String
+}
+
+fn invalid(line: &str) -> ProtocolError {
+    ProtocolError::Invalid(line.to_owned())
+}
+
+fn parse_colors(line: &str, colors: &str) -> Result<Vec<Color>, ProtocolError> {
+    if colors == "-" {
+        return Ok(vec![]);
+    }
+    colors.split(',').map(|color| color.parse().map_err(|_| invalid(line))).collect()
+}
+
+fn write_colors(f: &mut fmt::Formatter, colors: &[Color]) -> fmt::Result {
+    if colors.is_empty() {
+        return f.write_str("-");
+    }
+    let names: Vec<&str> = colors.iter().map(|color| color.name()).collect();
+    f.write_str(&names.join(","))
+}
+
+impl FromStr for Announcement {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Announcement, ProtocolError> {
+        let line = line.trim();
+        let words: Vec<&str> = line.splitn(6, ' ').collect();
+        match words.first() {
+            Some(&"three-game") => {},
+            Some(&"") | None => return Err(ProtocolError::Empty),
+            Some(_) => return Err(ProtocolError::Unknown(line.to_owned()))
+        }
+        if words.len() != 6 {
+            return Err(invalid(line));
+        }
+        Ok(Announcement {
+            port: words[1].parse().map_err(|_| invalid(line))?,
+            variant: words[2].to_owned(),
+            players: parse_colors(line, words[3])?,
+            open_seats: parse_colors(line, words[4])?,
+            name: words[5].trim().to_owned()
+        })
+    }
+}
+
+impl fmt::Display for Announcement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "three-game {} {} ", self.port, self.variant)?;
+        write_colors(f, &self.players)?;
+        f.write_str(" ")?;
+        write_colors(f, &self.open_seats)?;
+        write!(f, " {}", self.name)
+    }
+}
+
+// What others see this machine as
+pub fn host_name() -> String {
+    env::var("HOSTNAME").ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "three".to_owned())
+}
+
+// Names go on one line after the seats, so line breaks and runs of spaces
+// become single spaces and an empty name becomes the machine's
+pub fn clean_name(name: &str) -> String {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    if words.is_empty() {
+        return host_name();
+    }
+    words.join(" ")
+}
+
+// The broadcast address announcements go to by default
+pub fn broadcast_address() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)), DISCOVERY_PORT)
+}
+
+// Sends the announcement to the target every second on a thread of its own
+// until dropped. The announcement can be changed as seats fill up.
+pub struct Announcer {
+    announcement: Arc<Mutex<Announcement>>,
+    running: Arc<AtomicBool>
+}
+
+impl Announcer {
+    pub fn start(target: SocketAddr, announcement: Announcement) -> io::Result<Announcer> {
+        let socket = UdpSocket::bind((Ipv4Addr::new(0, 0, 0, 0), 0))?;
+        socket.set_broadcast(true)?;
+        let announcer = Announcer {
+            announcement: Arc::new(Mutex::new(announcement)),
+            running: Arc::new(AtomicBool::new(true))
+        };
+        let announcement = announcer.announcement.clone();
+        let running = announcer.running.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                let datagram = announcement.lock().unwrap().to_string();
+                // A network that is down now may be up on the next round
+                let _ = socket.send_to(datagram.as_bytes(), target);
+                thread::sleep(ANNOUNCE_INTERVAL);
+            }
+        });
+        Ok(announcer)
+    }
+
+    pub fn announcement(&self) -> Announcement {
+        self.announcement.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, announcement: Announcement) {
+        *self.announcement.lock().unwrap() = announcement;
+    }
+}
+
+impl Drop for Announcer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+// A game found on the network and where to join it
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Found {
+    pub address: SocketAddr,
+    pub announcement: Announcement
+}
+
+// Listens for announcements and keeps a list of the hosts heard recently.
+// The port is released when the browser is dropped so that it can be opened
+// again.
+pub struct Browser {
+    address: SocketAddr,
+    announcements: Receiver<Found>,
+    hosts: Vec<(Found, Instant)>,
+    running: Arc<AtomicBool>
+}
+
+impl Browser {
+    pub fn bind(address: SocketAddr) -> io::Result<Browser> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+        let address = socket.local_addr()?;
+        let (sender, announcements) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let receiving = running.clone();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 1024];
+            while receiving.load(Ordering::SeqCst) {
+                let (length, from) = match socket.recv_from(&mut buffer) {
+                    Ok(received) => received,
+                    Err(_) => continue
+                };
+                let text = String::from_utf8_lossy(&buffer[..length]);
+                // Anything else on the port is ignored
+                if let Ok(announcement) = text.parse::<Announcement>() {
+                    let found = Found {
+                        address: SocketAddr::new(from.ip(), announcement.port),
+                        announcement: announcement
+                    };
+                    if sender.send(found).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Browser {
+            address: address,
+            announcements: announcements,
+            hosts: vec![],
+            running: running
+        })
+    }
+
+    // Listens on the discovery port on every interface
+    pub fn open() -> io::Result<Browser> {
+        Browser::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), DISCOVERY_PORT))
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Games heard from recently, in the order they were first found
+    pub fn hosts(&mut self) -> Vec<Found> {
+        let now = Instant::now();
+        while let Ok(found) = self.announcements.try_recv() {
+            match self.hosts.iter().position(|&(ref host, _)| host.address == found.address) {
+                Some(i) => self.hosts[i] = (found, now),
+                None => self.hosts.push((found, now))
+            }
+        }
+        self.hosts.retain(|&(_, seen)| now.duration_since(seen) < HOST_TIMEOUT);
+        self.hosts.iter().map(|&(ref found, _)| found.clone()).collect()
+    }
+}
+
+impl Drop for Browser {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+fn announcement(open_seats: &[Color]) -> Announcement {
+    Announcement {
+        port: 7333,
+        variant: STANDARD_VARIANT.to_owned(),
+        players: vec![Color::Blue, Color::Green, Color::Red],
+        open_seats: open_seats.to_vec(),
+        name: "living room".to_owned()
+    }
+}
+
+#[test]
+fn test_announcement_round_trip() {
+    for open_seats in &[vec![Color::Green], vec![]] {
+        let announcement = announcement(open_seats);
+        assert_eq!(announcement.to_string().parse::<Announcement>(), Ok(announcement));
+    }
+    assert!("three-game 7333 standard blue".parse::<Announcement>().is_err());
+    assert!("three-game port standard blue - host".parse::<Announcement>().is_err());
+    assert!("join".parse::<Announcement>().is_err());
+}
+
+#[test]
+fn test_announcement_names() {
+    let names = [("", host_name()), (" \n", host_name()), ("living\nroom ", "living room".to_owned())];
+    for &(name, ref cleaned) in &names {
+        let announcement = Announcement { name: clean_name(name), ..announcement(&[Color::Green]) };
+        assert_eq!(&announcement.name, cleaned);
+        assert_eq!(announcement.to_string().parse::<Announcement>(), Ok(announcement));
+    }
+}
+
+#[test]
+fn test_discovery_on_loopback() {
+    let mut browser = Browser::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let announcer = Announcer::start(browser.address(), announcement(&[Color::Green])).unwrap();
+    let mut hosts = vec![];
+    for _ in 0..100 {
+        hosts = browser.hosts();
+        if !hosts.is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(hosts.len(), 1);
+    assert_eq!(hosts[0].address, "127.0.0.1:7333".parse().unwrap());
+    assert_eq!(hosts[0].announcement, announcement(&[Color::Green]));
+
+    announcer.update(announcement(&[]));
+    thread::sleep(ANNOUNCE_INTERVAL + Duration::from_millis(200));
+    let hosts = browser.hosts();
+    assert_eq!(hosts.len(), 1);
+    assert!(hosts[0].announcement.open_seats.is_empty());
+
+    // The port can be listened on again once the browser is gone
+    let address = browser.address();
+    drop(browser);
+    thread::sleep(RECEIVE_TIMEOUT * 2);
+    assert!(Browser::bind(address).is_ok());
+}
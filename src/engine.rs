@@ -0,0 +1,443 @@
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use model::{Color, Pattern, patterns};
+use game::{Game, Move, Status};
+use transposition::{TranspositionTable, Entry, Bound};
+use solver::{Solver, Strategy};
+use book::Book;
+use random::Random;
+use zobrist;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Level {
+    Easy,
+    Medium,
+    Hard,
+    Perfect
+}
+
+pub const LEVELS: [Level; 4] = [Level::Easy, Level::Medium, Level::Hard, Level::Perfect];
+
+impl Level {
+    fn depth(&self) -> u8 {
+        match self {
+            &Level::Easy => 1,
+            &Level::Medium => 2,
+            &Level::Hard => 4,
+            &Level::Perfect => 5
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Level::Easy => "easy",
+            &Level::Medium => "medium",
+            &Level::Hard => "hard",
+            &Level::Perfect => "perfect"
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Level, String> {
+        LEVELS.iter()
+            .find(|level| level.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown level \"{}\"", s))
+    }
+}
+
+// Scores at least this large mean somebody has won
+const WIN: i32 = 100000;
+const MAX_PLIES: i32 = 100;
+
+// Win scores are stored relative to the position they were found in so that
+// they stay valid when the same position is reached at a different depth
+fn to_table(score: i32, ply: i32) -> i32 {
+    if score > WIN - MAX_PLIES {
+        score + ply
+    } else if score < -(WIN - MAX_PLIES) {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: i32) -> i32 {
+    if score > WIN - MAX_PLIES {
+        score - ply
+    } else if score < -(WIN - MAX_PLIES) {
+        score + ply
+    } else {
+        score
+    }
+}
+
+// Counts how close each side is to completing the winning patterns that are
+// still open to them. Everyone else is treated as a single opponent.
+fn evaluate(game: &Game, patterns: &[Pattern], root: Color) -> i32 {
+    let board = game.board();
+    let mut score = 0;
+    for pattern in patterns {
+        let mut owner = None;
+        let mut owned = 0;
+        let mut blocked = false;
+        for &(location, ring) in pattern.rings.iter() {
+            match (board.get_ring(location, ring), owner) {
+                (None, _) => {},
+                (Some(color), None) => {
+                    owner = Some(color);
+                    owned = 1;
+                },
+                (Some(color), Some(other)) if color == other => owned += 1,
+                (Some(_), Some(_)) => blocked = true
+            }
+        }
+        if blocked {
+            continue;
+        }
+        if let Some(color) = owner {
+            let playable = pattern.rings.iter()
+                .filter(|&&(location, ring)| board.get_ring(location, ring).is_none())
+                .all(|&(_, ring)| game.supply(color).remaining(ring) > 0);
+            if !playable {
+                continue;
+            }
+            let value = if owned == 2 { 10 } else { 1 };
+            score += if color == root { value } else { -value };
+        }
+    }
+    score
+}
+
+// What bots know before they search, loaded once and shared between them
+#[derive(Debug,Clone,Default)]
+pub struct Knowledge {
+    pub book: Option<Rc<Book>>,
+    pub strategy: Option<Rc<Strategy>>
+}
+
+impl Knowledge {
+    // Whatever has been built into the data directory
+    pub fn load() -> Knowledge {
+        Knowledge {
+            book: Book::default_path()
+                .and_then(|path| Book::load(path).ok())
+                .map(Rc::new),
+            strategy: Strategy::default_path()
+                .and_then(|path| Strategy::load(path).ok())
+                .map(Rc::new)
+        }
+    }
+}
+
+pub struct Bot {
+    level: Level,
+    random: Random,
+    table: TranspositionTable,
+    patterns: Vec<Pattern>,
+    book: Option<Rc<Book>>,
+    strategy: Option<Rc<Strategy>>,
+    solver: Option<Solver>,
+    deadline: Option<Instant>,
+    nodes: u64,
+    aborted: bool
+}
+
+impl Bot {
+    pub fn new(level: Level, random: Random) -> Bot {
+        Bot {
+            level: level,
+            random: random,
+            table: TranspositionTable::new(1 << 16),
+            patterns: patterns(),
+            book: None,
+            strategy: None,
+            solver: None,
+            deadline: None,
+            nodes: 0,
+            aborted: false
+        }
+    }
+
+    // Bots play known opening moves without searching. Perfect bots look
+    // moves up from the solver's strategy table instead, before they solve
+    // positions themselves.
+    pub fn with_knowledge(level: Level, random: Random, knowledge: &Knowledge) -> Bot {
+        let mut bot = Bot::new(level, random);
+        bot.book = knowledge.book.clone();
+        bot.strategy = knowledge.strategy.clone();
+        bot
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn choose(&mut self, game: &Game) -> Option<Move> {
+        if game.is_over() {
+            return None;
+        }
+        if let Some(mv) = self.without_search(game) {
+            return Some(mv);
+        }
+        self.deadline = None;
+        let depth = self.level.depth();
+        self.search_root(game, depth)
+    }
+
+    // Searches deeper and deeper until the time is up instead of stopping
+    // at the depth of the bot's level
+    pub fn choose_within(&mut self, game: &Game, limit: Duration) -> Option<Move> {
+        if game.is_over() {
+            return None;
+        }
+        if let Some(mv) = self.without_search(game) {
+            return Some(mv);
+        }
+        let start = Instant::now();
+        self.deadline = Some(start + limit);
+        let mut best = None;
+        let max_depth = (27 - game.history().len()) as u8;
+        for depth in 1..max_depth + 1 {
+            match self.search_root(game, depth) {
+                Some(mv) => best = Some(mv),
+                None => break
+            }
+            // The next iteration would most likely not finish in time
+            if start.elapsed() * 4 > limit {
+                break;
+            }
+        }
+        self.deadline = None;
+        best.or_else(|| game.legal_moves().first().cloned())
+    }
+
+    fn without_search(&mut self, game: &Game) -> Option<Move> {
+        if self.level == Level::Perfect && game.players().len() == 2 {
+            return self.perfect_move(game);
+        }
+        if let Some(mv) = self.book.as_ref().and_then(|book| book.best(game)) {
+            return Some(mv);
+        }
+        if self.level == Level::Easy && self.random.below(4) == 0 {
+            let moves = game.legal_moves();
+            return Some(moves[self.random.below(moves.len())]);
+        }
+        None
+    }
+
+    // Returns None if the search ran out of time
+    fn search_root(&mut self, game: &Game, depth: u8) -> Option<Move> {
+        let mut moves = game.legal_moves();
+        self.random.shuffle(&mut moves);
+        let root = game.current();
+        let mut game = game.clone();
+        let mut best = None;
+        let mut alpha = -WIN - 1;
+        self.aborted = false;
+        for mv in moves {
+            game.place(mv).unwrap();
+            let score = self.search(&mut game, depth - 1, 1, alpha, WIN + 1, root);
+            game.undo();
+            if self.aborted {
+                return None;
+            }
+            if score > alpha || best.is_none() {
+                alpha = score;
+                best = Some(mv);
+            }
+        }
+        best
+    }
+
+    fn perfect_move(&mut self, game: &Game) -> Option<Move> {
+        let known = self.strategy.as_ref()
+            .and_then(|strategy| strategy.lookup(game))
+            .filter(|mv| game.check(*mv).is_ok());
+        if known.is_some() {
+            return known;
+        }
+        let solver = self.solver.get_or_insert_with(|| Solver::with_capacity(1 << 18));
+        solver.best_move(game).map(|(mv, _)| mv)
+    }
+
+    // Paranoid minimax: the bot maximizes the score for its own color and
+    // assumes every other player is out to minimize it
+    fn search(&mut self,
+              game: &mut Game,
+              depth: u8,
+              ply: i32,
+              mut alpha: i32,
+              mut beta: i32,
+              root: Color) -> i32 {
+        self.nodes += 1;
+        if self.nodes % 1024 == 0 {
+            if let Some(deadline) = self.deadline {
+                self.aborted = Instant::now() >= deadline;
+            }
+        }
+        if self.aborted {
+            return 0;
+        }
+
+        let current = match game.status() {
+            Status::Won(color) if color == root => return WIN - ply,
+            Status::Won(_) => return -(WIN - ply),
+            Status::Draw => return 0,
+            Status::Playing(color) => color
+        };
+        if depth == 0 {
+            return evaluate(game, &self.patterns, root);
+        }
+
+        let key = game.hash() ^ zobrist::turn_key(root).rotate_left(17);
+        let mut moves = game.legal_moves();
+        if let Some(entry) = self.table.probe(key) {
+            if entry.depth >= depth {
+                let score = from_table(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+            if let Some(best) = entry.best {
+                if let Some(i) = moves.iter().position(|mv| *mv == best) {
+                    moves.swap(0, i);
+                }
+            }
+        }
+
+        let (original_alpha, original_beta) = (alpha, beta);
+        let maximizing = current == root;
+        let mut best_score = if maximizing { -WIN - 1 } else { WIN + 1 };
+        let mut best_move = None;
+        for mv in moves {
+            game.place(mv).unwrap();
+            let score = self.search(game, depth - 1, ply + 1, alpha, beta, root);
+            game.undo();
+            if maximizing && score > best_score || !maximizing && score < best_score {
+                best_score = score;
+                best_move = Some(mv);
+            }
+            if maximizing && score > alpha {
+                alpha = score;
+            } else if !maximizing && score < beta {
+                beta = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        if self.aborted {
+            return 0;
+        }
+
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= original_beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(Entry {
+            key: key,
+            depth: depth,
+            score: to_table(best_score, ply),
+            bound: bound,
+            best: best_move
+        });
+        best_score
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+fn game_after(players: &[Color], moves: &[&str]) -> Game {
+    let mut game = Game::new(players);
+    for mv in moves {
+        game.place(mv.parse().unwrap()).unwrap();
+    }
+    game
+}
+
+#[test]
+fn test_level_names() {
+    for level in LEVELS.iter() {
+        assert_eq!(level.name().parse::<Level>(), Ok(*level));
+    }
+    assert!("grandmaster".parse::<Level>().is_err());
+}
+
+#[test]
+fn test_bot_takes_win() {
+    let game = game_after(&[Color::Blue, Color::Green], &["a1S", "a2S", "b1S", "b2S"]);
+    for level in [Level::Medium, Level::Hard, Level::Perfect].iter() {
+        let mut bot = Bot::new(*level, Random::new(1));
+        assert_eq!(bot.choose(&game), Some("c1S".parse().unwrap()));
+    }
+}
+
+#[test]
+fn test_bot_blocks() {
+    // Green has to stop Blue's top row even with four players around
+    let players = [Color::Blue, Color::Green, Color::Red, Color::Yellow];
+    let game = game_after(&players, &["a1S", "c3L", "a3M", "b3L", "b1S"]);
+    let mut bot = Bot::new(Level::Hard, Random::new(1));
+    let mv = bot.choose(&game).unwrap();
+    assert_eq!(mv, "c1S".parse().unwrap());
+}
+
+#[test]
+fn test_bot_plays_book() {
+    let players = [Color::Blue, Color::Green];
+    let mut book = Book::new();
+    let moves: Vec<Move> = ["a1L", "a2S", "b2L", "b1S", "c3L"].iter()
+        .map(|mv| mv.parse().unwrap())
+        .collect();
+    for _ in 0..::book::MIN_GAMES {
+        book.add_game(&players, &moves, 1);
+    }
+    let knowledge = Knowledge { book: Some(Rc::new(book)), strategy: None };
+    let game = Game::two_player();
+    let corners = ["a1L", "c1L", "a3L", "c3L"];
+    for seed in 0..4 {
+        let mut bot = Bot::with_knowledge(Level::Hard, Random::new(seed), &knowledge);
+        let mv = bot.choose(&game).unwrap();
+        assert!(corners.iter().any(|corner| mv == corner.parse().unwrap()), "{}", mv);
+    }
+}
+
+#[test]
+fn test_bot_plays_strategy() {
+    // Not what the solver would play, so the move has to come from the table
+    let game = Game::two_player();
+    let mut strategy = Strategy::new();
+    strategy.insert(&game, "b1S".parse().unwrap());
+    let knowledge = Knowledge { book: None, strategy: Some(Rc::new(strategy)) };
+    let mut bot = Bot::with_knowledge(Level::Perfect, Random::new(1), &knowledge);
+    assert_eq!(bot.choose(&game), Some("b1S".parse().unwrap()));
+}
+
+#[test]
+fn test_bot_time_limit() {
+    let game = Game::two_player();
+    let mut bot = Bot::new(Level::Hard, Random::new(1));
+    let start = Instant::now();
+    assert!(bot.choose_within(&game, Duration::from_millis(200)).is_some());
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,137 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command as Process, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use game::{Game, Move, Status};
+use engine::Level;
+use protocol::{Command, Response};
+
+// How long an engine gets to answer the handshake
+const STARTUP_TIME: Duration = Duration::from_secs(5);
+
+const BUILT_IN_ENGINE: &'static str = "three-engine";
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Poll {
+    Waiting,
+    Move(Option<Move>),
+    Disconnected
+}
+
+// An engine running in a separate process, spoken to over its stdin and
+// stdout. Responses are read on a thread of their own so that the frontend
+// never blocks while the engine is thinking.
+pub struct ExternalEngine {
+    name: String,
+    child: Child,
+    input: ChildStdin,
+    responses: Receiver<Response>
+}
+
+impl ExternalEngine {
+    pub fn start(program: &str) -> io::Result<ExternalEngine> {
+        let mut child = Process::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let (sender, responses) = mpsc::channel();
+        thread::spawn(move || {
+            for line in output.lines() {
+                let response = match line {
+                    Ok(line) => line.parse(),
+                    Err(_) => break
+                };
+                // Lines the protocol doesn't know about are skipped
+                if let Ok(response) = response {
+                    if sender.send(response).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        let mut engine = ExternalEngine {
+            name: program.to_owned(),
+            child: child,
+            input: input,
+            responses: responses
+        };
+        engine.handshake()?;
+        Ok(engine)
+    }
+
+    fn handshake(&mut self) -> io::Result<()> {
+        self.send(&Command::Three)?;
+        loop {
+            match self.responses.recv_timeout(STARTUP_TIME) {
+                Ok(Response::Id(ref key, ref value)) if key == "name" => self.name = value.clone(),
+                Ok(Response::ThreeOk) => return Ok(()),
+                Ok(_) => {},
+                Err(RecvTimeoutError::Timeout) =>
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "engine did not answer")),
+                Err(RecvTimeoutError::Disconnected) =>
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited"))
+            }
+        }
+    }
+
+    // The built-in bot at the given level. It's looked for next to the
+    // running program before the PATH, so that it's found without being
+    // installed.
+    pub fn built_in(level: Level) -> io::Result<ExternalEngine> {
+        let program = env::current_exe().ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(BUILT_IN_ENGINE)))
+            .filter(|path| path.exists())
+            .map_or(BUILT_IN_ENGINE.to_owned(), |path| path.to_string_lossy().into_owned());
+        let mut engine = ExternalEngine::start(&program)?;
+        engine.send(&Command::SetOption("level".to_owned(), level.name().to_owned()))?;
+        Ok(engine)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn send(&mut self, command: &Command) -> io::Result<()> {
+        writeln!(self.input, "{}", command)?;
+        self.input.flush()
+    }
+
+    // Sends the position and asks for a move, the answer arrives through
+    // poll_move
+    pub fn request_move(&mut self, game: &Game, limit: Duration) -> io::Result<()> {
+        // Drop anything left over from an earlier request
+        while let Ok(_) = self.responses.try_recv() {}
+        let moves = game.history().iter().map(|placement| placement.mv).collect();
+        let forfeits = game.forfeits().to_vec();
+        self.send(&Command::Position(game.players().to_vec(), moves, forfeits))?;
+        self.send(&Command::Go(Some(limit)))
+    }
+
+    pub fn poll_move(&mut self) -> Poll {
+        loop {
+            match self.responses.try_recv() {
+                Ok(Response::BestMove(mv)) => return Poll::Move(mv),
+                Ok(_) => {},
+                Err(TryRecvError::Empty) => return Poll::Waiting,
+                Err(TryRecvError::Disconnected) => return Poll::Disconnected
+            }
+        }
+    }
+
+    pub fn report_result(&mut self, status: Status) -> io::Result<()> {
+        self.send(&Command::Result(status))
+    }
+}
+
+impl Drop for ExternalEngine {
+    fn drop(&mut self) {
+        let _ = self.send(&Command::Quit);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
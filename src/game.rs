@@ -0,0 +1,551 @@
+use std::fmt;
+use std::error::Error;
+use std::str::FromStr;
+
+use model::{Board, Color, Ring, Location, RINGS, index_to_location};
+use zobrist;
+
+// Each player starts with this many rings of every size
+pub const RINGS_PER_SIZE: u8 = 3;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Move {
+    pub location: Location,
+    pub ring: Ring
+}
+
+impl Move {
+    pub fn new(location: Location, ring: Ring) -> Move {
+        Move {
+            location: location,
+            ring: ring
+        }
+    }
+}
+
+// Moves are written as column, row and ring size, like "b2L" for the large
+// ring in the middle of the board. Sizes are S(mall), M(edium) and L(arge).
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (x, y) = self.location;
+        let size = match self.ring {
+            Ring::Top => 'S',
+            Ring::Middle => 'M',
+            Ring::Bottom => 'L'
+        };
+        write!(f, "{}{}{}", (b'a' + x as u8) as char, y + 1, size)
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ParseMoveError(pub String);
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid move \"{}\"", self.0)
+    }
+}
+
+impl Error for ParseMoveError {
+    fn description(&self) -> &str {
+        "invalid move"
+    }
+}
+
+impl FromStr for Move {
+    type Err = ParseMoveError;
+
+    fn from_str(s: &str) -> Result<Move, ParseMoveError> {
+        let chars: Vec<char> = s.trim().chars().collect();
+        if chars.len() != 3 {
+            return Err(ParseMoveError(s.to_owned()));
+        }
+        let x = match "abc".find(chars[0].to_ascii_lowercase()) {
+            Some(x) => x,
+            None => return Err(ParseMoveError(s.to_owned()))
+        };
+        let y = match "123".find(chars[1]) {
+            Some(y) => y,
+            None => return Err(ParseMoveError(s.to_owned()))
+        };
+        let ring = match chars[2].to_ascii_uppercase() {
+            'S' => Ring::Top,
+            'M' => Ring::Middle,
+            'L' => Ring::Bottom,
+            _ => return Err(ParseMoveError(s.to_owned()))
+        };
+        Ok(Move::new((x, y), ring))
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Supply([u8; 3]);
+
+impl Default for Supply {
+    fn default() -> Supply {
+        Supply::full()
+    }
+}
+
+impl Supply {
+    pub fn full() -> Supply {
+        Supply([RINGS_PER_SIZE; 3])
+    }
+
+    pub fn remaining(&self, ring: Ring) -> u8 {
+        self.0[ring.index()]
+    }
+
+    pub fn total(&self) -> u8 {
+        self.0.iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    fn take(&mut self, ring: Ring) {
+        self.0[ring.index()] -= 1;
+    }
+
+    fn put_back(&mut self, ring: Ring) {
+        self.0[ring.index()] += 1;
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Status {
+    Playing(Color),
+    Won(Color),
+    Draw
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum MoveError {
+    GameOver,
+    Occupied,
+    NoRingsLeft
+}
+
+impl MoveError {
+    fn message(&self) -> &'static str {
+        match self {
+            &MoveError::GameOver => "the game is already over",
+            &MoveError::Occupied => "the ring is already taken",
+            &MoveError::NoRingsLeft => "no rings of that size left"
+        }
+    }
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl Error for MoveError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Placement {
+    pub color: Color,
+    pub mv: Move,
+    // Index into players of whoever was to move, so undo can restore it
+    // even if some players were skipped afterwards
+    turn: usize
+}
+
+#[derive(Debug,Clone)]
+pub struct Game {
+    board: Board,
+    players: Vec<Color>,
+    supplies: [Supply; 4],
+    turn: usize,
+    status: Status,
+    history: Vec<Placement>,
+    // Colors that dropped out and how many moves had been played when they
+    // did
+    forfeits: Vec<(Color, usize)>,
+    // Those of them who chose to
+    resigned: Vec<Color>,
+    agreed_draw: bool,
+    hash: u64
+}
+
+impl Game {
+    pub fn new(players: &[Color]) -> Game {
+        assert!(!players.is_empty(), "a game needs at least one player");
+        let board = Board::empty();
+        let supplies = [Supply::full(); 4];
+        let hash = zobrist::hash(&board, players[0], &supplies);
+        Game {
+            board: board,
+            players: players.to_vec(),
+            supplies: supplies,
+            turn: 0,
+            status: Status::Playing(players[0]),
+            history: vec![],
+            forfeits: vec![],
+            resigned: vec![],
+            agreed_draw: false,
+            hash: hash
+        }
+    }
+
+    pub fn two_player() -> Game {
+        Game::new(&[Color::Blue, Color::Green])
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn players(&self) -> &[Color] {
+        &self.players
+    }
+
+    pub fn current(&self) -> Color {
+        self.players[self.turn]
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn supply(&self, color: Color) -> Supply {
+        self.supplies[color.index()]
+    }
+
+    pub fn supplies(&self) -> &[Supply; 4] {
+        &self.supplies
+    }
+
+    pub fn history(&self) -> &[Placement] {
+        &self.history
+    }
+
+    pub fn forfeits(&self) -> &[(Color, usize)] {
+        &self.forfeits
+    }
+
+    pub fn resigned(&self) -> &[Color] {
+        &self.resigned
+    }
+
+    // Whether the game ended in a draw the players agreed to rather than
+    // one on the board
+    pub fn is_agreed_draw(&self) -> bool {
+        self.agreed_draw
+    }
+
+    // Whether the color still takes turns
+    pub fn is_active(&self, color: Color) -> bool {
+        self.players.contains(&color) && !self.forfeits.iter().any(|&(c, _)| c == color)
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn is_over(&self) -> bool {
+        match self.status {
+            Status::Playing(_) => false,
+            _ => true
+        }
+    }
+
+    pub fn check(&self, mv: Move) -> Result<(), MoveError> {
+        if self.is_over() {
+            Err(MoveError::GameOver)
+        } else if self.board.get_ring(mv.location, mv.ring).is_some() {
+            Err(MoveError::Occupied)
+        } else if self.supply(self.current()).remaining(mv.ring) == 0 {
+            Err(MoveError::NoRingsLeft)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.is_over() {
+            return vec![];
+        }
+        self.moves_for(self.current())
+    }
+
+    fn moves_for(&self, color: Color) -> Vec<Move> {
+        let supply = self.supply(color);
+        let mut moves = vec![];
+        for i in 0..9 {
+            let location = index_to_location(3, i);
+            for ring in RINGS.iter() {
+                if supply.remaining(*ring) > 0 &&
+                    self.board.get_ring(location, *ring).is_none() {
+                    moves.push(Move::new(location, *ring));
+                }
+            }
+        }
+        moves
+    }
+
+    fn can_move(&self, color: Color) -> bool {
+        !self.moves_for(color).is_empty()
+    }
+
+    pub fn place(&mut self, mv: Move) -> Result<(), MoveError> {
+        self.check(mv)?;
+        let color = self.current();
+        let remaining = self.supply(color).remaining(mv.ring);
+
+        self.board.set_ring(mv.location, mv.ring, color);
+        self.supplies[color.index()].take(mv.ring);
+        self.hash ^= zobrist::ring_key(color, mv.location, mv.ring)
+            ^ zobrist::supply_key(color, mv.ring, remaining)
+            ^ zobrist::supply_key(color, mv.ring, remaining - 1);
+
+        self.history.push(Placement {
+            color: color,
+            mv: mv,
+            turn: self.turn
+        });
+
+        if let Some(winner) = self.board.winner() {
+            self.status = Status::Won(winner);
+            self.set_turn((self.turn + 1) % self.players.len());
+            return Ok(());
+        }
+
+        self.next_turn();
+        Ok(())
+    }
+
+    // Players who have nothing left to place or who forfeited are skipped.
+    // If that's everyone the game ends in a draw.
+    fn next_turn(&mut self) {
+        let count = self.players.len();
+        let next = (1..count + 1)
+            .map(|n| (self.turn + n) % count)
+            .find(|&i| self.is_active(self.players[i]) && self.can_move(self.players[i]));
+        match next {
+            Some(i) => {
+                self.set_turn(i);
+                self.status = Status::Playing(self.current());
+            },
+            None => {
+                self.set_turn((self.turn + 1) % count);
+                self.status = Status::Draw;
+            }
+        }
+    }
+
+    // Takes a player out of the game and the others play on without them.
+    // When only one player is left they win.
+    pub fn forfeit(&mut self, color: Color) -> Result<(), MoveError> {
+        if self.is_over() {
+            return Err(MoveError::GameOver);
+        }
+        if !self.is_active(color) {
+            return Ok(());
+        }
+        self.forfeits.push((color, self.history.len()));
+        let active: Vec<Color> = self.players.iter()
+            .cloned()
+            .filter(|&c| self.is_active(c))
+            .collect();
+        if active.len() == 1 {
+            self.status = Status::Won(active[0]);
+        } else if self.current() == color {
+            self.next_turn();
+        }
+        Ok(())
+    }
+
+    // Forfeiting because the player gave up
+    pub fn resign(&mut self, color: Color) -> Result<(), MoveError> {
+        let active = self.is_active(color);
+        self.forfeit(color)?;
+        if active {
+            self.resigned.push(color);
+        }
+        Ok(())
+    }
+
+    pub fn agree_draw(&mut self) -> Result<(), MoveError> {
+        if self.is_over() {
+            return Err(MoveError::GameOver);
+        }
+        self.status = Status::Draw;
+        self.agreed_draw = true;
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Option<Placement> {
+        let placement = match self.history.pop() {
+            Some(placement) => placement,
+            None => return None
+        };
+        let Placement { color, mv, turn } = placement;
+        let remaining = self.supply(color).remaining(mv.ring);
+
+        self.board.clear_ring(mv.location, mv.ring);
+        self.supplies[color.index()].put_back(mv.ring);
+        self.hash ^= zobrist::ring_key(color, mv.location, mv.ring)
+            ^ zobrist::supply_key(color, mv.ring, remaining)
+            ^ zobrist::supply_key(color, mv.ring, remaining + 1);
+
+        // Forfeits and draws agreed to after the move are taken back with it
+        let plies = self.history.len();
+        self.forfeits.retain(|&(_, ply)| ply <= plies);
+        let forfeits = &self.forfeits;
+        self.resigned.retain(|color| forfeits.iter().any(|&(c, _)| c == *color));
+        self.agreed_draw = false;
+
+        self.set_turn(turn);
+        self.status = Status::Playing(color);
+        Some(placement)
+    }
+
+    fn set_turn(&mut self, turn: usize) {
+        self.hash ^= zobrist::turn_key(self.current()) ^ zobrist::turn_key(self.players[turn]);
+        self.turn = turn;
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+fn play(game: &mut Game, moves: &[(Location, Ring)]) {
+    for &(location, ring) in moves {
+        game.place(Move::new(location, ring)).unwrap();
+    }
+}
+
+#[test]
+fn test_game_turns() {
+    let mut game = Game::two_player();
+    assert_eq!(game.status(), Status::Playing(Color::Blue));
+    play(&mut game, &[((0, 0), Ring::Top)]);
+    assert_eq!(game.status(), Status::Playing(Color::Green));
+    assert_eq!(game.board().get_ring((0, 0), Ring::Top), Some(Color::Blue));
+    assert_eq!(game.supply(Color::Blue).remaining(Ring::Top), 2);
+    assert_eq!(game.place(Move::new((0, 0), Ring::Top)), Err(MoveError::Occupied));
+}
+
+#[test]
+fn test_game_supply() {
+    let mut game = Game::two_player();
+    play(&mut game, &[((0, 0), Ring::Top), ((0, 1), Ring::Top),
+                      ((1, 1), Ring::Top), ((1, 0), Ring::Top),
+                      ((2, 1), Ring::Top), ((2, 2), Ring::Top)]);
+    assert_eq!(game.current(), Color::Blue);
+    assert_eq!(game.supply(Color::Blue).remaining(Ring::Top), 0);
+    assert_eq!(game.place(Move::new((0, 2), Ring::Top)), Err(MoveError::NoRingsLeft));
+    assert!(game.legal_moves().iter().all(|mv| mv.ring != Ring::Top));
+}
+
+#[test]
+fn test_game_winner() {
+    let mut game = Game::two_player();
+    play(&mut game, &[((0, 0), Ring::Top), ((0, 1), Ring::Top),
+                      ((1, 0), Ring::Top), ((1, 1), Ring::Top),
+                      ((2, 0), Ring::Top)]);
+    assert_eq!(game.status(), Status::Won(Color::Blue));
+    assert!(game.legal_moves().is_empty());
+    assert_eq!(game.place(Move::new((2, 2), Ring::Top)), Err(MoveError::GameOver));
+}
+
+#[test]
+fn test_game_undo_restores_hash() {
+    let mut game = Game::two_player();
+    let mut hashes = vec![game.hash()];
+    play(&mut game, &[((0, 0), Ring::Top), ((0, 1), Ring::Top),
+                      ((1, 0), Ring::Middle), ((1, 1), Ring::Top),
+                      ((2, 0), Ring::Bottom)]);
+    for _ in 0..5 {
+        let expected = zobrist::hash(game.board(), game.current(), game.supplies());
+        assert_eq!(game.hash(), expected);
+        hashes.push(game.hash());
+        game.undo();
+    }
+    assert_eq!(game.hash(), hashes[0]);
+    assert_eq!(game.board(), &Board::empty());
+    assert_eq!(game.status(), Status::Playing(Color::Blue));
+    assert!(game.undo().is_none());
+}
+
+#[test]
+fn test_game_hash_transposition() {
+    let mut a = Game::two_player();
+    play(&mut a, &[((0, 0), Ring::Top), ((1, 1), Ring::Middle),
+                   ((2, 2), Ring::Bottom)]);
+    let mut b = Game::two_player();
+    play(&mut b, &[((2, 2), Ring::Bottom), ((1, 1), Ring::Middle),
+                   ((0, 0), Ring::Top)]);
+    assert_eq!(a.hash(), b.hash());
+    let mut c = Game::new(&::model::COLORS);
+    play(&mut c, &[((0, 0), Ring::Top), ((1, 1), Ring::Middle),
+                   ((2, 2), Ring::Bottom)]);
+    assert!(a.hash() != c.hash());
+}
+
+#[test]
+fn test_game_forfeit() {
+    let mut game = Game::new(&[Color::Blue, Color::Green, Color::Red]);
+    play(&mut game, &[((0, 0), Ring::Top)]);
+    game.forfeit(Color::Green).unwrap();
+    assert_eq!(game.status(), Status::Playing(Color::Red));
+    assert_eq!(game.forfeits(), &[(Color::Green, 1)]);
+    play(&mut game, &[((1, 1), Ring::Top)]);
+    assert_eq!(game.status(), Status::Playing(Color::Blue));
+
+    // Undoing past the forfeit brings the player back
+    game.undo();
+    game.undo();
+    assert!(game.is_active(Color::Green));
+    assert_eq!(game.status(), Status::Playing(Color::Blue));
+
+    play(&mut game, &[((0, 0), Ring::Top)]);
+    game.forfeit(Color::Red).unwrap();
+    assert_eq!(game.status(), Status::Playing(Color::Green));
+    game.forfeit(Color::Blue).unwrap();
+    assert_eq!(game.status(), Status::Won(Color::Green));
+    assert_eq!(game.forfeit(Color::Green), Err(MoveError::GameOver));
+}
+
+#[test]
+fn test_game_resign_and_draw() {
+    let mut game = Game::new(&[Color::Blue, Color::Green, Color::Red]);
+    play(&mut game, &[((0, 0), Ring::Top)]);
+    game.resign(Color::Red).unwrap();
+    game.resign(Color::Red).unwrap();
+    assert_eq!(game.resigned(), &[Color::Red]);
+    assert_eq!(game.forfeits(), &[(Color::Red, 1)]);
+    assert_eq!(game.status(), Status::Playing(Color::Green));
+
+    game.agree_draw().unwrap();
+    assert_eq!(game.status(), Status::Draw);
+    assert!(game.is_agreed_draw());
+    assert_eq!(game.agree_draw(), Err(MoveError::GameOver));
+
+    game.undo();
+    assert!(!game.is_agreed_draw());
+    assert!(game.resigned().is_empty());
+    assert_eq!(game.status(), Status::Playing(Color::Blue));
+    game.resign(Color::Blue).unwrap();
+    game.resign(Color::Green).unwrap();
+    assert_eq!(game.status(), Status::Won(Color::Red));
+}
+
+#[test]
+fn test_move_notation() {
+    let mv = Move::new((1, 2), Ring::Bottom);
+    assert_eq!(mv.to_string(), "b3L");
+    assert_eq!("b3L".parse::<Move>(), Ok(mv));
+    assert_eq!("A1s".parse::<Move>(), Ok(Move::new((0, 0), Ring::Top)));
+    assert!("d1S".parse::<Move>().is_err());
+    assert!("a4M".parse::<Move>().is_err());
+    assert!("a1X".parse::<Move>().is_err());
+}
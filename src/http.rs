@@ -0,0 +1,287 @@
+use std::io::{self, BufRead, Read, Write};
+
+use sha1::sha1;
+
+// The small part of HTTP/1.1 and WebSocket the game server needs. Every
+// request gets its own connection, which is closed after the response
+// unless it turns into a WebSocket.
+
+// Requests with larger bodies are refused
+const MAX_BODY: usize = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+// The request line and every header line
+const MAX_LINE: usize = 8 * 1024;
+
+const WEBSOCKET_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Request {
+    pub method: String,
+    // Without the query string
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.by_ref().take(MAX_LINE as u64).read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the connection was closed"));
+    }
+    if !line.ends_with('\n') {
+        return Err(invalid_data("the line is too long"));
+    }
+    Ok(line.trim_end_matches(|c| c == '\r' || c == '\n').to_owned())
+}
+
+impl Request {
+    pub fn read<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+        let line = read_line(reader)?;
+        let words: Vec<&str> = line.split(' ').collect();
+        if words.len() != 3 || !words[2].starts_with("HTTP/") {
+            return Err(invalid_data("not an HTTP request"));
+        }
+        let path = words[1].split('?').next().unwrap_or("").to_owned();
+        let mut headers = vec![];
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(invalid_data("too many headers"));
+            }
+            match line.find(':') {
+                Some(i) => headers.push((line[..i].trim().to_lowercase(), line[i + 1..].trim().to_owned())),
+                None => return Err(invalid_data("invalid header"))
+            }
+        }
+        let mut request = Request {
+            method: words[0].to_owned(),
+            path: path,
+            headers: headers,
+            body: String::new()
+        };
+        let length = match request.header("content-length") {
+            Some(length) => length.parse().map_err(|_| invalid_data("invalid content length"))?,
+            None => 0
+        };
+        if length > MAX_BODY {
+            return Err(invalid_data("the body is too large"));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        request.body = String::from_utf8(body).map_err(|_| invalid_data("the body is not UTF-8"))?;
+        Ok(request)
+    }
+
+    // Header names are compared without case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|&&(ref n, _)| *n == name).map(|&(_, ref value)| &value[..])
+    }
+
+    pub fn is_websocket(&self) -> bool {
+        self.header("upgrade").map_or(false, |upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error"
+    }
+}
+
+// Browsers only let pages from elsewhere read the answers with these
+const CORS_HEADERS: &'static str = "Access-Control-Allow-Origin: *\r\n\
+                                    Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+                                    Access-Control-Allow-Headers: Content-Type\r\n";
+
+pub fn write_response<W: Write>(writer: &mut W, status: u16, content_type: &str, body: &str)
+                                -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {} {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n\r\n{}",
+           status, reason(status), CORS_HEADERS, content_type, body.len(), body)?;
+    writer.flush()
+}
+
+// Agrees to turn the connection into a WebSocket
+pub fn write_websocket_handshake<W: Write>(writer: &mut W, request: &Request) -> io::Result<()> {
+    let key = match request.header("sec-websocket-key") {
+        Some(key) => key,
+        None => return Err(invalid_data("missing Sec-WebSocket-Key"))
+    };
+    write!(writer, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                    Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+           websocket_accept(key))?;
+    writer.flush()
+}
+
+pub fn websocket_accept(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close
+}
+
+impl Frame {
+    fn opcode(&self) -> u8 {
+        match self {
+            &Frame::Text(_) => 0x1,
+            &Frame::Binary(_) => 0x2,
+            &Frame::Close => 0x8,
+            &Frame::Ping(_) => 0x9,
+            &Frame::Pong(_) => 0xa
+        }
+    }
+
+    fn payload(&self) -> &[u8] {
+        match self {
+            &Frame::Text(ref text) => text.as_bytes(),
+            &Frame::Binary(ref data) | &Frame::Ping(ref data) | &Frame::Pong(ref data) => data,
+            &Frame::Close => &[]
+        }
+    }
+
+    // Frames from the server are never masked or fragmented
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let payload = self.payload();
+        let mut header = vec![0x80 | self.opcode()];
+        match payload.len() {
+            n if n < 126 => header.push(n as u8),
+            n if n < 0x10000 => {
+                header.push(126);
+                header.extend_from_slice(&[(n >> 8) as u8, n as u8]);
+            },
+            n => {
+                header.push(127);
+                header.extend((0..8).rev().map(|i| ((n as u64) >> (i * 8)) as u8));
+            }
+        }
+        writer.write_all(&header)?;
+        writer.write_all(payload)?;
+        writer.flush()
+    }
+
+    // Reads a frame from a client. Fragmented messages aren't supported,
+    // the server has no use for anything clients send except closing.
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Frame> {
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header)?;
+        let length = match header[1] & 0x7f {
+            126 => {
+                let mut bytes = [0u8; 2];
+                reader.read_exact(&mut bytes)?;
+                ((bytes[0] as usize) << 8) | bytes[1] as usize
+            },
+            127 => {
+                let mut bytes = [0u8; 8];
+                reader.read_exact(&mut bytes)?;
+                bytes.iter().fold(0u64, |n, &byte| (n << 8) | byte as u64) as usize
+            },
+            n => n as usize
+        };
+        if length > MAX_BODY {
+            return Err(invalid_data("the frame is too large"));
+        }
+        let mut mask = [0u8; 4];
+        if header[1] & 0x80 != 0 {
+            reader.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; length];
+        reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        match header[0] & 0x0f {
+            0x1 => String::from_utf8(payload)
+                .map(Frame::Text)
+                .map_err(|_| invalid_data("the text is not UTF-8")),
+            0x2 => Ok(Frame::Binary(payload)),
+            0x8 => Ok(Frame::Close),
+            0x9 => Ok(Frame::Ping(payload)),
+            0xa => Ok(Frame::Pong(payload)),
+            _ => Err(invalid_data("unsupported frame"))
+        }
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &'static [u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+// Tests
+
+#[test]
+fn test_websocket_accept() {
+    // The example from RFC 6455
+    assert_eq!(websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    assert_eq!(base64(b"ab"), "YWI=");
+    assert_eq!(base64(b"a"), "YQ==");
+}
+
+#[test]
+fn test_read_request() {
+    let text = "POST /games/1/moves?x=1 HTTP/1.1\r\nHost: localhost\r\n\
+                Content-Length: 6\r\n\r\n{\"a\":1}";
+    let request = Request::read(&mut io::Cursor::new(text)).unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/games/1/moves");
+    assert_eq!(request.header("HOST"), Some("localhost"));
+    assert_eq!(request.body, "{\"a\":1");
+    assert!(Request::read(&mut io::Cursor::new("hello\r\n\r\n")).is_err());
+    let long = format!("GET /{} HTTP/1.1\r\n\r\n", "x".repeat(MAX_LINE));
+    assert!(Request::read(&mut io::Cursor::new(long)).is_err());
+    let unfinished = format!("GET / HTTP/1.1\r\nHost: {}", "x".repeat(10));
+    assert!(Request::read(&mut io::Cursor::new(unfinished)).is_err());
+}
+
+#[test]
+fn test_frames() {
+    for frame in vec![Frame::Text("x".repeat(5)), Frame::Text("x".repeat(300)),
+                      Frame::Binary(vec![7; 70000]), Frame::Ping(vec![1]), Frame::Close] {
+        let mut bytes = vec![];
+        frame.write(&mut bytes).unwrap();
+        if let Frame::Binary(_) = frame {
+            // Too large to read back
+            assert!(Frame::read(&mut io::Cursor::new(bytes)).is_err());
+        } else {
+            assert_eq!(Frame::read(&mut io::Cursor::new(bytes)).unwrap(), frame);
+        }
+    }
+    // Clients mask what they send
+    let masked = [0x81, 0x82, 1, 2, 3, 4, b'h' ^ 1, b'i' ^ 2];
+    assert_eq!(Frame::read(&mut io::Cursor::new(&masked[..])).unwrap(), Frame::Text("hi".to_owned()));
+}
@@ -0,0 +1,88 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use game::Game;
+use record::Record;
+use paths;
+
+// The game being played in the window, written out again after every move
+// so that it survives the window being closed or the program crashing. The
+// journal holds a single record and is removed when the game ends.
+pub fn default_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("journal.txt"))
+}
+
+// The record is written next to the journal and moved over it, so that a
+// crash while writing leaves the last complete journal behind
+pub fn save<P: AsRef<Path>>(path: P, record: &Record) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension("tmp");
+    let file = File::create(&temporary)?;
+    record.write(BufWriter::new(&file))?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+pub fn clear<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result
+    }
+}
+
+// The game left unfinished, if there is one worth going on with
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<(Record, Game)>> {
+    match File::open(path) {
+        Ok(file) => read(BufReader::new(file)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err)
+    }
+}
+
+pub fn read<R: BufRead>(reader: R) -> io::Result<Option<(Record, Game)>> {
+    let record = match Record::read(reader)?.pop() {
+        Some(record) => record,
+        None => return Ok(None)
+    };
+    let game = record.replay()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    if game.is_over() || record.moves.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((record, game)))
+}
+
+// Tests
+
+#[test]
+fn test_journal() {
+    let mut game = Game::two_player();
+    assert!(read(&b""[..]).unwrap().is_none());
+
+    let mut text = vec![];
+    Record::from_game(&game).write(&mut text).unwrap();
+    assert!(read(&text[..]).unwrap().is_none());
+
+    for mv in "a1S a2S b1S b2S".split(' ') {
+        game.place(mv.parse().unwrap()).unwrap();
+    }
+    let mut record = Record::from_game(&game);
+    record.set_tag("Start", "blue");
+    let mut text = vec![];
+    record.write(&mut text).unwrap();
+    let (resumed, resumed_game) = read(&text[..]).unwrap().unwrap();
+    assert_eq!(resumed, record);
+    assert_eq!(resumed_game.board(), game.board());
+    assert_eq!(resumed_game.current(), game.current());
+    assert_eq!(resumed_game.supplies(), game.supplies());
+
+    game.place("c1S".parse().unwrap()).unwrap();
+    let mut text = vec![];
+    Record::from_game(&game).write(&mut text).unwrap();
+    assert!(read(&text[..]).unwrap().is_none());
+    assert!(read(&b"b2M b2M\n"[..]).is_err());
+}
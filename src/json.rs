@@ -0,0 +1,352 @@
+use std::fmt;
+use std::error::Error;
+use std::str::FromStr;
+
+// Just enough JSON for the game server, requests are small and responses
+// are built by hand
+#[derive(Debug,Clone,PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Keys keep their order so that responses read the same every time
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_owned(), value)).collect())
+    }
+
+    pub fn string(text: &str) -> Json {
+        Json::String(text.to_owned())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            &Json::Object(ref fields) =>
+                fields.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref value)| value),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            &Json::String(ref text) => Some(text),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            &Json::Array(ref items) => Some(items),
+            _ => None
+        }
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Json::Null => f.write_str("null"),
+            &Json::Bool(value) => write!(f, "{}", value),
+            &Json::Number(n) if n.fract() == 0. && n.abs() < 1e15 => write!(f, "{}", n as i64),
+            &Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            &Json::Number(_) => f.write_str("null"),
+            &Json::String(ref text) => write_string(f, text),
+            &Json::Array(ref items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            },
+            &Json::Object(ref fields) => {
+                f.write_str("{")?;
+                for (i, &(ref key, ref value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+// Where parsing stopped, as a byte offset into the text
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct ParseJsonError(pub usize);
+
+impl fmt::Display for ParseJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid JSON at byte {}", self.0)
+    }
+}
+
+impl Error for ParseJsonError {
+    fn description(&self) -> &str {
+        "invalid JSON"
+    }
+}
+
+// Nesting deeper than this is refused rather than overflowing the stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self) -> Result<T, ParseJsonError> {
+        Err(ParseJsonError(self.position))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.position += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), ParseJsonError> {
+        if self.text[self.position..].starts_with(expected) {
+            self.position += expected.len();
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, ParseJsonError> {
+        if depth > MAX_DEPTH {
+            return self.error();
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(depth),
+            Some('{') => self.object(depth),
+            Some(c) if c == '-' || c.is_digit(10) => self.number(),
+            _ => self.error()
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, ParseJsonError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if !(c.is_digit(10) || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break;
+            }
+            self.position += 1;
+        }
+        match self.text[start..self.position].parse() {
+            Ok(n) => Ok(Json::Number(n)),
+            Err(_) => Err(ParseJsonError(start))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseJsonError> {
+        self.expect("\"")?;
+        let mut text = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return self.error()
+            };
+            self.position += c.len_utf8();
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some(c) => c,
+                        None => return self.error()
+                    };
+                    self.position += 1;
+                    text.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return self.error()
+                    });
+                },
+                c if (c as u32) < 0x20 => return self.error(),
+                c => text.push(c)
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseJsonError> {
+        let digits = self.text.get(self.position..self.position + 4);
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(n) => {
+                self.position += 4;
+                Ok(n)
+            },
+            None => self.error()
+        }
+    }
+
+    // Characters outside the basic plane come as surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, ParseJsonError> {
+        let high = self.hex4()?;
+        let code = if high >= 0xd800 && high < 0xdc00 {
+            self.expect("\\u")?;
+            let low = self.hex4()?;
+            if low < 0xdc00 || low >= 0xe000 {
+                return self.error();
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        match ::std::char::from_u32(code) {
+            Some(c) => Ok(c),
+            None => self.error()
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Json, ParseJsonError> {
+        self.expect("[")?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                },
+                _ => return self.error()
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Json, ParseJsonError> {
+        self.expect("{")?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            fields.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                },
+                _ => return self.error()
+            }
+        }
+    }
+}
+
+impl FromStr for Json {
+    type Err = ParseJsonError;
+
+    fn from_str(text: &str) -> Result<Json, ParseJsonError> {
+        let mut parser = Parser { text: text, position: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.position != text.len() {
+            return parser.error();
+        }
+        Ok(value)
+    }
+}
+
+// Tests
+
+#[test]
+fn test_json_round_trip() {
+    let value = Json::object(vec![
+        ("id", Json::from(3)),
+        ("name", Json::string("a \"quoted\" name\n")),
+        ("moves", Json::Array(vec![Json::string("b2M"), Json::string("a1S")])),
+        ("winner", Json::Null),
+        ("over", Json::Bool(false)),
+        ("half", Json::Number(0.5))
+    ]);
+    let text = value.to_string();
+    assert_eq!(text, r#"{"id":3,"name":"a \"quoted\" name\n","moves":["b2M","a1S"],"winner":null,"over":false,"half":0.5}"#);
+    assert_eq!(text.parse::<Json>(), Ok(value));
+}
+
+#[test]
+fn test_json_parse() {
+    let value: Json = r#" { "players" : [ "blue", "green" ], "n": -1e2, "s": "\u00e4\ud83d\ude00\/" } "#
+        .parse().unwrap();
+    assert_eq!(value.get("players").and_then(Json::as_array).map(|players| players.len()), Some(2));
+    assert_eq!(value.get("n"), Some(&Json::Number(-100.)));
+    assert_eq!(value.get("s").and_then(Json::as_str), Some("ä😀/"));
+    assert_eq!("[1, 2".parse::<Json>(), Err(ParseJsonError(5)));
+    assert!("{\"a\" 1}".parse::<Json>().is_err());
+    assert!("[] []".parse::<Json>().is_err());
+    assert!("\"\\ud800\"".parse::<Json>().is_err());
+    assert!("[".repeat(100).parse::<Json>().is_err());
+}
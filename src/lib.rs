@@ -0,0 +1,30 @@
+#[macro_use]
+extern crate itertools;
+
+pub mod model;
+pub mod game;
+pub mod zobrist;
+pub mod transposition;
+pub mod symmetry;
+pub mod random;
+pub mod solver;
+pub mod engine;
+pub mod book;
+pub mod paths;
+pub mod protocol;
+pub mod external;
+pub mod tournament;
+pub mod record;
+pub mod net;
+pub mod discovery;
+pub mod json;
+pub mod sha1;
+pub mod http;
+pub mod server;
+pub mod correspondence;
+pub mod clock;
+pub mod series;
+pub mod setup;
+pub mod profile;
+pub mod archive;
+pub mod journal;
@@ -1,5 +1,4 @@
-#[macro_use]
-extern crate itertools;
+extern crate three;
 
 #[macro_use]
 extern crate slog;
@@ -11,23 +10,39 @@ extern crate gdk_sys;
 extern crate gtk;
 extern crate cairo;
 
+use std::env;
+
 use slog::Logger;
 use slog::DrainExt;
 
-mod model;
 mod window;
 mod cell;
+mod render;
+mod cli;
+mod tui;
+
+use three::{model, game, solver, engine, book, random, record, protocol, external, tournament, net, discovery, server, correspondence, clock, series, setup, profile, archive, journal};
 
 use window::MainWindow;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.get(0).map(|arg| &arg[..]) {
+        None => gui(&[]),
+        Some("gui") => gui(&args[1..]),
+        Some(arg) if arg.starts_with("--") && arg != "--help" => gui(&args),
+        Some(_) => cli::run(&args)
+    }
+}
+
+fn gui(args: &[String]) {
+    let (engines, connection, time_control, series, start) = cli::gui_options(args);
+
     gtk::init().unwrap();
 
     let drain = slog_term::streamer().compact().build().fuse();
     let log = Logger::root(drain, None);
 
-    let app = MainWindow::new(log);
+    let app = MainWindow::new(log, engines, connection, time_control, series, start);
     app.run();
 }
-
-
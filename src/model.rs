@@ -1,4 +1,6 @@
+use std::fmt;
 use std::slice;
+use std::str::FromStr;
 use std::ops::{Index, IndexMut};
 
 #[derive(Debug,Clone,Copy,PartialEq,Eq)]
@@ -9,6 +11,58 @@ pub enum Color {
     Yellow
 }
 
+pub const COLORS: [Color; 4] = [Color::Blue, Color::Green, Color::Red, Color::Yellow];
+
+impl Color {
+    pub fn index(&self) -> usize {
+        match self {
+            &Color::Blue => 0,
+            &Color::Green => 1,
+            &Color::Red => 2,
+            &Color::Yellow => 3
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Color::Blue => "blue",
+            &Color::Green => "green",
+            &Color::Red => "red",
+            &Color::Yellow => "yellow"
+        }
+    }
+
+    pub fn initial(&self) -> char {
+        match self {
+            &Color::Blue => 'b',
+            &Color::Green => 'g',
+            &Color::Red => 'r',
+            &Color::Yellow => 'y'
+        }
+    }
+
+    // Foreground color in terminals
+    pub fn ansi_code(&self) -> u8 {
+        match self {
+            &Color::Blue => 34,
+            &Color::Green => 32,
+            &Color::Red => 31,
+            &Color::Yellow => 33
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Color, String> {
+        COLORS.iter()
+            .find(|color| color.name() == s.to_lowercase())
+            .cloned()
+            .ok_or_else(|| format!("unknown color \"{}\"", s))
+    }
+}
+
 #[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum Ring {
     Top,
@@ -16,7 +70,17 @@ pub enum Ring {
     Bottom
 }
 
+pub const RINGS: [Ring; 3] = [Ring::Top, Ring::Middle, Ring::Bottom];
+
 impl Ring {
+    pub fn index(&self) -> usize {
+        match self {
+            &Ring::Top => 0,
+            &Ring::Middle => 1,
+            &Ring::Bottom => 2
+        }
+    }
+
     pub fn radius(&self, width: f64, height: f64) -> f64 {
         let dim = width.min(height) / 2.;
         let factor = match self {
@@ -72,6 +136,47 @@ impl Layer {
     pub fn full(&self) -> bool {
         self.0.iter().all(|p| p.is_some())
     }
+
+    pub fn ansi(&self) -> Ansi<Layer> {
+        Ansi(self)
+    }
+}
+
+// Each ring is written as the first letter of its owner's color or a dot
+fn write_ring(f: &mut fmt::Formatter, color: Option<Color>, ansi: bool) -> fmt::Result {
+    match color {
+        Some(color) if ansi => write!(f, "\x1b[{}m{}\x1b[0m", color.ansi_code(), color.initial()),
+        Some(color) => write!(f, "{}", color.initial()),
+        None => write!(f, ".")
+    }
+}
+
+fn write_layer_row(f: &mut fmt::Formatter, layer: &Layer, y: usize, ansi: bool) -> fmt::Result {
+    for x in 0..3 {
+        write_ring(f, layer[(x, y)], ansi)?;
+    }
+    Ok(())
+}
+
+fn write_layer(f: &mut fmt::Formatter, layer: &Layer, ansi: bool) -> fmt::Result {
+    for y in 0..3 {
+        if y > 0 {
+            writeln!(f)?;
+        }
+        write_layer_row(f, layer, y, ansi)?;
+    }
+    Ok(())
+}
+
+// Three rows of three rings
+//
+//   b.g
+//   .r.
+//   ...
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_layer(f, self, false)
+    }
 }
 
 pub fn location_to_index(width: usize, (x, y): Location) -> usize {
@@ -118,7 +223,7 @@ impl Lines for Layer {
     }
 }
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,Clone,PartialEq,Eq)]
 pub struct Board {
     top: Layer,
     middle: Layer,
@@ -154,6 +259,16 @@ impl Board {
             .or(self.three_in_order())
     }
 
+    // The rings that won the game, if any
+    pub fn winning_pattern(&self) -> Option<Pattern> {
+        patterns().into_iter().find(|pattern| {
+            let (location, ring) = pattern.rings[0];
+            let owner = self.get_ring(location, ring);
+            owner.is_some() &&
+                pattern.rings.iter().all(|&(location, ring)| self.get_ring(location, ring) == owner)
+        })
+    }
+
     fn full_stack(&self) -> Option<Color> {
         for stack in izip!(self.top.iter(),
                            self.middle.iter(),
@@ -197,6 +312,10 @@ impl Board {
         self.top.full() && self.middle.full() && self.bottom.full()
     }
 
+    pub fn ansi(&self) -> Ansi<Board> {
+        Ansi(self)
+    }
+
     pub fn get_ring(&self, ind: Location, ring: Ring) -> Option<Color> {
         let layer = match ring {
             Ring::Top => &self.top,
@@ -215,6 +334,146 @@ impl Board {
         layer[ind] = Some(color);
     }
 
+    pub fn clear_ring(&mut self, ind: Location, ring: Ring) {
+        let layer = match ring {
+            Ring::Top => &mut self.top,
+            Ring::Middle => &mut self.middle,
+            Ring::Bottom => &mut self.bottom
+        };
+        layer[ind] = None;
+    }
+
+}
+
+// Every cell shows the owners of its large, medium and small ring in that
+// order. The alternate form {:#} shows the layers side by side instead.
+//
+//       a   b   c           S    M    L
+//   1  ..b ... ...          b..  ...  ...
+//   2  ... bg. ...          ...  .g.  .b.
+//   3  ... ... .r.          ...  ..r  ...
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_board(f, self, false)
+    }
+}
+
+fn write_board(f: &mut fmt::Formatter, board: &Board, ansi: bool) -> fmt::Result {
+    if f.alternate() {
+        write!(f, "S    M    L")?;
+        for y in 0..3 {
+            writeln!(f)?;
+            write_layer_row(f, &board.top, y, ansi)?;
+            write!(f, "  ")?;
+            write_layer_row(f, &board.middle, y, ansi)?;
+            write!(f, "  ")?;
+            write_layer_row(f, &board.bottom, y, ansi)?;
+        }
+    } else {
+        write!(f, "    a   b   c")?;
+        for y in 0..3 {
+            write!(f, "\n{} ", y + 1)?;
+            for x in 0..3 {
+                write!(f, " ")?;
+                for ring in [Ring::Bottom, Ring::Middle, Ring::Top].iter() {
+                    write_ring(f, board.get_ring((x, y), *ring), ansi)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Shows a board or a layer with the rings in terminal colors
+pub struct Ansi<'a, T: 'a>(&'a T);
+
+impl<'a> fmt::Display for Ansi<'a, Board> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_board(f, self.0, true)
+    }
+}
+
+impl<'a> fmt::Display for Ansi<'a, Layer> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_layer(f, self.0, true)
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PatternKind {
+    FullStack,
+    ThreeOfSame,
+    ThreeInOrder
+}
+
+pub const PATTERN_KINDS: [PatternKind; 3] =
+    [PatternKind::FullStack, PatternKind::ThreeOfSame, PatternKind::ThreeInOrder];
+
+impl PatternKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &PatternKind::FullStack => "full-stack",
+            &PatternKind::ThreeOfSame => "three-of-same",
+            &PatternKind::ThreeInOrder => "three-in-order"
+        }
+    }
+}
+
+impl FromStr for PatternKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PatternKind, String> {
+        PATTERN_KINDS.iter()
+            .find(|kind| kind.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown pattern \"{}\"", s))
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub rings: [(Location, Ring); 3]
+}
+
+fn lines() -> Vec<[Location; 3]> {
+    let mut lines = vec![];
+    for n in 0..3 {
+        lines.push([(0, n), (1, n), (2, n)]);
+        lines.push([(n, 0), (n, 1), (n, 2)]);
+    }
+    lines.push([(0, 0), (1, 1), (2, 2)]);
+    lines.push([(0, 2), (1, 1), (2, 0)]);
+    lines
+}
+
+// Every combination of rings that wins the game when owned by one player
+pub fn patterns() -> Vec<Pattern> {
+    let mut patterns = vec![];
+    for i in 0..9 {
+        let location = index_to_location(3, i);
+        patterns.push(Pattern {
+            kind: PatternKind::FullStack,
+            rings: [(location, Ring::Top), (location, Ring::Middle), (location, Ring::Bottom)]
+        });
+    }
+    for line in lines() {
+        for ring in RINGS.iter() {
+            patterns.push(Pattern {
+                kind: PatternKind::ThreeOfSame,
+                rings: [(line[0], *ring), (line[1], *ring), (line[2], *ring)]
+            });
+        }
+        patterns.push(Pattern {
+            kind: PatternKind::ThreeInOrder,
+            rings: [(line[0], Ring::Top), (line[1], Ring::Middle), (line[2], Ring::Bottom)]
+        });
+        patterns.push(Pattern {
+            kind: PatternKind::ThreeInOrder,
+            rings: [(line[0], Ring::Bottom), (line[1], Ring::Middle), (line[2], Ring::Top)]
+        });
+    }
+    patterns
 }
 
 pub trait Lines {
@@ -305,25 +564,25 @@ fn test_layer_three_in_row() {
         Some(Color::Blue), Some(Color::Red), Some(Color::Blue),
         Some(Color::Green), Some(Color::Blue), Some(Color::Red)
     ]);
-    assert_eq!(layer.three_in_row(), Some(Color::Yellow));
+    assert_eq!(layer.three_in_row(), Some(Color::Yellow), "\n{}", layer);
     let layer = Layer::new([
         Some(Color::Yellow), Some(Color::Blue), Some(Color::Red),
         Some(Color::Blue), Some(Color::Red), Some(Color::Blue),
         Some(Color::Red), Some(Color::Blue), Some(Color::Green)
     ]);
-    assert_eq!(layer.three_in_row(), Some(Color::Red));
+    assert_eq!(layer.three_in_row(), Some(Color::Red), "\n{}", layer);
     let layer = Layer::new([
         Some(Color::Yellow), Some(Color::Blue), Some(Color::Green),
         Some(Color::Blue), Some(Color::Red), Some(Color::Green),
         Some(Color::Red), Some(Color::Blue), Some(Color::Green)
     ]);
-    assert_eq!(layer.three_in_row(), Some(Color::Green));
+    assert_eq!(layer.three_in_row(), Some(Color::Green), "\n{}", layer);
     let layer = Layer::new([
         Some(Color::Yellow), Some(Color::Blue), Some(Color::Green),
         Some(Color::Blue), Some(Color::Red), Some(Color::Yellow),
         Some(Color::Red), Some(Color::Blue), Some(Color::Green)
     ]);
-    assert_eq!(layer.three_in_row(), None);
+    assert_eq!(layer.three_in_row(), None, "\n{}", layer);
 }
 
 #[test]
@@ -334,7 +593,9 @@ fn test_board_full_stack() {
         None, None, None
     ]);
     let board = Board::new(layer.clone(), layer.clone(), layer);
-    assert_eq!(board.full_stack(), Some(Color::Red));
+    assert_eq!(board.full_stack(), Some(Color::Red), "\n{}", board);
+    assert_eq!(board.winning_pattern().map(|pattern| pattern.kind), Some(PatternKind::FullStack));
+    assert_eq!(Board::empty().winning_pattern(), None);
 }
 
 #[test]
@@ -355,7 +616,43 @@ fn test_board_three_in_order() {
         None, None, Some(Color::Blue)
     ]);
     let board = Board::new(top.clone(), middle.clone(), bottom.clone());
-    assert_eq!(board.three_in_order(), Some(Color::Blue));
+    assert_eq!(board.three_in_order(), Some(Color::Blue), "\n{:#}", board);
     let board = Board::new(bottom, middle, top);
-    assert_eq!(board.three_in_order(), Some(Color::Blue));
+    assert_eq!(board.three_in_order(), Some(Color::Blue), "\n{:#}", board);
+}
+
+#[test]
+fn test_patterns_win() {
+    let patterns = patterns();
+    assert_eq!(patterns.len(), 49);
+    for pattern in patterns.iter() {
+        let mut board = Board::empty();
+        for &(location, ring) in pattern.rings.iter() {
+            board.set_ring(location, ring, Color::Green);
+        }
+        assert_eq!(board.winner(), Some(Color::Green), "\n{}", board);
+        assert_eq!(board.winning_pattern(), Some(*pattern), "\n{}", board);
+    }
+}
+
+#[test]
+fn test_color_names() {
+    for color in COLORS.iter() {
+        assert_eq!(color.name().parse::<Color>(), Ok(*color));
+    }
+    assert_eq!("Red".parse::<Color>(), Ok(Color::Red));
+    assert!("purple".parse::<Color>().is_err());
+}
+
+#[test]
+fn test_board_display() {
+    let mut board = Board::empty();
+    board.set_ring((0, 0), Ring::Top, Color::Blue);
+    board.set_ring((1, 1), Ring::Bottom, Color::Blue);
+    board.set_ring((1, 1), Ring::Middle, Color::Green);
+    board.set_ring((2, 2), Ring::Middle, Color::Red);
+    assert_eq!(board.to_string(), "    a   b   c\n1  ..b ... ...\n2  ... bg. ...\n3  ... ... .r.");
+    assert_eq!(format!("{:#}", board), "S    M    L\nb..  ...  ...\n...  .g.  .b.\n...  ..r  ...");
+    assert_eq!(board.middle.to_string(), "...\n.g.\n..r");
+    assert_eq!(board.ansi().to_string().matches("\x1b[0m").count(), 4);
 }
@@ -0,0 +1,1246 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, RecvTimeoutError, TryRecvError};
+use std::thread;
+
+use model::Color;
+use game::{Game, Move, MoveError, Status};
+use engine::{Bot, Level};
+use random::Random;
+use protocol::{Command, ProtocolError};
+use discovery::{self, Announcement, Announcer, STANDARD_VARIANT};
+
+pub const DEFAULT_PORT: u16 = 7333;
+
+// How long joining a game may take
+const CONNECT_TIME: Duration = Duration::from_secs(5);
+
+// Thinking time of bots that take over abandoned seats
+const BOT_MOVE_TIME: Duration = Duration::from_millis(500);
+
+// Remote players and observers who fall this many messages behind, or
+// don't take a message for this long, are disconnected
+const REMOTE_BACKLOG: usize = 64;
+const WRITE_TIME: Duration = Duration::from_secs(5);
+
+// Players on different machines talk to the host over TCP, one message per
+// line like the engine protocol. The host owns the game, checks every move
+// and tells everyone about the ones it accepts:
+//
+//   join                                   -> welcome green 5f0e...
+//                                             position players blue green moves b2M
+//   move a1S                               -> placed green a1S (to everyone)
+//   move a1S                               -> rejected a1S not your turn
+//
+//   watch                                  -> watching
+//                                             position players blue green moves b2M
+//                                             status playing green
+//
+//   resume 5f0e...                         -> welcome green 5f0e...
+//                                             forfeited red 4
+//                                             position players blue green red moves ...
+//
+//   resign                                 -> resigned green 6 (to everyone)
+//
+// When every seat is taken the host answers join with full. Every placement
+// is followed by the status of the game, which also tells how it ended.
+// Observers get the same messages as players but can't move.
+//
+// The token in the welcome lets a player whose connection dropped take
+// their seat back, which is paused meanwhile and announced to everyone with
+// paused and resumed. If they stay away too long a bot takes over the seat
+// (replaced green) or the color forfeits (forfeited green 7, after seven
+// moves), and resume is answered with expired. Players who resign leave the
+// game the same way, but their seat is freed. Tokens only keep players on
+// the same network from mixing up seats, they are no protection against
+// anyone listening in.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Message {
+    Join,
+    Watch,
+    Resume(String),
+    Move(Move),
+    Resign,
+    Welcome(Color, String),
+    Watching,
+    Full,
+    Expired,
+    Position(Vec<Color>, Vec<Move>),
+    Placed(Color, Move),
+    Status(Status),
+    Rejected(Move, String),
+    Paused(Color),
+    Resumed(Color),
+    Replaced(Color),
+    Forfeited(Color, usize),
+    Resigned(Color, usize)
+}
+
+fn invalid(line: &str) -> ProtocolError {
+    ProtocolError::Invalid(line.to_owned())
+}
+
+impl FromStr for Message {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Message, ProtocolError> {
+        let line = line.trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Err(ProtocolError::Empty)
+        };
+        match (name, args.len()) {
+            ("join", 0) => Ok(Message::Join),
+            ("watch", 0) => Ok(Message::Watch),
+            ("resume", 1) => Ok(Message::Resume(args[0].to_owned())),
+            ("move", 1) => args[0].parse().map(Message::Move).map_err(|_| invalid(line)),
+            ("resign", 0) => Ok(Message::Resign),
+            ("welcome", 2) => {
+                let color = args[0].parse().map_err(|_| invalid(line))?;
+                Ok(Message::Welcome(color, args[1].to_owned()))
+            },
+            ("watching", 0) => Ok(Message::Watching),
+            ("full", 0) => Ok(Message::Full),
+            ("expired", 0) => Ok(Message::Expired),
+            ("position", _) => match line.parse()? {
+                Command::Position(players, moves, _) => Ok(Message::Position(players, moves)),
+                _ => Err(invalid(line))
+            },
+            ("placed", 2) => {
+                let color = args[0].parse().map_err(|_| invalid(line))?;
+                let mv = args[1].parse().map_err(|_| invalid(line))?;
+                Ok(Message::Placed(color, mv))
+            },
+            ("status", 1) if args[0] == "draw" => Ok(Message::Status(Status::Draw)),
+            ("status", 2) => {
+                let color = args[1].parse().map_err(|_| invalid(line))?;
+                match args[0] {
+                    "playing" => Ok(Message::Status(Status::Playing(color))),
+                    "won" => Ok(Message::Status(Status::Won(color))),
+                    _ => Err(invalid(line))
+                }
+            },
+            ("rejected", n) if n >= 1 => {
+                let mv = args[0].parse().map_err(|_| invalid(line))?;
+                Ok(Message::Rejected(mv, args[1..].join(" ")))
+            },
+            ("paused", 1) => args[0].parse().map(Message::Paused).map_err(|_| invalid(line)),
+            ("resumed", 1) => args[0].parse().map(Message::Resumed).map_err(|_| invalid(line)),
+            ("replaced", 1) => args[0].parse().map(Message::Replaced).map_err(|_| invalid(line)),
+            ("forfeited", 2) => {
+                let color = args[0].parse().map_err(|_| invalid(line))?;
+                let ply = args[1].parse().map_err(|_| invalid(line))?;
+                Ok(Message::Forfeited(color, ply))
+            },
+            ("resigned", 2) => {
+                let color = args[0].parse().map_err(|_| invalid(line))?;
+                let ply = args[1].parse().map_err(|_| invalid(line))?;
+                Ok(Message::Resigned(color, ply))
+            },
+            ("join", _) | ("watch", _) | ("resume", _) | ("move", _) | ("resign", _) |
+            ("welcome", _) | ("watching", _) | ("full", _) | ("expired", _) | ("placed", _) |
+            ("status", _) | ("rejected", _) | ("paused", _) | ("resumed", _) | ("replaced", _) |
+            ("forfeited", _) | ("resigned", _) => Err(invalid(line)),
+            _ => Err(ProtocolError::Unknown(line.to_owned()))
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Message::Join => write!(f, "join"),
+            &Message::Watch => write!(f, "watch"),
+            &Message::Resume(ref token) => write!(f, "resume {}", token),
+            &Message::Move(mv) => write!(f, "move {}", mv),
+            &Message::Resign => write!(f, "resign"),
+            &Message::Welcome(color, ref token) => write!(f, "welcome {} {}", color.name(), token),
+            &Message::Watching => write!(f, "watching"),
+            &Message::Full => write!(f, "full"),
+            &Message::Expired => write!(f, "expired"),
+            &Message::Position(ref players, ref moves) =>
+                write!(f, "{}", Command::Position(players.clone(), moves.clone(), vec![])),
+            &Message::Placed(color, mv) => write!(f, "placed {} {}", color.name(), mv),
+            &Message::Status(Status::Playing(color)) => write!(f, "status playing {}", color.name()),
+            &Message::Status(Status::Won(color)) => write!(f, "status won {}", color.name()),
+            &Message::Status(Status::Draw) => write!(f, "status draw"),
+            &Message::Rejected(mv, ref reason) => write!(f, "rejected {} {}", mv, reason),
+            &Message::Paused(color) => write!(f, "paused {}", color.name()),
+            &Message::Resumed(color) => write!(f, "resumed {}", color.name()),
+            &Message::Replaced(color) => write!(f, "replaced {}", color.name()),
+            &Message::Forfeited(color, ply) => write!(f, "forfeited {} {}", color.name(), ply),
+            &Message::Resigned(color, ply) => write!(f, "resigned {} {}", color.name(), ply)
+        }
+    }
+}
+
+// What happened since the last poll
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Event {
+    Joined(Color),
+    // Only once the game is over, before that seats are paused
+    Left(Color),
+    Paused(Color),
+    Resumed(Color),
+    Replaced(Color),
+    Forfeited(Color),
+    Resigned(Color),
+    Watching,
+    Placed(Color, Move),
+    Status(Status),
+    Rejected(Move, String),
+    Disconnected
+}
+
+fn send(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+    writeln!(stream, "{}", message)?;
+    stream.flush()
+}
+
+// Reads messages on a thread of their own and hands them to the callback
+// until it returns false. None means the connection was closed.
+fn read_messages<F>(stream: TcpStream, mut handle: F)
+    where F: FnMut(Option<Message>) -> bool + Send + 'static {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let message = match line {
+                Ok(line) => line.parse(),
+                Err(_) => break
+            };
+            // Lines we don't understand are skipped
+            if let Ok(message) = message {
+                if !handle(Some(message)) {
+                    return;
+                }
+            }
+        }
+        handle(None);
+    });
+}
+
+enum Input {
+    Connected(usize, TcpStream),
+    Received(usize, Message),
+    Closed(usize),
+    // A bot's move for the color at the given ply
+    BotMove(Color, usize, Move)
+}
+
+// Messages to remotes are written on a thread of their own so that a peer
+// that stops reading can't hold up the host
+struct Remote {
+    id: usize,
+    stream: TcpStream,
+    messages: SyncSender<Message>,
+    color: Option<Color>,
+    observer: bool
+}
+
+impl Remote {
+    fn new(id: usize, stream: TcpStream) -> io::Result<Remote> {
+        let mut writer = stream.try_clone()?;
+        writer.set_write_timeout(Some(WRITE_TIME))?;
+        let (messages, queue) = mpsc::sync_channel(REMOTE_BACKLOG);
+        thread::spawn(move || {
+            for message in queue {
+                if send(&mut writer, &message).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        Ok(Remote {
+            id: id,
+            stream: stream,
+            messages: messages,
+            color: None,
+            observer: false
+        })
+    }
+
+    // Closed connections are noticed by their readers
+    fn send(&self, message: &Message) {
+        if self.messages.try_send(message.clone()).is_err() {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// What becomes of a seat whose player doesn't come back in time
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Abandoned {
+    Bot(Level),
+    Forfeit
+}
+
+enum SeatState {
+    Connected,
+    Paused(Instant),
+    Bot(Level)
+}
+
+// A color taken by a remote player
+struct Seat {
+    color: Color,
+    token: String,
+    state: SeatState
+}
+
+// Hosts a game on a TCP port. The local colors are played on this machine
+// and the rest of the seats go to whoever joins first.
+pub struct Host {
+    game: Game,
+    local: Vec<Color>,
+    remotes: Vec<Remote>,
+    seats: Vec<Seat>,
+    seat_timeout: Option<(Duration, Abandoned)>,
+    random: Random,
+    inputs: Receiver<Input>,
+    sender: Sender<Input>,
+    // The ply and color a bot is thinking about
+    thinking: Option<(usize, Color)>,
+    address: SocketAddr,
+    announcer: Option<Announcer>
+}
+
+impl Host {
+    pub fn bind<A: ToSocketAddrs>(address: A, players: &[Color], local: &[Color]) -> io::Result<Host> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let (sender, inputs) = mpsc::channel();
+        let bot_sender = sender.clone();
+        thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue
+                };
+                let reader = match stream.try_clone() {
+                    Ok(reader) => reader,
+                    Err(_) => continue
+                };
+                if sender.send(Input::Connected(id, stream)).is_err() {
+                    break;
+                }
+                let sender = sender.clone();
+                read_messages(reader, move |message| {
+                    let input = match message {
+                        Some(message) => Input::Received(id, message),
+                        None => Input::Closed(id)
+                    };
+                    sender.send(input).is_ok()
+                });
+            }
+        });
+        Ok(Host {
+            game: Game::new(players),
+            local: local.to_vec(),
+            remotes: vec![],
+            seats: vec![],
+            seat_timeout: None,
+            random: Random::from_time(),
+            inputs: inputs,
+            sender: bot_sender,
+            thinking: None,
+            address: address,
+            announcer: None
+        })
+    }
+
+    // Without a timeout paused seats wait for their players forever
+    pub fn set_seat_timeout(&mut self, timeout: Duration, abandoned: Abandoned) {
+        self.seat_timeout = Some((timeout, abandoned));
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn is_local(&self, color: Color) -> bool {
+        self.local.contains(&color)
+    }
+
+    pub fn observers(&self) -> usize {
+        self.remotes.iter().filter(|remote| remote.observer).count()
+    }
+
+    // Seats nobody has taken yet
+    pub fn open_seats(&self) -> Vec<Color> {
+        self.game.players().iter()
+            .filter(|color| !self.is_local(**color) && self.game.is_active(**color))
+            .filter(|color| !self.seats.iter().any(|seat| seat.color == **color))
+            .cloned()
+            .collect()
+    }
+
+    // Seats waiting for their players to reconnect
+    pub fn paused_seats(&self) -> Vec<Color> {
+        self.seats.iter()
+            .filter(|seat| match seat.state {
+                SeatState::Paused(_) => true,
+                _ => false
+            })
+            .map(|seat| seat.color)
+            .collect()
+    }
+
+    // Lets others on the network find the game under the given name, see
+    // discovery::Browser. The announcement follows the seats as they fill.
+    pub fn announce(&mut self, target: SocketAddr, name: &str) -> io::Result<()> {
+        let announcement = Announcement {
+            port: self.address.port(),
+            variant: STANDARD_VARIANT.to_owned(),
+            players: self.game.players().to_vec(),
+            open_seats: self.open_seats(),
+            name: discovery::clean_name(name)
+        };
+        self.announcer = Some(Announcer::start(target, announcement)?);
+        Ok(())
+    }
+
+    fn update_announcement(&self) {
+        if let Some(ref announcer) = self.announcer {
+            announcer.update(Announcement {
+                open_seats: self.open_seats(),
+                ..announcer.announcement()
+            });
+        }
+    }
+
+    // Plays a move for one of the local colors
+    pub fn play(&mut self, mv: Move) -> Result<(), MoveError> {
+        let color = self.game.current();
+        self.game.place(mv)?;
+        self.placed(color, mv);
+        Ok(())
+    }
+
+    // Gives up for one of the local colors
+    pub fn resign(&mut self, color: Color) -> Result<(), MoveError> {
+        let ply = self.game.history().len();
+        self.game.resign(color)?;
+        self.seats.retain(|seat| seat.color != color);
+        let status = self.game.status();
+        self.broadcast(&Message::Resigned(color, ply));
+        self.broadcast(&Message::Status(status));
+        Ok(())
+    }
+
+    // The player stays connected and follows the rest of the game
+    fn remote_resign(&mut self, index: usize) -> Option<Event> {
+        let color = self.remotes[index].color?;
+        self.resign(color).ok()?;
+        self.remotes[index].color = None;
+        self.remotes[index].observer = true;
+        Some(Event::Resigned(color))
+    }
+
+    fn placed(&mut self, color: Color, mv: Move) {
+        let status = self.game.status();
+        self.broadcast(&Message::Placed(color, mv));
+        self.broadcast(&Message::Status(status));
+    }
+
+    fn broadcast(&mut self, message: &Message) {
+        for remote in self.remotes.iter() {
+            remote.send(message);
+        }
+    }
+
+    fn remote_move(&mut self, index: usize, mv: Move) -> Option<Event> {
+        let result = match (self.remotes[index].color, self.game.status()) {
+            (None, _) if self.remotes[index].observer => Err("watching only".to_owned()),
+            (None, _) => Err("not seated".to_owned()),
+            (Some(color), Status::Playing(current)) if color != current =>
+                Err("not your turn".to_owned()),
+            (Some(color), _) => self.game.place(mv).map(|_| color).map_err(|err| err.to_string())
+        };
+        match result {
+            Ok(color) => {
+                self.placed(color, mv);
+                Some(Event::Placed(color, mv))
+            },
+            Err(reason) => {
+                self.remotes[index].send(&Message::Rejected(mv, reason));
+                None
+            }
+        }
+    }
+
+    fn new_token(&mut self) -> String {
+        format!("{:016x}{:016x}", self.random.next_u64(), self.random.next_u64())
+    }
+
+    fn seat(&mut self, index: usize) -> Option<Event> {
+        let remote = &self.remotes[index];
+        let color = match self.open_seats().first() {
+            Some(&color) if remote.color.is_none() && !remote.observer => color,
+            _ => {
+                self.remotes[index].send(&Message::Full);
+                return None;
+            }
+        };
+        let token = self.new_token();
+        self.seats.push(Seat { color: color, token: token.clone(), state: SeatState::Connected });
+        self.remotes[index].color = Some(color);
+        self.send_game(index, &Message::Welcome(color, token));
+        Some(Event::Joined(color))
+    }
+
+    fn resume(&mut self, index: usize, token: &str) -> Option<Event> {
+        if self.remotes[index].color.is_some() || self.remotes[index].observer {
+            return None;
+        }
+        let color = match self.seats.iter_mut().find(|seat| seat.token == token) {
+            Some(seat) => {
+                seat.state = SeatState::Connected;
+                seat.color
+            },
+            None => {
+                self.remotes[index].send(&Message::Expired);
+                return None;
+            }
+        };
+        // The old connection may not have noticed yet that it is gone
+        if let Some(old) = self.remotes.iter_mut().find(|remote| remote.color == Some(color)) {
+            let _ = old.stream.shutdown(Shutdown::Both);
+            old.color = None;
+        }
+        self.remotes[index].color = Some(color);
+        self.send_game(index, &Message::Welcome(color, token.to_owned()));
+        self.broadcast(&Message::Resumed(color));
+        Some(Event::Resumed(color))
+    }
+
+    fn watch(&mut self, index: usize) -> Option<Event> {
+        if self.remotes[index].color.is_some() {
+            return None;
+        }
+        self.remotes[index].observer = true;
+        self.send_game(index, &Message::Watching);
+        Some(Event::Watching)
+    }
+
+    // Everything a newcomer needs to follow the game
+    fn send_game(&mut self, index: usize, greeting: &Message) {
+        let forfeits: Vec<Message> = self.game.forfeits().iter()
+            .map(|&(color, ply)| if self.game.resigned().contains(&color) {
+                Message::Resigned(color, ply)
+            } else {
+                Message::Forfeited(color, ply)
+            })
+            .collect();
+        let moves = self.game.history().iter().map(|placement| placement.mv).collect();
+        let position = Message::Position(self.game.players().to_vec(), moves);
+        let status = Message::Status(self.game.status());
+        let remote = &self.remotes[index];
+        remote.send(greeting);
+        for forfeit in forfeits.iter() {
+            remote.send(forfeit);
+        }
+        remote.send(&position);
+        remote.send(&status);
+    }
+
+    // Seats stay reserved for their players until the game is over
+    fn leave(&mut self, color: Color) -> Event {
+        if self.game.is_over() {
+            self.seats.retain(|seat| seat.color != color);
+            return Event::Left(color);
+        }
+        if let Some(seat) = self.seats.iter_mut().find(|seat| seat.color == color) {
+            seat.state = SeatState::Paused(Instant::now());
+        }
+        self.broadcast(&Message::Paused(color));
+        Event::Paused(color)
+    }
+
+    // Gives up the seats of players who have been gone too long
+    fn abandon_seats(&mut self) -> Vec<Event> {
+        let (timeout, abandoned) = match self.seat_timeout {
+            Some(seat_timeout) => seat_timeout,
+            None => return vec![]
+        };
+        let expired: Vec<Color> = self.seats.iter()
+            .filter(|seat| match seat.state {
+                SeatState::Paused(since) => since.elapsed() >= timeout,
+                _ => false
+            })
+            .map(|seat| seat.color)
+            .collect();
+        let mut events = vec![];
+        for color in expired {
+            match abandoned {
+                Abandoned::Bot(level) => {
+                    if let Some(seat) = self.seats.iter_mut().find(|seat| seat.color == color) {
+                        seat.state = SeatState::Bot(level);
+                    }
+                    self.broadcast(&Message::Replaced(color));
+                    events.push(Event::Replaced(color));
+                },
+                Abandoned::Forfeit => {
+                    self.seats.retain(|seat| seat.color != color);
+                    let ply = self.game.history().len();
+                    if self.game.forfeit(color).is_ok() {
+                        let status = self.game.status();
+                        self.broadcast(&Message::Forfeited(color, ply));
+                        self.broadcast(&Message::Status(status));
+                        events.push(Event::Forfeited(color));
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    fn bot_level(&self, color: Color) -> Option<Level> {
+        self.seats.iter()
+            .find(|seat| seat.color == color)
+            .and_then(|seat| match seat.state {
+                SeatState::Bot(level) => Some(level),
+                _ => None
+            })
+    }
+
+    // Bots that took over a seat think on a thread of their own, so that
+    // polling never waits for them, and their moves arrive with the inputs
+    fn start_bot(&mut self) {
+        let color = match self.game.status() {
+            Status::Playing(color) => color,
+            _ => return
+        };
+        let level = match self.bot_level(color) {
+            Some(level) => level,
+            None => return
+        };
+        let ply = self.game.history().len();
+        if self.thinking == Some((ply, color)) {
+            return;
+        }
+        self.thinking = Some((ply, color));
+        let game = self.game.clone();
+        let random = Random::new(self.random.next_u64());
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            if let Some(mv) = Bot::new(level, random).choose_within(&game, BOT_MOVE_TIME) {
+                let _ = sender.send(Input::BotMove(color, ply, mv));
+            }
+        });
+    }
+
+    // Moves of bots whose player came back or whose game went on without
+    // them are dropped
+    fn bot_move(&mut self, color: Color, ply: usize, mv: Move) -> Option<Event> {
+        if self.game.history().len() != ply || self.game.status() != Status::Playing(color) ||
+            self.bot_level(color).is_none() {
+            return None;
+        }
+        self.game.place(mv).ok()?;
+        self.placed(color, mv);
+        Some(Event::Placed(color, mv))
+    }
+
+    // Handles everything that arrived from the remote players, call this
+    // regularly
+    pub fn poll(&mut self) -> Vec<Event> {
+        let mut events = vec![];
+        while let Ok(input) = self.inputs.try_recv() {
+            let event = match input {
+                Input::Connected(id, stream) => {
+                    if let Ok(remote) = Remote::new(id, stream) {
+                        self.remotes.push(remote);
+                    }
+                    None
+                },
+                Input::Received(id, message) => {
+                    let index = match self.remotes.iter().position(|remote| remote.id == id) {
+                        Some(index) => index,
+                        None => continue
+                    };
+                    match message {
+                        Message::Join => self.seat(index),
+                        Message::Watch => self.watch(index),
+                        Message::Resume(token) => self.resume(index, &token),
+                        Message::Move(mv) => self.remote_move(index, mv),
+                        Message::Resign => self.remote_resign(index),
+                        _ => None
+                    }
+                },
+                Input::Closed(id) => {
+                    let index = self.remotes.iter().position(|remote| remote.id == id);
+                    index.and_then(|index| self.remotes.remove(index).color)
+                        .map(|color| self.leave(color))
+                },
+                Input::BotMove(color, ply, mv) => self.bot_move(color, ply, mv)
+            };
+            events.extend(event);
+        }
+        events.extend(self.abandon_seats());
+        self.start_bot();
+        self.update_announcement();
+        events
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        for remote in self.remotes.iter() {
+            let _ = remote.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// A seat in a game hosted on another machine, or a place to watch it from.
+// The game here mirrors the host's and only changes when the host says a
+// move was placed.
+pub struct Client {
+    stream: TcpStream,
+    address: SocketAddr,
+    color: Option<Color>,
+    token: Option<String>,
+    game: Game,
+    messages: Receiver<Message>,
+    connected: bool
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "the host closed the connection")
+}
+
+// Rebuilds a game from its moves and the points where players forfeited
+// or resigned
+fn replay(players: &[Color], moves: &[Move], forfeits: &[(Color, usize)], resigned: &[Color])
+          -> Result<Game, MoveError> {
+    let mut game = Game::new(players);
+    for ply in 0..moves.len() + 1 {
+        for &(color, _) in forfeits.iter().filter(|&&(_, at)| at == ply) {
+            if resigned.contains(&color) {
+                game.resign(color)?;
+            } else {
+                game.forfeit(color)?;
+            }
+        }
+        if let Some(&mv) = moves.get(ply) {
+            game.place(mv)?;
+        }
+    }
+    Ok(game)
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Client> {
+        Client::open(address, Message::Join)
+    }
+
+    // Follows the game without taking a seat
+    pub fn watch<A: ToSocketAddrs>(address: A) -> io::Result<Client> {
+        Client::open(address, Message::Watch)
+    }
+
+    // Takes back a seat with the token the host handed out when joining
+    pub fn resume<A: ToSocketAddrs>(address: A, token: &str) -> io::Result<Client> {
+        Client::open(address, Message::Resume(token.to_owned()))
+    }
+
+    // Connects again after the connection dropped, to the same seat if
+    // there was one. Fails with PermissionDenied once the seat is gone.
+    pub fn reconnect(&self) -> io::Result<Client> {
+        match self.token {
+            Some(ref token) => Client::resume(self.address, token),
+            None => Client::watch(self.address)
+        }
+    }
+
+    fn open<A: ToSocketAddrs>(address: A, request: Message) -> io::Result<Client> {
+        let stream = TcpStream::connect(address)?;
+        let address = stream.peer_addr()?;
+        let (sender, messages) = mpsc::channel();
+        read_messages(stream.try_clone()?, move |message| match message {
+            Some(message) => sender.send(message).is_ok(),
+            None => false
+        });
+        // Dropping the client closes the connection if joining fails
+        let mut client = Client {
+            stream: stream,
+            address: address,
+            color: None,
+            token: None,
+            game: Game::two_player(),
+            messages: messages,
+            connected: true
+        };
+        client.join(&request)?;
+        Ok(client)
+    }
+
+    fn join(&mut self, request: &Message) -> io::Result<()> {
+        send(&mut self.stream, request)?;
+        let mut welcomed = false;
+        let mut forfeits = vec![];
+        let mut resigned = vec![];
+        loop {
+            match self.messages.recv_timeout(CONNECT_TIME) {
+                Ok(Message::Welcome(seat, token)) => {
+                    self.color = Some(seat);
+                    self.token = Some(token);
+                    welcomed = true;
+                },
+                Ok(Message::Watching) => welcomed = true,
+                Ok(Message::Forfeited(color, ply)) => forfeits.push((color, ply)),
+                Ok(Message::Resigned(color, ply)) => {
+                    forfeits.push((color, ply));
+                    resigned.push(color);
+                },
+                Ok(Message::Position(players, moves)) => {
+                    if !welcomed {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                  "the host sent a position before a seat"));
+                    }
+                    self.game = replay(&players, &moves, &forfeits, &resigned)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                    return Ok(());
+                },
+                Ok(Message::Full) =>
+                    return Err(io::Error::new(io::ErrorKind::Other, "the game is full")),
+                Ok(Message::Expired) =>
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                              "the seat has been given up")),
+                Ok(_) => {},
+                Err(RecvTimeoutError::Timeout) =>
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "the host did not answer")),
+                Err(RecvTimeoutError::Disconnected) => return Err(disconnected())
+            }
+        }
+    }
+
+    // None for observers
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    // Hand this to resume to get the seat back
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_ref().map(|token| &token[..])
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // Asks the host to play a move, it shows up in poll once accepted
+    pub fn send_move(&mut self, mv: Move) -> io::Result<()> {
+        send(&mut self.stream, &Message::Move(mv))
+    }
+
+    // Gives up the seat, afterwards the game can still be watched
+    pub fn resign(&mut self) -> io::Result<()> {
+        send(&mut self.stream, &Message::Resign)?;
+        self.token = None;
+        Ok(())
+    }
+
+    pub fn poll(&mut self) -> Vec<Event> {
+        let mut events = vec![];
+        while self.connected {
+            match self.messages.try_recv() {
+                Ok(Message::Placed(color, mv)) => {
+                    // The host already checked the move, failing here means
+                    // we are out of sync
+                    if self.game.place(mv).is_err() {
+                        self.connected = false;
+                        events.push(Event::Disconnected);
+                    } else {
+                        events.push(Event::Placed(color, mv));
+                    }
+                },
+                Ok(Message::Status(status)) => {
+                    if status != self.game.status() {
+                        self.connected = false;
+                        events.push(Event::Disconnected);
+                    } else {
+                        events.push(Event::Status(status));
+                    }
+                },
+                Ok(Message::Forfeited(color, ply)) => {
+                    if ply != self.game.history().len() || self.game.forfeit(color).is_err() {
+                        self.connected = false;
+                        events.push(Event::Disconnected);
+                    } else {
+                        events.push(Event::Forfeited(color));
+                    }
+                },
+                Ok(Message::Resigned(color, ply)) => {
+                    if ply != self.game.history().len() || self.game.resign(color).is_err() {
+                        self.connected = false;
+                        events.push(Event::Disconnected);
+                    } else {
+                        if self.color == Some(color) {
+                            self.color = None;
+                        }
+                        events.push(Event::Resigned(color));
+                    }
+                },
+                Ok(Message::Rejected(mv, reason)) => events.push(Event::Rejected(mv, reason)),
+                Ok(Message::Paused(color)) => events.push(Event::Paused(color)),
+                Ok(Message::Resumed(color)) => events.push(Event::Resumed(color)),
+                Ok(Message::Replaced(color)) => events.push(Event::Replaced(color)),
+                Ok(_) => {},
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    events.push(Event::Disconnected);
+                }
+            }
+        }
+        events
+    }
+}
+
+// The reader thread holds a clone of the stream, so it has to be shut down
+// for the host to notice that we left
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// Either end of a networked game, as seen by a frontend
+pub enum Connection {
+    Host(Host),
+    Client(Client)
+}
+
+impl Connection {
+    pub fn game(&self) -> &Game {
+        match self {
+            &Connection::Host(ref host) => host.game(),
+            &Connection::Client(ref client) => client.game()
+        }
+    }
+
+    // Whether the color is played on this machine
+    pub fn is_local(&self, color: Color) -> bool {
+        match self {
+            &Connection::Host(ref host) => host.is_local(color),
+            &Connection::Client(ref client) => client.color() == Some(color)
+        }
+    }
+
+    pub fn poll(&mut self) -> Vec<Event> {
+        match self {
+            &mut Connection::Host(ref mut host) => host.poll(),
+            &mut Connection::Client(ref mut client) => client.poll()
+        }
+    }
+}
+
+// Tests
+
+// Polls until the given number of events has arrived
+#[cfg(test)]
+fn poll_until<F>(count: usize, mut poll: F) -> Vec<Event> where F: FnMut() -> Vec<Event> {
+    let mut events = vec![];
+    for _ in 0..200 {
+        events.extend(poll());
+        if events.len() >= count {
+            return events;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("expected {} events, got {:?}", count, events);
+}
+
+#[cfg(test)]
+fn mv(text: &str) -> Move {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_message_round_trip() {
+    let messages = vec![
+        Message::Join,
+        Message::Watch,
+        Message::Resume("0123456789abcdef".to_owned()),
+        Message::Move(mv("b2M")),
+        Message::Welcome(Color::Red, "0123456789abcdef".to_owned()),
+        Message::Watching,
+        Message::Full,
+        Message::Expired,
+        Message::Position(vec![Color::Blue, Color::Yellow], vec![mv("a1S")]),
+        Message::Position(vec![Color::Blue, Color::Green], vec![]),
+        Message::Placed(Color::Green, mv("c3L")),
+        Message::Status(Status::Playing(Color::Yellow)),
+        Message::Status(Status::Won(Color::Red)),
+        Message::Status(Status::Draw),
+        Message::Rejected(mv("a1S"), "the ring is already taken".to_owned()),
+        Message::Paused(Color::Green),
+        Message::Resumed(Color::Green),
+        Message::Replaced(Color::Red),
+        Message::Forfeited(Color::Red, 4),
+        Message::Resign,
+        Message::Resigned(Color::Green, 2)
+    ];
+    for message in messages {
+        assert_eq!(message.to_string().parse::<Message>(), Ok(message));
+    }
+    assert!("move d1S".parse::<Message>().is_err());
+    assert!("placed blue".parse::<Message>().is_err());
+    assert!("status lost blue".parse::<Message>().is_err());
+    assert!("welcome blue".parse::<Message>().is_err());
+    assert!("forfeited blue many".parse::<Message>().is_err());
+}
+
+#[test]
+fn test_host_and_client() {
+    let players = [Color::Blue, Color::Green];
+    let mut host = Host::bind("127.0.0.1:0", &players, &[Color::Blue]).unwrap();
+    let address = host.address();
+
+    let joining = thread::spawn(move || Client::connect(address));
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Joined(Color::Green)]);
+    let mut client = joining.join().unwrap().unwrap();
+    assert_eq!(client.color(), Some(Color::Green));
+    assert_eq!(poll_until(1, || client.poll()), vec![Event::Status(Status::Playing(Color::Blue))]);
+    assert!(host.open_seats().is_empty());
+
+    // A third player finds the game full
+    let joining = thread::spawn(move || Client::connect(address));
+    while !joining.is_finished() {
+        host.poll();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(joining.join().unwrap().is_err());
+    assert!(host.poll().is_empty());
+
+    host.play(mv("b2M")).unwrap();
+    assert_eq!(poll_until(2, || client.poll()),
+               vec![Event::Placed(Color::Blue, mv("b2M")), Event::Status(Status::Playing(Color::Green))]);
+
+    // The host checks both the turn and the move itself
+    client.send_move(mv("b2M")).unwrap();
+    let rejected = poll_until(1, || { host.poll(); client.poll() });
+    assert_eq!(rejected, vec![Event::Rejected(mv("b2M"), "the ring is already taken".to_owned())]);
+    client.send_move(mv("a1S")).unwrap();
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Placed(Color::Green, mv("a1S"))]);
+    assert_eq!(poll_until(2, || client.poll())[0], Event::Placed(Color::Green, mv("a1S")));
+    client.send_move(mv("a2S")).unwrap();
+    let rejected = poll_until(1, || { host.poll(); client.poll() });
+    assert_eq!(rejected, vec![Event::Rejected(mv("a2S"), "not your turn".to_owned())]);
+    assert_eq!(client.game().board(), host.game().board());
+
+    // The seat waits for its player instead of opening up again
+    drop(client);
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Paused(Color::Green)]);
+    assert_eq!(host.paused_seats(), vec![Color::Green]);
+    assert!(host.open_seats().is_empty());
+}
+
+#[test]
+fn test_observer() {
+    let players = [Color::Blue, Color::Green];
+    let mut host = Host::bind("127.0.0.1:0", &players, &players).unwrap();
+    let address = host.address();
+    host.play(mv("a1S")).unwrap();
+
+    // Observers joining late get the whole game so far
+    let watching = thread::spawn(move || Client::watch(address));
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Watching]);
+    let mut observer = watching.join().unwrap().unwrap();
+    assert_eq!(observer.color(), None);
+    assert_eq!(observer.game().board(), host.game().board());
+    assert_eq!(host.observers(), 1);
+    assert_eq!(host.open_seats(), vec![]);
+
+    observer.send_move(mv("b1S")).unwrap();
+    let events = poll_until(2, || { host.poll(); observer.poll() });
+    assert_eq!(events, vec![Event::Status(Status::Playing(Color::Green)),
+                            Event::Rejected(mv("b1S"), "watching only".to_owned())]);
+
+    for text in &["a2S", "b1S", "b2S", "c1S"] {
+        host.play(mv(text)).unwrap();
+    }
+    let events = poll_until(8, || observer.poll());
+    assert_eq!(events[6], Event::Placed(Color::Blue, mv("c1S")));
+    assert_eq!(events[7], Event::Status(Status::Won(Color::Blue)));
+    assert!(observer.game().is_over());
+}
+
+#[test]
+fn test_stuck_remote() {
+    use std::io::Read;
+
+    let players = [Color::Blue, Color::Green];
+    let mut host = Host::bind("127.0.0.1:0", &players, &players).unwrap();
+    // Asks for the whole game over and over without reading the answers,
+    // far more than the connection holds
+    let mut stream = TcpStream::connect(host.address()).unwrap();
+    let requests = "watch\n".repeat(300000);
+    let flooding = thread::spawn(move || {
+        stream.write_all(requests.as_bytes()).unwrap();
+        stream
+    });
+    let mut stream = flooding.join().unwrap();
+    let started = Instant::now();
+    host.poll();
+    host.play(mv("a1S")).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // The host hung up on it instead
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    match stream.read_to_end(&mut vec![]) {
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+            err.kind() == io::ErrorKind::TimedOut => panic!("the observer is still connected"),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_announced_seats() {
+    use discovery::Browser;
+
+    let mut browser = Browser::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let players = [Color::Blue, Color::Green];
+    let mut host = Host::bind("127.0.0.1:0", &players, &[Color::Blue]).unwrap();
+    host.announce(browser.address(), "test").unwrap();
+    let address = host.address();
+
+    let joining = thread::spawn(move || Client::connect(address));
+    poll_until(1, || host.poll());
+    let _client = joining.join().unwrap().unwrap();
+
+    // Announcements go out every second, wait for one after the join
+    for _ in 0..300 {
+        let hosts = browser.hosts();
+        if hosts.len() == 1 && hosts[0].announcement.open_seats.is_empty() {
+            assert_eq!(hosts[0].address, address);
+            assert_eq!(hosts[0].announcement.players, players.to_vec());
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("the announcement did not follow the seats");
+}
+
+#[test]
+fn test_resume() {
+    let players = [Color::Blue, Color::Green, Color::Red];
+    let mut host = Host::bind("127.0.0.1:0", &players, &[Color::Blue, Color::Red]).unwrap();
+    let address = host.address();
+    let joining = thread::spawn(move || Client::connect(address));
+    poll_until(1, || host.poll());
+    let client = joining.join().unwrap().unwrap();
+    let token = client.token().unwrap().to_owned();
+    host.play(mv("b2M")).unwrap();
+
+    drop(client);
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Paused(Color::Green)]);
+
+    // Strangers can't have the seat, its player gets the whole game back
+    let resuming = thread::spawn(move || Client::resume(address, "0123"));
+    while !resuming.is_finished() {
+        host.poll();
+        thread::sleep(Duration::from_millis(10));
+    }
+    let err = resuming.join().unwrap().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    let resuming = thread::spawn(move || Client::resume(address, &token));
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Resumed(Color::Green)]);
+    let mut client = resuming.join().unwrap().unwrap();
+    assert_eq!(client.color(), Some(Color::Green));
+    assert_eq!(client.game().board(), host.game().board());
+    assert_eq!(client.game().status(), Status::Playing(Color::Green));
+    assert!(host.paused_seats().is_empty());
+
+    // The old connection is gone, the new one plays
+    client.send_move(mv("a1S")).unwrap();
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Placed(Color::Green, mv("a1S"))]);
+}
+
+#[test]
+fn test_abandoned_seats() {
+    let players = [Color::Blue, Color::Green, Color::Red];
+    let mut host = Host::bind("127.0.0.1:0", &players, &[Color::Blue]).unwrap();
+    host.set_seat_timeout(Duration::from_millis(0), Abandoned::Forfeit);
+    let address = host.address();
+    let joining = thread::spawn(move || Client::connect(address));
+    poll_until(1, || host.poll());
+    let green = joining.join().unwrap().unwrap();
+    let joining = thread::spawn(move || Client::connect(address));
+    poll_until(1, || host.poll());
+    let mut red = joining.join().unwrap().unwrap();
+    let token = green.token().unwrap().to_owned();
+
+    drop(green);
+    assert_eq!(poll_until(2, || host.poll()),
+               vec![Event::Paused(Color::Green), Event::Forfeited(Color::Green)]);
+    // After the status that came with joining
+    let events = poll_until(4, || red.poll());
+    assert_eq!(&events[1..3], &[Event::Paused(Color::Green), Event::Forfeited(Color::Green)]);
+    assert!(!red.game().is_active(Color::Green));
+    host.play(mv("b2M")).unwrap();
+    assert_eq!(host.game().status(), Status::Playing(Color::Red));
+
+    // A late observer sees the game without green as well
+    let watching = thread::spawn(move || Client::watch(address));
+    poll_until(1, || host.poll());
+    let observer = watching.join().unwrap().unwrap();
+    assert_eq!(observer.game().status(), Status::Playing(Color::Red));
+    let resuming = thread::spawn(move || Client::resume(address, &token));
+    while !resuming.is_finished() {
+        host.poll();
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(resuming.join().unwrap().is_err());
+
+    // Red's seat goes to a bot instead, which moves on its own
+    host.set_seat_timeout(Duration::from_millis(0), Abandoned::Bot(Level::Easy));
+    drop(red);
+    // Polling doesn't wait for the bot to think
+    let events = poll_until(3, || {
+        let started = Instant::now();
+        let events = host.poll();
+        assert!(started.elapsed() < BOT_MOVE_TIME / 5);
+        events
+    });
+    assert_eq!(&events[..2], &[Event::Paused(Color::Red), Event::Replaced(Color::Red)]);
+    match events[2] {
+        Event::Placed(Color::Red, _) => {},
+        ref event => panic!("expected a move from the bot, got {:?}", event)
+    }
+    assert_eq!(host.game().status(), Status::Playing(Color::Blue));
+}
+
+#[test]
+fn test_resign() {
+    let players = [Color::Blue, Color::Green, Color::Red];
+    let mut host = Host::bind("127.0.0.1:0", &players, &[Color::Blue, Color::Red]).unwrap();
+    let address = host.address();
+    let joining = thread::spawn(move || Client::connect(address));
+    poll_until(1, || host.poll());
+    let mut green = joining.join().unwrap().unwrap();
+    host.play(mv("b2M")).unwrap();
+
+    // Resigning works out of turn, afterwards the player only watches
+    host.resign(Color::Red).unwrap();
+    green.resign().unwrap();
+    assert_eq!(poll_until(1, || host.poll()), vec![Event::Resigned(Color::Green)]);
+    let events = poll_until(7, || green.poll());
+    assert_eq!(&events[3..], &[Event::Resigned(Color::Red), Event::Status(Status::Playing(Color::Green)),
+                               Event::Resigned(Color::Green), Event::Status(Status::Won(Color::Blue))]);
+    assert_eq!(green.color(), None);
+    assert_eq!(host.game().resigned(), &[Color::Red, Color::Green]);
+
+    let watching = thread::spawn(move || Client::watch(address));
+    poll_until(1, || host.poll());
+    let observer = watching.join().unwrap().unwrap();
+    assert_eq!(observer.game().resigned(), host.game().resigned());
+    assert_eq!(observer.game().status(), Status::Won(Color::Blue));
+}
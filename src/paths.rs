@@ -0,0 +1,23 @@
+use std::env;
+use std::path::PathBuf;
+
+const APPLICATION_DIR: &'static str = "three";
+
+fn base_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+    env::var_os(variable)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+        .map(|dir| dir.join(APPLICATION_DIR))
+}
+
+// Where generated data such as the opening book lives, following the XDG
+// base directory specification
+pub fn data_dir() -> Option<PathBuf> {
+    base_dir("XDG_DATA_HOME", ".local/share")
+}
+
+// Where settings are remembered between runs
+pub fn config_dir() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config")
+}
@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use model::Color;
+use game::Status;
+use paths;
+
+const PROFILES_HEADER: &'static str = "# three player profiles";
+
+// Somebody who plays at this machine, with their results over all games
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Profile {
+    pub name: String,
+    // Shown next to the name in place of a picture
+    pub initial: char,
+    // The color they sit at unless told otherwise
+    pub color: Option<Color>,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32
+}
+
+impl Profile {
+    pub fn new(name: &str, color: Option<Color>) -> Profile {
+        Profile {
+            name: name.to_owned(),
+            initial: name.chars().next().map_or('?', |c| c.to_uppercase().next().unwrap_or(c)),
+            color: color,
+            wins: 0,
+            losses: 0,
+            draws: 0
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    pub fn record(&self) -> String {
+        format!("{} won, {} lost, {} drawn", self.wins, self.losses, self.draws)
+    }
+}
+
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct Profiles {
+    profiles: Vec<Profile>
+}
+
+impl Profiles {
+    pub fn new() -> Profiles {
+        Profiles {
+            profiles: vec![]
+        }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("profiles.txt"))
+    }
+
+    pub fn all(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Profile> {
+        self.profiles.iter_mut().find(|profile| profile.name == name)
+    }
+
+    // Whoever likes to play the color
+    pub fn preferring(&self, color: Color) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.color == Some(color))
+    }
+
+    pub fn add(&mut self, profile: Profile) -> Result<(), String> {
+        if profile.name.trim().is_empty() || profile.name.trim() != profile.name {
+            return Err(format!("\"{}\" can't be used as a name", profile.name));
+        }
+        if profile.initial.is_whitespace() {
+            return Err(format!("{} needs an initial", profile.name));
+        }
+        if self.get(&profile.name).is_some() {
+            return Err(format!("There is already a player called {}", profile.name));
+        }
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        let index = self.profiles.iter().position(|profile| profile.name == name)?;
+        Some(self.profiles.remove(index))
+    }
+
+    // Counts a finished game for the players who have a profile, given by
+    // the color they played
+    pub fn record_game(&mut self, players: &[(Color, String)], status: Status) {
+        for &(color, ref name) in players.iter() {
+            let profile = match self.get_mut(name) {
+                Some(profile) => profile,
+                None => continue
+            };
+            match status {
+                Status::Won(winner) if winner == color => profile.wins += 1,
+                Status::Won(_) => profile.losses += 1,
+                Status::Draw => profile.draws += 1,
+                Status::Playing(_) => {}
+            }
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Profiles> {
+        let file = File::open(path)?;
+        Profiles::read(BufReader::new(file))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Profiles> {
+        let mut profiles = Profiles::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.splitn(6, ' ').collect();
+            let profile = if fields.len() == 6 {
+                parse_profile(&fields)
+            } else {
+                None
+            };
+            let added = match profile {
+                Some(profile) => profiles.add(profile),
+                None => Err(format!("invalid profile: {}", line))
+            };
+            added.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+        Ok(profiles)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", PROFILES_HEADER)?;
+        writeln!(writer, "# initial color wins losses draws name")?;
+        for profile in self.profiles.iter() {
+            writeln!(writer, "{} {} {} {} {} {}",
+                     profile.initial, profile.color.map_or("-", |color| color.name()),
+                     profile.wins, profile.losses, profile.draws, profile.name)?;
+        }
+        writer.flush()
+    }
+}
+
+fn parse_profile(fields: &[&str]) -> Option<Profile> {
+    let mut initial = fields[0].chars();
+    let profile = Profile {
+        initial: initial.next()?,
+        color: match fields[1] {
+            "-" => None,
+            color => Some(color.parse().ok()?)
+        },
+        wins: fields[2].parse().ok()?,
+        losses: fields[3].parse().ok()?,
+        draws: fields[4].parse().ok()?,
+        name: fields[5].to_owned()
+    };
+    if initial.next().is_some() {
+        return None;
+    }
+    Some(profile)
+}
+
+// Tests
+
+#[test]
+fn test_profiles() {
+    let mut profiles = Profiles::new();
+    profiles.add(Profile::new("ann lee", Some(Color::Red))).unwrap();
+    profiles.add(Profile::new("Max", None)).unwrap();
+    assert_eq!(profiles.get("ann lee").unwrap().initial, 'A');
+    assert!(profiles.add(Profile::new("Max", Some(Color::Blue))).is_err());
+    assert!(profiles.add(Profile::new(" ", None)).is_err());
+    assert_eq!(profiles.preferring(Color::Red).map(|profile| &profile.name[..]), Some("ann lee"));
+
+    let players = vec![(Color::Red, "ann lee".to_owned()), (Color::Blue, "Max".to_owned()),
+                       (Color::Green, "Nobody".to_owned())];
+    profiles.record_game(&players, Status::Won(Color::Red));
+    profiles.record_game(&players, Status::Draw);
+    profiles.record_game(&players[1..], Status::Won(Color::Green));
+    assert_eq!(profiles.get("ann lee").unwrap().record(), "1 won, 0 lost, 1 drawn");
+    assert_eq!(profiles.get("Max").unwrap().record(), "0 won, 2 lost, 1 drawn");
+    assert_eq!(profiles.get("Max").unwrap().games(), 3);
+
+    let mut text = vec![];
+    profiles.write(&mut text).unwrap();
+    assert_eq!(Profiles::read(&text[..]).unwrap(), profiles);
+    assert!(Profiles::read(&b"AB red 0 0 0 Ann\n"[..]).is_err());
+    assert!(Profiles::read(&b"A red 0 0 Ann\n"[..]).is_err());
+
+    assert_eq!(profiles.remove("Max").map(|profile| profile.name), Some("Max".to_owned()));
+    assert!(profiles.get("Max").is_none());
+}
@@ -0,0 +1,409 @@
+use std::fmt;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
+use std::io::{self, BufRead, Write};
+
+use model::Color;
+use game::{Game, Move, Status};
+use engine::{Bot, Level, Knowledge};
+use random::Random;
+
+// A line based protocol for talking to engines over stdin and stdout, in
+// the spirit of UCI. The frontend sends commands and the engine answers:
+//
+//   three                      -> id name ..., threeok
+//   isready                    -> readyok
+//   setoption level hard
+//   newgame
+//   position players blue green moves b2M a1S
+//   position players blue green red moves b2M a1S forfeits green 1
+//   go movetime 1000           -> bestmove c3L (or bestmove none)
+//   result blue                   (or result draw)
+//   quit
+//
+// Errors are reported as info lines. After an invalid position go answers
+// bestmove none until the next valid position or newgame.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Command {
+    Three,
+    IsReady,
+    SetOption(String, String),
+    NewGame,
+    // Players, moves and the colors that dropped out with how many moves
+    // had been played when they did
+    Position(Vec<Color>, Vec<Move>, Vec<(Color, usize)>),
+    Go(Option<Duration>),
+    Result(Status),
+    Quit
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Response {
+    Id(String, String),
+    ThreeOk,
+    ReadyOk,
+    BestMove(Option<Move>),
+    Info(String)
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum ProtocolError {
+    Empty,
+    Unknown(String),
+    Invalid(String)
+}
+
+impl ProtocolError {
+    fn message(&self) -> &str {
+        match self {
+            &ProtocolError::Empty => "empty line",
+            &ProtocolError::Unknown(_) => "unknown command",
+            &ProtocolError::Invalid(_) => "invalid arguments"
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ProtocolError::Empty => f.write_str(self.message()),
+            &ProtocolError::Unknown(ref line) |
+            &ProtocolError::Invalid(ref line) => write!(f, "{}: {}", self.message(), line)
+        }
+    }
+}
+
+impl Error for ProtocolError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+fn invalid(line: &str) -> ProtocolError {
+    ProtocolError::Invalid(line.to_owned())
+}
+
+fn parse_position(line: &str, args: &[&str]) -> Result<Command, ProtocolError> {
+    let mut players = vec![];
+    let mut moves = vec![];
+    let mut forfeits = vec![];
+    let mut forfeited = None;
+    let mut section = None;
+    for arg in args {
+        match (*arg, section) {
+            ("players", _) | ("moves", _) | ("forfeits", _) if forfeited.is_none() =>
+                section = Some(*arg),
+            (color, Some("players")) => players.push(color.parse().map_err(|_| invalid(line))?),
+            (mv, Some("moves")) => moves.push(mv.parse().map_err(|_| invalid(line))?),
+            (ply, Some("forfeits")) => match forfeited.take() {
+                Some(color) => forfeits.push((color, ply.parse().map_err(|_| invalid(line))?)),
+                None => forfeited = Some(ply.parse().map_err(|_| invalid(line))?)
+            },
+            _ => return Err(invalid(line))
+        }
+    }
+    if forfeited.is_some() {
+        return Err(invalid(line));
+    }
+    if players.is_empty() {
+        players = vec![Color::Blue, Color::Green];
+    }
+    Ok(Command::Position(players, moves, forfeits))
+}
+
+impl FromStr for Command {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Command, ProtocolError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Err(ProtocolError::Empty)
+        };
+        match (name, args.len()) {
+            ("three", 0) => Ok(Command::Three),
+            ("isready", 0) => Ok(Command::IsReady),
+            ("setoption", 2) => Ok(Command::SetOption(args[0].to_owned(), args[1].to_owned())),
+            ("newgame", 0) => Ok(Command::NewGame),
+            ("position", _) => parse_position(line, args),
+            ("go", 0) => Ok(Command::Go(None)),
+            ("go", 2) if args[0] == "movetime" => {
+                let ms = args[1].parse().map_err(|_| invalid(line))?;
+                Ok(Command::Go(Some(Duration::from_millis(ms))))
+            },
+            ("result", 1) if args[0] == "draw" => Ok(Command::Result(Status::Draw)),
+            ("result", 1) => {
+                let color = args[0].parse().map_err(|_| invalid(line))?;
+                Ok(Command::Result(Status::Won(color)))
+            },
+            ("quit", 0) => Ok(Command::Quit),
+            ("three", _) | ("isready", _) | ("setoption", _) | ("newgame", _) |
+            ("go", _) | ("result", _) | ("quit", _) => Err(invalid(line)),
+            _ => Err(ProtocolError::Unknown(line.to_owned()))
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Command::Three => write!(f, "three"),
+            &Command::IsReady => write!(f, "isready"),
+            &Command::SetOption(ref name, ref value) => write!(f, "setoption {} {}", name, value),
+            &Command::NewGame => write!(f, "newgame"),
+            &Command::Position(ref players, ref moves, ref forfeits) => {
+                write!(f, "position players")?;
+                for color in players {
+                    write!(f, " {}", color.name())?;
+                }
+                if !moves.is_empty() {
+                    write!(f, " moves")?;
+                    for mv in moves {
+                        write!(f, " {}", mv)?;
+                    }
+                }
+                if !forfeits.is_empty() {
+                    write!(f, " forfeits")?;
+                    for &(color, ply) in forfeits {
+                        write!(f, " {} {}", color.name(), ply)?;
+                    }
+                }
+                Ok(())
+            },
+            &Command::Go(None) => write!(f, "go"),
+            &Command::Go(Some(limit)) => {
+                let ms = limit.as_secs() * 1000 + limit.subsec_nanos() as u64 / 1000000;
+                write!(f, "go movetime {}", ms)
+            },
+            &Command::Result(Status::Won(color)) => write!(f, "result {}", color.name()),
+            &Command::Result(_) => write!(f, "result draw"),
+            &Command::Quit => write!(f, "quit")
+        }
+    }
+}
+
+impl FromStr for Response {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Response, ProtocolError> {
+        let line = line.trim();
+        let (name, rest) = match line.find(' ') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => (line, "")
+        };
+        match name {
+            "id" => {
+                let (key, value) = match rest.find(' ') {
+                    Some(i) => (&rest[..i], rest[i + 1..].trim()),
+                    None => return Err(invalid(line))
+                };
+                Ok(Response::Id(key.to_owned(), value.to_owned()))
+            },
+            "threeok" if rest.is_empty() => Ok(Response::ThreeOk),
+            "readyok" if rest.is_empty() => Ok(Response::ReadyOk),
+            "bestmove" if rest == "none" => Ok(Response::BestMove(None)),
+            "bestmove" => rest.parse()
+                .map(|mv| Response::BestMove(Some(mv)))
+                .map_err(|_| invalid(line)),
+            "info" => Ok(Response::Info(rest.to_owned())),
+            "" => Err(ProtocolError::Empty),
+            _ => Err(ProtocolError::Unknown(line.to_owned()))
+        }
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Response::Id(ref key, ref value) => write!(f, "id {} {}", key, value),
+            &Response::ThreeOk => write!(f, "threeok"),
+            &Response::ReadyOk => write!(f, "readyok"),
+            &Response::BestMove(Some(mv)) => write!(f, "bestmove {}", mv),
+            &Response::BestMove(None) => write!(f, "bestmove none"),
+            &Response::Info(ref text) => write!(f, "info {}", text)
+        }
+    }
+}
+
+fn game_from(players: &[Color], moves: &[Move], forfeits: &[(Color, usize)])
+             -> Result<Game, String> {
+    if let Some(&(color, ply)) = forfeits.iter().find(|&&(_, ply)| ply > moves.len()) {
+        return Err(format!("{} can't drop out after move {}", color.name(), ply));
+    }
+    let mut game = Game::new(players);
+    for ply in 0..moves.len() + 1 {
+        for &(color, _) in forfeits.iter().filter(|&&(_, at)| at == ply) {
+            game.forfeit(color)
+                .map_err(|err| format!("{} can't drop out: {}", color.name(), err))?;
+        }
+        if let Some(mv) = moves.get(ply) {
+            game.place(*mv).map_err(|err| format!("{} is not playable: {}", mv, err))?;
+        }
+    }
+    Ok(game)
+}
+
+// Serves the protocol with the built-in bot until the input ends or the
+// frontend says quit
+pub fn run_engine<R: BufRead, W: Write>(input: R, mut output: W, knowledge: &Knowledge)
+                                        -> io::Result<()> {
+    let mut bot = Bot::with_knowledge(Level::Hard, Random::from_time(), knowledge);
+    // None after an invalid position, until the next valid one
+    let mut game = Some(Game::two_player());
+    let respond = |output: &mut W, response: Response| -> io::Result<()> {
+        writeln!(output, "{}", response)?;
+        output.flush()
+    };
+    for line in input.lines() {
+        let line = line?;
+        let command = match line.parse() {
+            Ok(command) => command,
+            Err(ProtocolError::Empty) => continue,
+            Err(err) => {
+                respond(&mut output, Response::Info(format!("error {}", err)))?;
+                continue;
+            }
+        };
+        match command {
+            Command::Three => {
+                let version = format!("three {}", env!("CARGO_PKG_VERSION"));
+                respond(&mut output, Response::Id("name".to_owned(), version))?;
+                respond(&mut output, Response::ThreeOk)?;
+            },
+            Command::IsReady => respond(&mut output, Response::ReadyOk)?,
+            Command::SetOption(ref name, ref value) if name == "level" => {
+                match value.parse() {
+                    Ok(level) => bot = Bot::with_knowledge(level, Random::from_time(), knowledge),
+                    Err(err) => respond(&mut output, Response::Info(format!("error {}", err)))?
+                }
+            },
+            Command::SetOption(name, _) => {
+                respond(&mut output, Response::Info(format!("error unknown option {}", name)))?
+            },
+            Command::NewGame => game = Some(Game::two_player()),
+            Command::Position(players, moves, forfeits) => {
+                game = match game_from(&players, &moves, &forfeits) {
+                    Ok(position) => Some(position),
+                    Err(err) => {
+                        respond(&mut output, Response::Info(format!("error {}", err)))?;
+                        None
+                    }
+                }
+            },
+            Command::Go(limit) => {
+                let mv = match (game.as_ref(), limit) {
+                    (Some(game), Some(limit)) => bot.choose_within(game, limit),
+                    (Some(game), None) => bot.choose(game),
+                    (None, _) => {
+                        respond(&mut output, Response::Info("error no valid position".to_owned()))?;
+                        None
+                    }
+                };
+                respond(&mut output, Response::BestMove(mv))?;
+            },
+            Command::Result(_) => {},
+            Command::Quit => break
+        }
+    }
+    Ok(())
+}
+
+// Tests
+
+#[test]
+fn test_command_round_trip() {
+    let commands = vec![
+        Command::Three,
+        Command::IsReady,
+        Command::SetOption("level".to_owned(), "easy".to_owned()),
+        Command::NewGame,
+        Command::Position(vec![Color::Red, Color::Blue, Color::Yellow],
+                          vec!["b2M".parse().unwrap(), "a1S".parse().unwrap()],
+                          vec![]),
+        Command::Position(vec![Color::Blue, Color::Green, Color::Red],
+                          vec!["b2M".parse().unwrap()],
+                          vec![(Color::Green, 1), (Color::Red, 1)]),
+        Command::Go(None),
+        Command::Go(Some(Duration::from_millis(1500))),
+        Command::Result(Status::Won(Color::Green)),
+        Command::Result(Status::Draw),
+        Command::Quit
+    ];
+    for command in commands {
+        assert_eq!(command.to_string().parse::<Command>(), Ok(command));
+    }
+    assert_eq!("position moves a1S".parse::<Command>(),
+               Ok(Command::Position(vec![Color::Blue, Color::Green],
+                                    vec!["a1S".parse().unwrap()],
+                                    vec![])));
+    assert!("position moves d4S".parse::<Command>().is_err());
+    assert!("position moves a1S forfeits green".parse::<Command>().is_err());
+    assert!("go movetime soon".parse::<Command>().is_err());
+    assert_eq!("castle".parse::<Command>(), Err(ProtocolError::Unknown("castle".to_owned())));
+}
+
+#[test]
+fn test_response_round_trip() {
+    let responses = vec![
+        Response::Id("name".to_owned(), "three 0.1.0".to_owned()),
+        Response::ThreeOk,
+        Response::ReadyOk,
+        Response::BestMove(Some("c3L".parse().unwrap())),
+        Response::BestMove(None),
+        Response::Info("depth 4".to_owned())
+    ];
+    for response in responses {
+        assert_eq!(response.to_string().parse::<Response>(), Ok(response));
+    }
+}
+
+#[test]
+fn test_run_engine() {
+    let input = b"three\nisready\nsetoption level medium\n\
+                  position moves a1S a2S b1S b2S\ngo movetime 100\n\
+                  position moves a1S a1S\ngo\nnewgame\ngo\nquit\ngo\n";
+    let mut output = vec![];
+    run_engine(&input[..], &mut output, &Knowledge::default()).unwrap();
+    let responses: Vec<Response> = String::from_utf8(output).unwrap()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect();
+    assert_eq!(responses.len(), 8);
+    assert_eq!(responses[1], Response::ThreeOk);
+    assert_eq!(responses[2], Response::ReadyOk);
+    assert_eq!(responses[3], Response::BestMove(Some("c1S".parse().unwrap())));
+    // No move for the position before the invalid one
+    for response in responses[4..6].iter() {
+        match *response {
+            Response::Info(ref text) => assert!(text.starts_with("error")),
+            ref other => panic!("expected an error, got {}", other)
+        }
+    }
+    assert_eq!(responses[6], Response::BestMove(None));
+    match responses[7] {
+        Response::BestMove(Some(_)) => {},
+        ref other => panic!("expected a move after newgame, got {}", other)
+    }
+}
+
+#[test]
+fn test_run_engine_forfeits() {
+    // With green out after the first move red is to move and wins the
+    // column, while without the forfeit it would be blue's turn
+    let input = b"position players blue green red moves b3S a1M c3L a2M c1L \
+                  forfeits green 1\ngo\n\
+                  position players blue green red moves b3S forfeits green 2\ngo\n";
+    let mut output = vec![];
+    run_engine(&input[..], &mut output, &Knowledge::default()).unwrap();
+    let responses: Vec<Response> = String::from_utf8(output).unwrap()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect();
+    assert_eq!(responses.len(), 4);
+    assert_eq!(responses[0], Response::BestMove(Some("a3M".parse().unwrap())));
+    match responses[1] {
+        Response::Info(ref text) => assert!(text.starts_with("error")),
+        ref other => panic!("expected an error, got {}", other)
+    }
+}
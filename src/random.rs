@@ -0,0 +1,54 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Small xorshift generator. Bots only need something cheap and seedable so
+// that games can be replayed, not anything cryptographically strong.
+#[derive(Debug,Clone)]
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    pub fn from_time() -> Random {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() ^ ((d.subsec_nanos() as u64) << 32))
+            .unwrap_or(0);
+        Random::new(now)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+// Tests
+
+#[test]
+fn test_random_is_repeatable() {
+    let mut a = Random::new(42);
+    let mut b = Random::new(42);
+    let mut c = Random::new(43);
+    let xs: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+    let ys: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+    let zs: Vec<u64> = (0..10).map(|_| c.next_u64()).collect();
+    assert_eq!(xs, ys);
+    assert!(xs != zs);
+    assert!((0..100).all(|_| a.below(3) < 3));
+}
@@ -0,0 +1,362 @@
+use std::fmt;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use model::Color;
+use game::{Game, Move, MoveError, Status};
+
+// Games are stored as text in the spirit of PGN: a few tags followed by the
+// moves in the order they were played.
+//
+//   [Players "blue green"]
+//   [Result "blue"]
+//
+//   b2M a1S c1S b1S a3L c3M a2S
+//
+// Players who dropped out are listed with the number of moves played when
+// they did, like [Forfeits "green 4 red 6"], and those of them who resigned
+// again, like [Resigned "red"]. Draws the players agreed to are marked with
+// [Draw "agreed"]. A file can hold several games, each one starting with its
+// tags.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Record {
+    pub players: Vec<Color>,
+    pub moves: Vec<Move>,
+    pub forfeits: Vec<(Color, usize)>,
+    pub resigned: Vec<Color>,
+    pub agreed_draw: bool,
+    // None while the game is still going
+    pub result: Option<Status>,
+    tags: Vec<(String, String)>
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum RecordError {
+    IllegalMove(usize, Move, MoveError),
+    WrongResult(Status, Status)
+}
+
+impl RecordError {
+    fn message(&self) -> &str {
+        match self {
+            &RecordError::IllegalMove(..) => "illegal move",
+            &RecordError::WrongResult(..) => "wrong result"
+        }
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &RecordError::IllegalMove(ply, mv, err) =>
+                write!(f, "{} {} at ply {}: {}", self.message(), mv, ply, err),
+            &RecordError::WrongResult(recorded, actual) =>
+                write!(f, "{}: recorded {} but the game ends in {}",
+                       self.message(), result_name(Some(recorded)), result_name(Some(actual)))
+        }
+    }
+}
+
+impl Error for RecordError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+pub fn result_name(result: Option<Status>) -> String {
+    match result {
+        Some(Status::Won(color)) => color.name().to_owned(),
+        Some(Status::Draw) => "draw".to_owned(),
+        _ => "*".to_owned()
+    }
+}
+
+fn parse_result(value: &str) -> Option<Option<Status>> {
+    match value {
+        "*" => Some(None),
+        "draw" => Some(Some(Status::Draw)),
+        color => color.parse().ok().map(|color| Some(Status::Won(color)))
+    }
+}
+
+fn invalid(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid record line: {}", line))
+}
+
+impl Record {
+    pub fn new(players: &[Color]) -> Record {
+        Record {
+            players: players.to_vec(),
+            moves: vec![],
+            forfeits: vec![],
+            resigned: vec![],
+            agreed_draw: false,
+            result: None,
+            tags: vec![]
+        }
+    }
+
+    pub fn from_game(game: &Game) -> Record {
+        let mut record = Record::new(game.players());
+        record.moves = game.history().iter().map(|placement| placement.mv).collect();
+        record.forfeits = game.forfeits().to_vec();
+        record.resigned = game.resigned().to_vec();
+        record.agreed_draw = game.is_agreed_draw();
+        if game.is_over() {
+            record.result = Some(game.status());
+        }
+        record
+    }
+
+    // Tags other than the players and the result
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|&&(ref key, _)| key == name)
+            .map(|&(_, ref value)| &value[..])
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter().position(|&(ref key, _)| key == name) {
+            Some(i) => self.tags[i].1 = value.to_owned(),
+            None => self.tags.push((name.to_owned(), value.to_owned()))
+        }
+    }
+
+    // Plays the moves through, checking each of them and the result
+    pub fn replay(&self) -> Result<Game, RecordError> {
+        let mut game = Game::new(&self.players);
+        for (ply, mv) in self.moves.iter().enumerate() {
+            self.forfeit(&mut game, ply);
+            game.place(*mv).map_err(|err| RecordError::IllegalMove(ply + 1, *mv, err))?;
+        }
+        self.forfeit(&mut game, self.moves.len());
+        if self.agreed_draw {
+            let _ = game.agree_draw();
+        }
+        match self.result {
+            Some(result) if result != game.status() =>
+                Err(RecordError::WrongResult(result, game.status())),
+            _ => Ok(game)
+        }
+    }
+
+    fn forfeit(&self, game: &mut Game, plies: usize) {
+        for &(color, _) in self.forfeits.iter().filter(|&&(_, ply)| ply == plies) {
+            let _ = if self.resigned.contains(&color) {
+                game.resign(color)
+            } else {
+                game.forfeit(color)
+            };
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Record>> {
+        let file = File::open(path)?;
+        Record::read(BufReader::new(file))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Vec<Record>> {
+        let mut records = vec![];
+        let mut current: Option<Record> = None;
+        let mut in_moves = false;
+        let mut has_players = false;
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                let (name, value) = parse_tag(line).ok_or_else(|| invalid(line))?;
+                // Tags after moves start the next game, and so do the
+                // players of games that ended before any move
+                if in_moves || current.is_none() || (name == "Players" && has_players) {
+                    records.extend(current.take());
+                    current = Some(Record::new(&[Color::Blue, Color::Green]));
+                    in_moves = false;
+                    has_players = false;
+                }
+                let record = current.as_mut().unwrap();
+                match name {
+                    "Players" => {
+                        let players = value.split_whitespace()
+                            .map(|color| color.parse())
+                            .collect::<Result<Vec<Color>, String>>()
+                            .map_err(|_| invalid(line))?;
+                        // Games can't be played without players or with a
+                        // color twice
+                        if players.is_empty() ||
+                            players.iter().enumerate().any(|(i, color)| players[..i].contains(color)) {
+                            return Err(invalid(line));
+                        }
+                        record.players = players;
+                        has_players = true;
+                    },
+                    "Result" => record.result = parse_result(value).ok_or_else(|| invalid(line))?,
+                    "Forfeits" => record.forfeits = parse_forfeits(value).ok_or_else(|| invalid(line))?,
+                    "Resigned" => {
+                        record.resigned = value.split_whitespace()
+                            .map(|color| color.parse())
+                            .collect::<Result<Vec<Color>, String>>()
+                            .map_err(|_| invalid(line))?;
+                    },
+                    "Draw" if value == "agreed" => record.agreed_draw = true,
+                    _ => record.set_tag(name, value)
+                }
+            } else {
+                let record = current.get_or_insert_with(|| Record::new(&[Color::Blue, Color::Green]));
+                for mv in line.split_whitespace() {
+                    record.moves.push(mv.parse().map_err(|_| invalid(line))?);
+                }
+                in_moves = true;
+            }
+        }
+        records.extend(current);
+        Ok(records)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let players: Vec<&str> = self.players.iter().map(|color| color.name()).collect();
+        writeln!(writer, "[Players \"{}\"]", players.join(" "))?;
+        writeln!(writer, "[Result \"{}\"]", result_name(self.result))?;
+        if !self.forfeits.is_empty() {
+            let forfeits: Vec<String> = self.forfeits.iter()
+                .map(|&(color, ply)| format!("{} {}", color.name(), ply))
+                .collect();
+            writeln!(writer, "[Forfeits \"{}\"]", forfeits.join(" "))?;
+        }
+        if !self.resigned.is_empty() {
+            let resigned: Vec<&str> = self.resigned.iter().map(|color| color.name()).collect();
+            writeln!(writer, "[Resigned \"{}\"]", resigned.join(" "))?;
+        }
+        if self.agreed_draw {
+            writeln!(writer, "[Draw \"agreed\"]")?;
+        }
+        for &(ref name, ref value) in self.tags.iter() {
+            writeln!(writer, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(writer)?;
+        for line in self.moves.chunks(12) {
+            let moves: Vec<String> = line.iter().map(|mv| mv.to_string()).collect();
+            writeln!(writer, "{}", moves.join(" "))?;
+        }
+        writer.flush()
+    }
+}
+
+fn parse_forfeits(value: &str) -> Option<Vec<(Color, usize)>> {
+    let words: Vec<&str> = value.split_whitespace().collect();
+    words.chunks(2)
+        .map(|pair| match pair {
+            &[color, ply] => Some((color.parse().ok()?, ply.parse().ok()?)),
+            _ => None
+        })
+        .collect()
+}
+
+fn parse_tag(line: &str) -> Option<(&str, &str)> {
+    if !line.ends_with(']') {
+        return None;
+    }
+    let inner = &line[1..line.len() - 1];
+    let space = inner.find(' ')?;
+    let (name, value) = (&inner[..space], inner[space..].trim());
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return None;
+    }
+    Some((name, &value[1..value.len() - 1]))
+}
+
+// Tests
+
+#[cfg(test)]
+fn parse_moves(moves: &[&str]) -> Vec<Move> {
+    moves.iter().map(|mv| mv.parse().unwrap()).collect()
+}
+
+#[test]
+fn test_record_round_trip() {
+    let mut game = Game::new(&[Color::Red, Color::Yellow, Color::Blue]);
+    for mv in parse_moves(&["a1S", "b2M", "c3L", "a2S"]) {
+        game.place(mv).unwrap();
+    }
+    let mut record = Record::from_game(&game);
+    record.set_tag("Event", "test game");
+    let mut buffer = vec![];
+    record.write(&mut buffer).unwrap();
+    record.write(&mut buffer).unwrap();
+    let loaded = Record::read(&buffer[..]).unwrap();
+    assert_eq!(loaded, vec![record.clone(), record.clone()]);
+    assert_eq!(loaded[0].tag("Event"), Some("test game"));
+    assert_eq!(loaded[0].replay().unwrap().board(), game.board());
+    assert!(Record::read(&b"[Players \"blue purple\"]"[..]).is_err());
+
+    // Games that ended before anybody moved
+    let mut resigned = Game::two_player();
+    resigned.resign(Color::Blue).unwrap();
+    let mut drawn = Game::two_player();
+    drawn.agree_draw().unwrap();
+    let mut buffer = vec![];
+    let records = vec![Record::from_game(&resigned), Record::from_game(&drawn), record.clone()];
+    for record in records.iter() {
+        record.write(&mut buffer).unwrap();
+    }
+    assert_eq!(Record::read(&buffer[..]).unwrap(), records);
+    assert!(Record::read(&b"a1S d1S"[..]).is_err());
+}
+
+#[test]
+fn test_record_validation() {
+    let mut record = Record::new(&[Color::Blue, Color::Green]);
+    record.moves = parse_moves(&["a1S", "a2S", "b1S", "b2S", "c1S"]);
+    record.result = Some(Status::Won(Color::Blue));
+    assert!(record.replay().unwrap().is_over());
+    record.result = Some(Status::Won(Color::Green));
+    assert_eq!(record.replay().unwrap_err(),
+               RecordError::WrongResult(Status::Won(Color::Green), Status::Won(Color::Blue)));
+    record.result = None;
+    record.moves[3] = "a1S".parse().unwrap();
+    assert_eq!(record.replay().unwrap_err(),
+               RecordError::IllegalMove(4, "a1S".parse().unwrap(), MoveError::Occupied));
+
+    assert!(Record::read(&b"[Players \"\"]\n\nb2M\n"[..]).is_err());
+    assert!(Record::read(&b"[Players \"blue green blue\"]\n"[..]).is_err());
+    assert!(Record::read(&b"[Players \"blue\"]\n"[..]).is_ok());
+}
+
+#[test]
+fn test_record_forfeits() {
+    let mut game = Game::new(&[Color::Blue, Color::Green, Color::Red]);
+    game.place("b2M".parse().unwrap()).unwrap();
+    game.forfeit(Color::Green).unwrap();
+    game.place("a1S".parse().unwrap()).unwrap();
+    game.resign(Color::Blue).unwrap();
+    let record = Record::from_game(&game);
+    assert_eq!(record.result, Some(Status::Won(Color::Red)));
+    let mut buffer = vec![];
+    record.write(&mut buffer).unwrap();
+    let text = String::from_utf8_lossy(&buffer).into_owned();
+    assert!(text.contains("[Forfeits \"green 1 blue 2\"]"));
+    assert!(text.contains("[Resigned \"blue\"]"));
+    let loaded = Record::read(&buffer[..]).unwrap();
+    assert_eq!(loaded, vec![record]);
+    assert_eq!(loaded[0].replay().unwrap().resigned(), &[Color::Blue]);
+    assert_eq!(loaded[0].replay().unwrap().status(), Status::Won(Color::Red));
+    assert!(Record::read(&b"[Forfeits \"green\"]"[..]).is_err());
+
+    let mut game = Game::two_player();
+    game.place("b2M".parse().unwrap()).unwrap();
+    game.agree_draw().unwrap();
+    let mut record = Record::from_game(&game);
+    assert_eq!(record.result, Some(Status::Draw));
+    assert!(record.replay().unwrap().is_agreed_draw());
+    record.agreed_draw = false;
+    assert!(record.replay().is_err());
+}
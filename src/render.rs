@@ -0,0 +1,306 @@
+use std::f64;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use cairo::{Context, Format, ImageSurface, IoError};
+
+use model::{Board, Color, Ring, Location, Pattern, index_to_location};
+use game::{Game, Move};
+
+pub type RGB = (f64, f64, f64);
+
+pub struct RingColor {
+    pub stroke: RGB,
+    pub fill: RGB
+}
+
+impl From<Option<Color>> for RingColor {
+    fn from(cell: Option<Color>) -> RingColor {
+        match cell {
+            Some(Color::Blue) => RingColor {
+                stroke: (0., 0., 0.),
+                fill: (0.118, 0.565, 1.000)
+            },
+            Some(Color::Green) => RingColor {
+                stroke: (0., 0., 0.),
+                fill: (0.196, 0.804, 0.196)
+            },
+            Some(Color::Red) => RingColor {
+                stroke: (0., 0., 0.),
+                fill: (0.698, 0.133, 0.133)
+            },
+            Some(Color::Yellow) => RingColor {
+                stroke: (0., 0., 0.),
+                fill: (0.957, 0.643, 0.376) 
+            },
+            None => RingColor {
+                stroke: (0., 0., 0.),
+                fill: (1., 1., 1.)
+            }
+        }
+    }
+}
+
+// Draws a ring centered in a cell of the given size
+pub fn draw_ring(ctx: &Context,
+                 width: f64,
+                 height: f64,
+                 ring: Ring,
+                 color: RingColor) {
+    let (x, y) = (width / 2.0, height / 2.0);
+    let radius = ring.radius(width, height);
+    ctx.save();
+    ctx.set_line_width(2.0);
+    let (r, g, b) = color.stroke;
+    ctx.set_source_rgb(r, g, b);
+    ctx.translate(x, y);
+    ctx.arc(0., 0., radius, 0., 2. * f64::consts::PI);
+    ctx.stroke_preserve();
+    let (r, g, b) = color.fill;
+    ctx.set_source_rgb(r, g, b);
+    ctx.fill();
+    ctx.restore();
+}
+
+// What a frame marks on top of the board: the rings that won the game and
+// the ring placed last, which is drawn over them
+#[derive(Debug,Clone,Copy,Default)]
+pub struct Marks {
+    pub last: Option<Move>,
+    pub pattern: Option<Pattern>
+}
+
+impl Marks {
+    // Marks the last placement of a game and how it was won
+    pub fn from_game(game: &Game) -> Marks {
+        Marks {
+            last: game.history().last().map(|placement| placement.mv),
+            pattern: game.board().winning_pattern()
+        }
+    }
+}
+
+const LAST_COLOR: RGB = (1., 0.843, 0.);
+const LAST_WIDTH: f64 = 5.;
+const PATTERN_COLOR: RGB = (0., 0., 0.);
+const PATTERN_WIDTH: f64 = 6.;
+
+fn center((x, y): Location, cell_size: f64) -> (f64, f64) {
+    ((x as f64 + 0.5) * cell_size, (y as f64 + 0.5) * cell_size)
+}
+
+// Draws the board the way the window shows it, each cell being a square of
+// the given size. The context can be on any surface.
+pub fn draw_board(ctx: &Context, board: &Board, marks: &Marks, cell_size: f64) {
+    ctx.set_source_rgb(1., 1., 1.);
+    ctx.paint();
+    for i in 0..9 {
+        let (x, y) = index_to_location(3, i);
+        ctx.save();
+        ctx.translate(x as f64 * cell_size, y as f64 * cell_size);
+        for ring in [Ring::Bottom, Ring::Middle, Ring::Top].iter() {
+            draw_ring(ctx, cell_size, cell_size, *ring, board.get_ring((x, y), *ring).into());
+        }
+        ctx.restore();
+    }
+
+    let outline = |location: Location, ring: Ring, (r, g, b): RGB, width: f64| {
+        let (x, y) = center(location, cell_size);
+        ctx.save();
+        ctx.set_line_width(width);
+        ctx.set_source_rgb(r, g, b);
+        ctx.arc(x, y, ring.radius(cell_size, cell_size), 0., 2. * f64::consts::PI);
+        ctx.stroke();
+        ctx.restore();
+    };
+    if let Some(pattern) = marks.pattern {
+        for &(location, ring) in pattern.rings.iter() {
+            outline(location, ring, PATTERN_COLOR, PATTERN_WIDTH);
+        }
+        let (r, g, b) = PATTERN_COLOR;
+        let (x, y) = center(pattern.rings[0].0, cell_size);
+        let (end_x, end_y) = center(pattern.rings[2].0, cell_size);
+        ctx.save();
+        ctx.set_line_width(PATTERN_WIDTH);
+        ctx.set_source_rgb(r, g, b);
+        ctx.move_to(x, y);
+        ctx.line_to(end_x, end_y);
+        ctx.stroke();
+        ctx.restore();
+    }
+    if let Some(mv) = marks.last {
+        outline(mv.location, mv.ring, LAST_COLOR, LAST_WIDTH);
+    }
+}
+
+// Renders the board as a PNG image, this works without a display
+pub fn write_png<W: Write>(board: &Board, marks: &Marks, size: i32, writer: W) -> io::Result<()> {
+    let surface = ImageSurface::create(Format::Rgb24, size, size);
+    {
+        let ctx = Context::new(&surface);
+        draw_board(&ctx, board, marks, size as f64 / 3.);
+    }
+    surface.write_to_png(writer).map_err(|err| match err {
+        IoError::Io(err) => err,
+        IoError::Cairo(status) => io::Error::new(io::ErrorKind::Other, format!("{:?}", status))
+    })
+}
+
+fn svg_color((r, g, b): RGB) -> String {
+    format!("#{:02x}{:02x}{:02x}", (r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8)
+}
+
+fn svg_header<W: Write>(writer: &mut W, size: i32) -> io::Result<()> {
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" \
+                      viewBox=\"0 0 {0} {0}\">", size)
+}
+
+fn svg_outline<W: Write>(writer: &mut W, location: Location, ring: Ring, color: RGB,
+                         width: f64, cell_size: f64) -> io::Result<()> {
+    let (x, y) = center(location, cell_size);
+    writeln!(writer, "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"none\" \
+                      stroke=\"{}\" stroke-width=\"{}\"/>",
+             x, y, ring.radius(cell_size, cell_size), svg_color(color), width)
+}
+
+// The board as SVG elements, drawn the same way as with cairo
+fn svg_board<W: Write>(writer: &mut W, board: &Board, marks: &Marks, size: i32) -> io::Result<()> {
+    let cell_size = size as f64 / 3.;
+    writeln!(writer, "  <rect width=\"{0}\" height=\"{0}\" fill=\"#ffffff\"/>", size)?;
+    for i in 0..9 {
+        let location = index_to_location(3, i);
+        let (x, y) = center(location, cell_size);
+        for ring in [Ring::Bottom, Ring::Middle, Ring::Top].iter() {
+            let color = RingColor::from(board.get_ring(location, *ring));
+            writeln!(writer, "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" \
+                              stroke=\"{}\" stroke-width=\"2\"/>",
+                     x, y, ring.radius(cell_size, cell_size),
+                     svg_color(color.fill), svg_color(color.stroke))?;
+        }
+    }
+    if let Some(pattern) = marks.pattern {
+        for &(location, ring) in pattern.rings.iter() {
+            svg_outline(writer, location, ring, PATTERN_COLOR, PATTERN_WIDTH, cell_size)?;
+        }
+        let (x, y) = center(pattern.rings[0].0, cell_size);
+        let (end_x, end_y) = center(pattern.rings[2].0, cell_size);
+        writeln!(writer, "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" \
+                          stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\"/>",
+                 x, y, end_x, end_y, svg_color(PATTERN_COLOR), PATTERN_WIDTH)?;
+    }
+    if let Some(mv) = marks.last {
+        svg_outline(writer, mv.location, mv.ring, LAST_COLOR, LAST_WIDTH, cell_size)?;
+    }
+    Ok(())
+}
+
+// The same picture as an SVG document. Our cairo bindings have no SVG
+// surface but the board is only circles, so it is written by hand.
+pub fn write_svg<W: Write>(board: &Board, marks: &Marks, size: i32, mut writer: W) -> io::Result<()> {
+    svg_header(&mut writer, size)?;
+    svg_board(&mut writer, board, marks, size)?;
+    writeln!(writer, "</svg>")?;
+    writer.flush()
+}
+
+fn is_svg(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("svg"))
+}
+
+// Picks the format from the extension, PNG unless the file ends in .svg
+pub fn save<P: AsRef<Path>>(board: &Board, marks: &Marks, size: i32, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let writer = BufWriter::new(File::create(path)?);
+    if is_svg(path) {
+        write_svg(board, marks, size, writer)
+    } else {
+        write_png(board, marks, size, writer)
+    }
+}
+
+// One frame for the empty board and one for every placement, the last one
+// showing the winning pattern. Players drop out when they did in the game so
+// that the turns come out the same.
+pub fn frames(game: &Game) -> Vec<(Board, Marks)> {
+    let mut replay = Game::new(game.players());
+    let mut frames = vec![(replay.board().clone(), Marks::default())];
+    for (ply, placement) in game.history().iter().enumerate() {
+        drop_out(game, &mut replay, ply);
+        replay.place(placement.mv).unwrap();
+        frames.push((replay.board().clone(), Marks::from_game(&replay)));
+    }
+    frames
+}
+
+fn drop_out(game: &Game, replay: &mut Game, plies: usize) {
+    for &(color, _) in game.forfeits().iter().filter(|&&(_, ply)| ply == plies) {
+        let _ = if game.resigned().contains(&color) {
+            replay.resign(color)
+        } else {
+            replay.forfeit(color)
+        };
+    }
+}
+
+// Shows the frames one after another using SVG animation, the last one
+// staying on screen
+pub fn write_animated_svg<W: Write>(frames: &[(Board, Marks)], size: i32, delay: Duration,
+                                    mut writer: W) -> io::Result<()> {
+    let delay = delay.as_secs() as f64 + delay.subsec_nanos() as f64 / 1e9;
+    svg_header(&mut writer, size)?;
+    for (n, &(ref board, ref marks)) in frames.iter().enumerate() {
+        writeln!(writer, "<g visibility=\"hidden\">")?;
+        let duration = if n + 1 == frames.len() {
+            "fill=\"freeze\"".to_owned()
+        } else {
+            format!("dur=\"{:.3}s\"", delay)
+        };
+        writeln!(writer, "  <set attributeName=\"visibility\" to=\"visible\" begin=\"{:.3}s\" {}/>",
+                 n as f64 * delay, duration)?;
+        svg_board(&mut writer, board, marks, size)?;
+        writeln!(writer, "</g>")?;
+    }
+    writeln!(writer, "</svg>")?;
+    writer.flush()
+}
+
+// Writes an animated SVG when the output ends in .svg and otherwise a
+// directory of numbered PNG frames
+pub fn save_animation<P: AsRef<Path>>(game: &Game, size: i32, delay: Duration,
+                                      output: P) -> io::Result<()> {
+    let output = output.as_ref();
+    let frames = frames(game);
+    if is_svg(output) {
+        return write_animated_svg(&frames, size, delay, BufWriter::new(File::create(output)?));
+    }
+    fs::create_dir_all(output)?;
+    for (n, &(ref board, ref marks)) in frames.iter().enumerate() {
+        save(board, marks, size, output.join(format!("frame-{:03}.png", n)))?;
+    }
+    Ok(())
+}
+
+// Tests
+
+#[test]
+fn test_frames_with_forfeits() {
+    use game::Status;
+
+    let mut game = Game::new(&[Color::Blue, Color::Green, Color::Red]);
+    game.place("a1S".parse().unwrap()).unwrap();
+    game.forfeit(Color::Green).unwrap();
+    game.place("b2M".parse().unwrap()).unwrap();
+    game.place("c3L".parse().unwrap()).unwrap();
+    game.resign(Color::Red).unwrap();
+    assert_eq!(game.status(), Status::Won(Color::Blue));
+
+    let frames = frames(&game);
+    assert_eq!(frames.len(), 4);
+    let board = &frames[3].0;
+    assert_eq!(board.get_ring((1, 1), Ring::Middle), Some(Color::Red));
+    assert_eq!(board.get_ring((2, 2), Ring::Bottom), Some(Color::Blue));
+    assert_eq!(frames[3].1.last, Some("c3L".parse().unwrap()));
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+use std::str::FromStr;
+
+use model::Color;
+use game::Status;
+use random::Random;
+
+// Who moves first, written as a color, "rotate" or "random" with an
+// optional seed like "random:42"
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Start {
+    Color(Color),
+    // Drawn for every game from the seed, so the same seed gives the same
+    // order of games again
+    Random(u64),
+    // Everyone in turn, beginning with the first player
+    Rotate
+}
+
+impl Start {
+    // The seating for a game: the players in their usual order, but
+    // beginning with whoever moves first
+    pub fn players(&self, players: &[Color], game: usize) -> Vec<Color> {
+        let first = match self {
+            &Start::Color(color) => players.iter().position(|&c| c == color).unwrap_or(0),
+            &Start::Random(seed) => Random::new(seed.wrapping_add(game as u64)).below(players.len()),
+            &Start::Rotate => game % players.len()
+        };
+        players[first..].iter().chain(players[..first].iter()).cloned().collect()
+    }
+}
+
+impl fmt::Display for Start {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Start::Color(color) => f.write_str(color.name()),
+            &Start::Random(seed) => write!(f, "random:{}", seed),
+            &Start::Rotate => f.write_str("rotate")
+        }
+    }
+}
+
+impl FromStr for Start {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Start, String> {
+        match s {
+            "rotate" => Ok(Start::Rotate),
+            "random" => Ok(Start::Random(Random::from_time().next_u64())),
+            s if s.starts_with("random:") => s[7..].parse()
+                .map(Start::Random)
+                .map_err(|_| format!("invalid seed \"{}\"", &s[7..])),
+            s => s.parse().map(Start::Color)
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Length {
+    // At most this many games, ending early once the leader can't be caught
+    BestOf(usize),
+    // Until somebody has won this many games
+    FirstTo(usize)
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Length::BestOf(games) => write!(f, "best of {}", games),
+            &Length::FirstTo(wins) => write!(f, "first to {}", wins)
+        }
+    }
+}
+
+// A match of several games between the same players. Usually the color that
+// moves first rotates from game to game so that nobody keeps the advantage.
+#[derive(Debug,Clone)]
+pub struct Series {
+    length: Length,
+    players: Vec<Color>,
+    start: Start,
+    // Who moved first in every game played so far and how it ended
+    games: Vec<(Color, Status)>
+}
+
+impl Series {
+    pub fn new(length: Length, players: &[Color], start: Start) -> Series {
+        assert!(!players.is_empty(), "a match needs at least one player");
+        Series {
+            length: length,
+            players: players.to_vec(),
+            start: start,
+            games: vec![]
+        }
+    }
+
+    pub fn length(&self) -> Length {
+        self.length
+    }
+
+    pub fn games(&self) -> &[(Color, Status)] {
+        &self.games
+    }
+
+    pub fn start(&self) -> Start {
+        self.start
+    }
+
+    pub fn next_players(&self) -> Vec<Color> {
+        self.start.players(&self.players, self.games.len())
+    }
+
+    pub fn add(&mut self, first: Color, result: Status) {
+        self.games.push((first, result));
+    }
+
+    pub fn wins(&self, color: Color) -> usize {
+        self.games.iter().filter(|&&(_, result)| result == Status::Won(color)).count()
+    }
+
+    pub fn draws(&self) -> usize {
+        self.games.iter().filter(|&&(_, result)| result == Status::Draw).count()
+    }
+
+    // The most wins and who else has as many
+    fn leaders(&self) -> (usize, Vec<Color>) {
+        let most = self.players.iter().map(|&color| self.wins(color)).max().unwrap_or(0);
+        (most, self.players.iter().cloned().filter(|&color| self.wins(color) == most).collect())
+    }
+
+    pub fn is_over(&self) -> bool {
+        let (most, leaders) = self.leaders();
+        match self.length {
+            Length::BestOf(games) => {
+                let left = games.saturating_sub(self.games.len());
+                let chasing = self.players.iter()
+                    .filter(|color| !leaders.contains(color))
+                    .map(|&color| self.wins(color))
+                    .max()
+                    .unwrap_or(0);
+                left == 0 || (leaders.len() == 1 && most > chasing + left)
+            },
+            Length::FirstTo(wins) => most >= wins
+        }
+    }
+
+    // None until the match is over, and after it when it ended level
+    pub fn winner(&self) -> Option<Color> {
+        let (_, leaders) = self.leaders();
+        if self.is_over() && leaders.len() == 1 {
+            Some(leaders[0])
+        } else {
+            None
+        }
+    }
+
+    pub fn scoreboard(&self) -> String {
+        let mut scores: Vec<String> = self.players.iter()
+            .map(|&color| format!("{:?} {}", color, self.wins(color)))
+            .collect();
+        if self.draws() > 0 {
+            scores.push(format!("draws {}", self.draws()));
+        }
+        format!("Game {}, {}: {}", self.games.len() + 1, self.length, scores.join("  "))
+    }
+
+    pub fn summary(&self) -> String {
+        let mut lines = vec![match self.winner() {
+            Some(color) => format!("{:?} wins the match!", color),
+            None => "The match is drawn!".to_owned()
+        }];
+        lines.push(String::new());
+        for (i, &(first, result)) in self.games.iter().enumerate() {
+            let result = match result {
+                Status::Won(color) => format!("{:?} won", color),
+                _ => "draw".to_owned()
+            };
+            lines.push(format!("Game {}: {:?} started, {}", i + 1, first, result));
+        }
+        lines.push(String::new());
+        for &color in self.players.iter() {
+            lines.push(format!("{:?}: {} won", color, self.wins(color)));
+        }
+        if self.draws() > 0 {
+            lines.push(format!("Draws: {}", self.draws()));
+        }
+        lines.join("\n")
+    }
+}
+
+// Tests
+
+#[test]
+fn test_series_rotation() {
+    let mut series = Series::new(Length::BestOf(3), &[Color::Blue, Color::Green, Color::Red], Start::Rotate);
+    assert_eq!(series.next_players(), vec![Color::Blue, Color::Green, Color::Red]);
+    series.add(Color::Blue, Status::Won(Color::Blue));
+    assert_eq!(series.next_players(), vec![Color::Green, Color::Red, Color::Blue]);
+    series.add(Color::Green, Status::Draw);
+    assert_eq!(series.next_players(), vec![Color::Red, Color::Blue, Color::Green]);
+    assert!(!series.is_over());
+    assert_eq!(series.scoreboard(), "Game 3, best of 3: Blue 1  Green 0  Red 0  draws 1");
+}
+
+#[test]
+fn test_start() {
+    let players = [Color::Blue, Color::Green, Color::Red];
+    assert_eq!(Start::Color(Color::Red).players(&players, 5), vec![Color::Red, Color::Blue, Color::Green]);
+    assert_eq!(Start::Rotate.players(&players, 4), vec![Color::Green, Color::Red, Color::Blue]);
+    let random = Start::Random(7);
+    let firsts: Vec<Color> = (0..30).map(|game| random.players(&players, game)[0]).collect();
+    assert!(players.iter().all(|color| firsts.contains(color)));
+    assert_eq!(random.players(&players, 3), Start::Random(7).players(&players, 3));
+
+    for text in &["green", "rotate", "random:12"] {
+        assert_eq!(text.parse::<Start>().unwrap().to_string(), *text);
+    }
+    match "random".parse() {
+        Ok(Start::Random(_)) => {},
+        start => panic!("expected a random start, got {:?}", start)
+    }
+    assert!("random:x".parse::<Start>().is_err());
+    assert!("purple".parse::<Start>().is_err());
+
+    let series = Series::new(Length::BestOf(3), &players, Start::Color(Color::Green));
+    assert_eq!(series.next_players()[0], Color::Green);
+}
+
+#[test]
+fn test_series_end() {
+    let players = [Color::Blue, Color::Green];
+    let mut series = Series::new(Length::BestOf(5), &players, Start::Rotate);
+    for _ in 0..2 {
+        series.add(Color::Blue, Status::Won(Color::Green));
+    }
+    assert!(!series.is_over());
+    series.add(Color::Blue, Status::Won(Color::Green));
+    // Blue can't catch up in the last two games
+    assert!(series.is_over());
+    assert_eq!(series.winner(), Some(Color::Green));
+
+    let mut series = Series::new(Length::BestOf(2), &players, Start::Rotate);
+    series.add(Color::Blue, Status::Won(Color::Blue));
+    series.add(Color::Green, Status::Won(Color::Green));
+    assert!(series.is_over());
+    assert_eq!(series.winner(), None);
+    assert!(series.summary().starts_with("The match is drawn!"));
+
+    let mut series = Series::new(Length::FirstTo(2), &players, Start::Rotate);
+    series.add(Color::Blue, Status::Won(Color::Blue));
+    series.add(Color::Green, Status::Draw);
+    series.add(Color::Blue, Status::Won(Color::Green));
+    assert!(!series.is_over());
+    series.add(Color::Green, Status::Won(Color::Blue));
+    assert_eq!(series.winner(), Some(Color::Blue));
+    assert!(series.summary().contains("Game 2: Green started, draw"));
+}
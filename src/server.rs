@@ -0,0 +1,500 @@
+use std::io::{self, BufReader};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+use model::{Color, Ring, RINGS, index_to_location};
+use game::{Game, Move, Status};
+use random::Random;
+use json::Json;
+use http::{self, Request, Frame};
+
+pub const DEFAULT_HTTP_PORT: u16 = 7380;
+
+// Clients that don't finish their request in this time are dropped, and so
+// are WebSocket clients that stop reading what is sent to them
+const REQUEST_TIME: Duration = Duration::from_secs(10);
+
+// Frames waiting to be sent to a WebSocket client. Clients further behind
+// than this stop getting pushed states.
+const SUBSCRIBER_BACKLOG: usize = 16;
+
+// Games for other programs to drive over HTTP, with JSON in both directions:
+//
+//   GET  /games                         -> [{"id":1,"status":"playing",...}]
+//   POST /games {"players":["blue","green","red"]}
+//                                       -> 201 state of the new game
+//   GET  /games/1                       -> state
+//   POST /games/1/seats {"color":"green"}
+//                                       -> 201 {"color":"green","token":"5f0e...","game":state}
+//   POST /games/1/moves {"token":"5f0e...","move":"b2M"}
+//                                       -> state
+//   GET  /games/1/stream                -> WebSocket sending the state as text
+//                                          after every change
+//
+// Bodies and the color in a seat request may be left out, for two players
+// and the first open seat. Errors come back as {"error":"..."} with a 4xx
+// status. The state lists the players, the open seats, the status, the
+// rings on the board by location and size, the rings each color has left
+// and the moves so far.
+pub struct Server {
+    listener: TcpListener,
+    games: Arc<Mutex<Games>>
+}
+
+struct Hosted {
+    game: Game,
+    // Colors taken so far with the tokens that play them
+    seats: Vec<(Color, String)>,
+    // Every WebSocket following the game has a thread of its own sending
+    // the frames, so that nothing is written with the games locked
+    subscribers: Vec<SyncSender<Frame>>
+}
+
+struct Games {
+    hosted: Vec<Hosted>,
+    random: Random
+}
+
+// Failed requests are answered with their status and the message
+struct ApiError(u16, String);
+
+fn api_error<T>(status: u16, message: &str) -> Result<T, ApiError> {
+    Err(ApiError(status, message.to_owned()))
+}
+
+fn ring_name(ring: Ring) -> &'static str {
+    match ring {
+        Ring::Top => "small",
+        Ring::Middle => "medium",
+        Ring::Bottom => "large"
+    }
+}
+
+fn color_json(color: Option<Color>) -> Json {
+    color.map_or(Json::Null, |color| Json::string(color.name()))
+}
+
+fn colors_json(colors: &[Color]) -> Json {
+    Json::Array(colors.iter().map(|color| Json::string(color.name())).collect())
+}
+
+impl Hosted {
+    fn open_seats(&self) -> Vec<Color> {
+        self.game.players().iter()
+            .cloned()
+            .filter(|&color| !self.seats.iter().any(|&(c, _)| c == color))
+            .collect()
+    }
+
+    fn state(&self, id: usize) -> Json {
+        let game = &self.game;
+        let (status, turn, winner) = match game.status() {
+            Status::Playing(color) => ("playing", Some(color), None),
+            Status::Won(color) => ("won", None, Some(color)),
+            Status::Draw => ("draw", None, None)
+        };
+        let board = (0..9).map(|i| {
+            let location = index_to_location(3, i);
+            let (x, y) = location;
+            let rings = RINGS.iter()
+                .map(|&ring| (ring_name(ring), color_json(game.board().get_ring(location, ring))))
+                .collect();
+            (format!("{}{}", (b'a' + x as u8) as char, y + 1), Json::object(rings))
+        }).collect();
+        let supplies = game.players().iter().map(|&color| {
+            let supply = game.supply(color);
+            let rings = RINGS.iter()
+                .map(|&ring| (ring_name(ring), Json::from(supply.remaining(ring) as usize)))
+                .collect();
+            (color.name().to_owned(), Json::object(rings))
+        }).collect();
+        let moves = game.history().iter()
+            .map(|placement| Json::String(placement.mv.to_string()))
+            .collect();
+        Json::object(vec![
+            ("id", Json::from(id)),
+            ("players", colors_json(game.players())),
+            ("open_seats", colors_json(&self.open_seats())),
+            ("status", Json::string(status)),
+            ("turn", color_json(turn)),
+            ("winner", color_json(winner)),
+            ("board", Json::Object(board)),
+            ("supplies", Json::Object(supplies)),
+            ("moves", Json::Array(moves))
+        ])
+    }
+
+    // Pushes the state to every WebSocket following the game and forgets
+    // the ones that have gone away or fallen behind
+    fn notify(&mut self, id: usize) {
+        let frame = Frame::Text(self.state(id).to_string());
+        self.subscribers.retain(|frames| frames.try_send(frame.clone()).is_ok());
+    }
+}
+
+impl Games {
+    fn get(&mut self, id: &str) -> Result<(usize, &mut Hosted), ApiError> {
+        match id.parse::<usize>() {
+            Ok(id) if id >= 1 && id <= self.hosted.len() => Ok((id, &mut self.hosted[id - 1])),
+            _ => api_error(404, "no such game")
+        }
+    }
+
+    fn new_token(&mut self) -> String {
+        format!("{:016x}{:016x}", self.random.next_u64(), self.random.next_u64())
+    }
+}
+
+fn parse_body(request: &Request) -> Result<Json, ApiError> {
+    if request.body.trim().is_empty() {
+        return Ok(Json::Object(vec![]));
+    }
+    request.body.parse().or_else(|err| api_error(400, &format!("{}", err)))
+}
+
+fn parse_color(value: &Json) -> Result<Color, ApiError> {
+    match value.as_str().map(|name| name.parse()) {
+        Some(Ok(color)) => Ok(color),
+        Some(Err(err)) => api_error(400, &err),
+        None => api_error(400, "colors are given as strings")
+    }
+}
+
+fn create(games: &mut Games, body: &Json) -> Result<(u16, Json), ApiError> {
+    let players = match body.get("players") {
+        Some(players) => match players.as_array() {
+            Some(players) => players.iter().map(parse_color).collect::<Result<Vec<Color>, ApiError>>()?,
+            None => return api_error(400, "players are given as a list")
+        },
+        None => vec![Color::Blue, Color::Green]
+    };
+    if players.len() < 2 || players.len() > 4 ||
+        players.iter().enumerate().any(|(i, color)| players[..i].contains(color)) {
+        return api_error(400, "a game needs two to four different colors");
+    }
+    games.hosted.push(Hosted {
+        game: Game::new(&players),
+        seats: vec![],
+        subscribers: vec![]
+    });
+    let id = games.hosted.len();
+    Ok((201, games.hosted[id - 1].state(id)))
+}
+
+fn join(games: &mut Games, id: &str, body: &Json) -> Result<(u16, Json), ApiError> {
+    let token = games.new_token();
+    let (id, hosted) = games.get(id)?;
+    let open = hosted.open_seats();
+    let color = match body.get("color") {
+        Some(color) => {
+            let color = parse_color(color)?;
+            if !hosted.game.players().contains(&color) {
+                return api_error(400, "the color doesn't play in this game");
+            }
+            if !open.contains(&color) {
+                return api_error(409, "the seat is taken");
+            }
+            color
+        },
+        None => match open.first() {
+            Some(&color) => color,
+            None => return api_error(409, "the game is full")
+        }
+    };
+    hosted.seats.push((color, token.clone()));
+    hosted.notify(id);
+    Ok((201, Json::object(vec![
+        ("color", Json::string(color.name())),
+        ("token", Json::String(token)),
+        ("game", hosted.state(id))
+    ])))
+}
+
+fn play(games: &mut Games, id: &str, body: &Json) -> Result<(u16, Json), ApiError> {
+    let (id, hosted) = games.get(id)?;
+    let token = body.get("token").and_then(Json::as_str);
+    let color = match hosted.seats.iter().find(|&&(_, ref t)| Some(&t[..]) == token) {
+        Some(&(color, _)) => color,
+        None => return api_error(403, "not seated")
+    };
+    let mv: Move = match body.get("move").and_then(Json::as_str).map(|mv| mv.parse()) {
+        Some(Ok(mv)) => mv,
+        Some(Err(err)) => return api_error(400, &err.to_string()),
+        None => return api_error(400, "missing move")
+    };
+    match hosted.game.status() {
+        Status::Playing(current) if current != color => return api_error(409, "not your turn"),
+        _ => {}
+    }
+    // The game checks the move against the board and the player's supply
+    if let Err(err) = hosted.game.place(mv) {
+        return api_error(409, &err.to_string());
+    }
+    hosted.notify(id);
+    Ok((200, hosted.state(id)))
+}
+
+fn route(request: &Request, games: &Mutex<Games>) -> Result<(u16, Json), ApiError> {
+    let parts: Vec<&str> = request.path.split('/').filter(|part| !part.is_empty()).collect();
+    let body = parse_body(request)?;
+    let mut games = games.lock().unwrap();
+    match (&request.method[..], &parts[..]) {
+        ("GET", &["games"]) => {
+            let list = games.hosted.iter().enumerate().map(|(i, hosted)| hosted.state(i + 1)).collect();
+            Ok((200, Json::Array(list)))
+        },
+        ("POST", &["games"]) => create(&mut games, &body),
+        ("GET", &["games", id]) => games.get(id).map(|(id, hosted)| (200, hosted.state(id))),
+        ("POST", &["games", id, "seats"]) => join(&mut games, id, &body),
+        ("POST", &["games", id, "moves"]) => play(&mut games, id, &body),
+        ("GET", &["games", _, "stream"]) => api_error(400, "expected a WebSocket upgrade"),
+        (_, &["games"]) | (_, &["games", _]) | (_, &["games", _, "seats"]) |
+        (_, &["games", _, "moves"]) | (_, &["games", _, "stream"]) =>
+            api_error(405, "method not allowed"),
+        _ => api_error(404, "not found")
+    }
+}
+
+// Follows a game until the client goes away. The state and the answers to
+// pings go through the same queue as the pushed states, so that they don't
+// interleave.
+fn follow(mut reader: BufReader<TcpStream>, stream: TcpStream, request: &Request,
+          games: &Mutex<Games>) -> io::Result<()> {
+    let id = request.path.split('/').filter(|part| !part.is_empty()).nth(1).unwrap_or("");
+    let (sender, frames) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+    let found = games.lock().unwrap().get(id).map(|(id, hosted)| {
+        let _ = sender.send(Frame::Text(hosted.state(id).to_string()));
+        hosted.subscribers.push(sender.clone());
+    });
+    if let Err(ApiError(status, message)) = found {
+        let body = Json::object(vec![("error", Json::String(message))]);
+        return http::write_response(&mut &stream, status, "application/json", &body.to_string());
+    }
+    stream.set_write_timeout(Some(REQUEST_TIME))?;
+    http::write_websocket_handshake(&mut &stream, request)?;
+    let writer = stream.try_clone()?;
+    writer.set_write_timeout(Some(REQUEST_TIME))?;
+    thread::spawn(move || write_frames(writer, frames));
+
+    stream.set_read_timeout(None)?;
+    loop {
+        let reply = match Frame::read(&mut reader) {
+            Ok(Frame::Ping(data)) => Frame::Pong(data),
+            Ok(Frame::Close) | Err(_) => break,
+            Ok(_) => continue
+        };
+        if sender.send(reply).is_err() {
+            break;
+        }
+    }
+    // The writer closes the connection, and the next push notices and drops
+    // the subscriber
+    let _ = sender.send(Frame::Close);
+    Ok(())
+}
+
+// Sends the frames for one WebSocket until it's closed, or until a write
+// fails or times out because the client stopped reading
+fn write_frames(stream: TcpStream, frames: Receiver<Frame>) {
+    for frame in frames {
+        if frame.write(&mut &stream).is_err() || frame == Frame::Close {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+fn handle(stream: TcpStream, games: &Mutex<Games>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIME))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = match Request::read(&mut reader) {
+        Ok(request) => request,
+        Err(err) => {
+            let body = Json::object(vec![("error", Json::String(err.to_string()))]);
+            return http::write_response(&mut &stream, 400, "application/json", &body.to_string());
+        }
+    };
+    if request.is_websocket() {
+        return follow(reader, stream, &request, games);
+    }
+    let (status, body) = match request.method.as_str() {
+        // Browsers ask before sending JSON to another origin
+        "OPTIONS" => (204, String::new()),
+        _ => match route(&request, games) {
+            Ok((status, body)) => (status, body.to_string()),
+            Err(ApiError(status, message)) =>
+                (status, Json::object(vec![("error", Json::String(message))]).to_string())
+        }
+    };
+    http::write_response(&mut &stream, status, "application/json", &body)
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(address)?,
+            games: Arc::new(Mutex::new(Games {
+                hosted: vec![],
+                random: Random::from_time()
+            }))
+        })
+    }
+
+    pub fn address(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Serves every connection on a thread of its own, forever
+    pub fn run(&self) {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue
+            };
+            let games = self.games.clone();
+            thread::spawn(move || {
+                // Clients that went away don't need an answer
+                let _ = handle(stream, &games);
+            });
+        }
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+fn send_request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Json) {
+    use std::io::{Read, Write};
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+           method, path, body.len(), body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+    (status, if body.is_empty() { Json::Null } else { body.parse().unwrap() })
+}
+
+#[cfg(test)]
+fn start_server() -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0").unwrap();
+    let address = server.address().unwrap();
+    thread::spawn(move || server.run());
+    address
+}
+
+#[test]
+fn test_server_game() {
+    let address = start_server();
+    let (status, game) = send_request(address, "POST", "/games", r#"{"players":["red","blue"]}"#);
+    assert_eq!(status, 201);
+    assert_eq!(game.get("id"), Some(&Json::from(1)));
+    assert_eq!(game.get("turn"), Some(&Json::string("red")));
+    assert_eq!(send_request(address, "POST", "/games", r#"{"players":["red"]}"#).0, 400);
+
+    let (status, red) = send_request(address, "POST", "/games/1/seats", "");
+    assert_eq!(status, 201);
+    assert_eq!(red.get("color"), Some(&Json::string("red")));
+    let (_, blue) = send_request(address, "POST", "/games/1/seats", r#"{"color":"blue"}"#);
+    assert_eq!(send_request(address, "POST", "/games/1/seats", "").0, 409);
+    let red = red.get("token").and_then(Json::as_str).unwrap().to_owned();
+    let blue = blue.get("token").and_then(Json::as_str).unwrap().to_owned();
+
+    let play = |token: &str, mv: &str| {
+        let body = Json::object(vec![("token", Json::string(token)), ("move", Json::string(mv))]);
+        send_request(address, "POST", "/games/1/moves", &body.to_string())
+    };
+    assert_eq!(play(&blue, "b2M").0, 409);
+    assert_eq!(play("nobody", "b2M").0, 403);
+    assert_eq!(play(&red, "d2M").0, 400);
+    let (status, game) = play(&red, "b2M");
+    assert_eq!(status, 200);
+    assert_eq!(game.get("board").and_then(|board| board.get("b2")).and_then(|cell| cell.get("medium")),
+               Some(&Json::string("red")));
+    assert_eq!(game.get("supplies").and_then(|supplies| supplies.get("red"))
+               .and_then(|supply| supply.get("medium")), Some(&Json::from(2)));
+    let (status, err) = play(&blue, "b2M");
+    assert_eq!(status, 409);
+    assert_eq!(err.get("error"), Some(&Json::string("the ring is already taken")));
+
+    let (status, game) = send_request(address, "GET", "/games/1", "");
+    assert_eq!(status, 200);
+    assert_eq!(game.get("moves"), Some(&Json::Array(vec![Json::string("b2M")])));
+    assert_eq!(send_request(address, "GET", "/games/2", "").0, 404);
+    assert_eq!(send_request(address, "DELETE", "/games/1", "").0, 405);
+}
+
+#[test]
+fn test_server_stream() {
+    use std::io::{BufRead, Write};
+
+    let address = start_server();
+    send_request(address, "POST", "/games", "");
+    let (_, seat) = send_request(address, "POST", "/games/1/seats", "");
+    let token = seat.get("token").and_then(Json::as_str).unwrap().to_owned();
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET /games/1/stream HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                    Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                    Sec-WebSocket-Version: 13\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut lines = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        lines.push(line);
+    }
+    assert!(lines[0].starts_with("HTTP/1.1 101"));
+    assert!(lines.contains(&"Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n".to_owned()));
+
+    let state = |reader: &mut BufReader<TcpStream>| match Frame::read(reader).unwrap() {
+        Frame::Text(text) => text.parse::<Json>().unwrap(),
+        frame => panic!("expected the state, got {:?}", frame)
+    };
+    assert_eq!(state(&mut reader).get("moves"), Some(&Json::Array(vec![])));
+    let body = Json::object(vec![("token", Json::String(token)), ("move", Json::string("a1S"))]);
+    send_request(address, "POST", "/games/1/moves", &body.to_string());
+    let pushed = state(&mut reader);
+    assert_eq!(pushed.get("moves"), Some(&Json::Array(vec![Json::string("a1S")])));
+    assert_eq!(pushed.get("turn"), Some(&Json::string("green")));
+
+    // Clients mask their frames
+    stream.write_all(&[0x89, 0x81, 0, 0, 0, 0, 7]).unwrap();
+    assert_eq!(Frame::read(&mut reader).unwrap(), Frame::Pong(vec![7]));
+    stream.write_all(&[0x88, 0x80, 0, 0, 0, 0]).unwrap();
+    assert_eq!(Frame::read(&mut reader).unwrap(), Frame::Close);
+}
+
+#[test]
+fn test_server_stuck_stream() {
+    use std::io::Write;
+    use std::time::Instant;
+
+    let address = start_server();
+    send_request(address, "POST", "/games", "");
+    // A client that keeps pinging but never reads the answers
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET /games/1/stream HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                    Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                    Sec-WebSocket-Version: 13\r\n\r\n").unwrap();
+    let mut ping = vec![0x89, 0xfe, 0xea, 0x60, 0, 0, 0, 0];
+    ping.extend(vec![0; 60000]);
+    thread::spawn(move || for _ in 0..300 {
+        if stream.write_all(&ping).is_err() {
+            break;
+        }
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    let started = Instant::now();
+    send_request(address, "POST", "/games/1/seats", "");
+    assert_eq!(send_request(address, "GET", "/games", "").0, 200);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
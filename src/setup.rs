@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use model::{Color, COLORS};
+use engine::Level;
+use clock::TimeControl;
+use discovery::STANDARD_VARIANT;
+use paths;
+
+// Rules that can be chosen for a game
+pub const VARIANTS: [&'static str; 1] = [STANDARD_VARIANT];
+
+const SETUP_HEADER: &'static str = "# three game setup";
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Player {
+    Human,
+    Bot(Level)
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Seat {
+    pub color: Color,
+    // Empty when the player goes by their color
+    pub name: String,
+    pub player: Player
+}
+
+// Who plays a game in the window and under which rules, kept between runs.
+// Seats are in the order the players move in.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Setup {
+    pub seats: Vec<Seat>,
+    pub variant: String,
+    pub time_control: Option<TimeControl>
+}
+
+impl Default for Setup {
+    // Everybody at one machine, as the window always used to start
+    fn default() -> Setup {
+        Setup {
+            seats: COLORS.iter()
+                .map(|&color| Seat { color: color, name: String::new(), player: Player::Human })
+                .collect(),
+            variant: STANDARD_VARIANT.to_owned(),
+            time_control: None
+        }
+    }
+}
+
+impl Setup {
+    pub fn default_path() -> Option<PathBuf> {
+        paths::config_dir().map(|dir| dir.join("setup.txt"))
+    }
+
+    pub fn players(&self) -> Vec<Color> {
+        self.seats.iter().map(|seat| seat.color).collect()
+    }
+
+    pub fn seat(&self, color: Color) -> Option<&Seat> {
+        self.seats.iter().find(|seat| seat.color == color)
+    }
+
+    pub fn bots(&self) -> Vec<(Color, Level)> {
+        self.seats.iter()
+            .filter_map(|seat| match seat.player {
+                Player::Bot(level) => Some((seat.color, level)),
+                Player::Human => None
+            })
+            .collect()
+    }
+
+    // What's wrong with the setup, if anything
+    pub fn check(&self) -> Result<(), String> {
+        if self.seats.len() < 2 || self.seats.len() > 4 {
+            return Err("Games have two to four players".to_owned());
+        }
+        for (i, seat) in self.seats.iter().enumerate() {
+            if self.seats[..i].iter().any(|other| other.color == seat.color) {
+                return Err(format!("{:?} has more than one seat", seat.color));
+            }
+        }
+        if !VARIANTS.contains(&&self.variant[..]) {
+            return Err(format!("Unknown rules \"{}\"", self.variant));
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Setup> {
+        let file = File::open(path)?;
+        Setup::read(BufReader::new(file))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Setup> {
+        let mut setup = Setup {
+            seats: vec![],
+            variant: STANDARD_VARIANT.to_owned(),
+            time_control: None
+        };
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !parse_line(&mut setup, line) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("invalid setup line: {}", line)));
+            }
+        }
+        setup.check().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(setup)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", SETUP_HEADER)?;
+        writeln!(writer, "variant {}", self.variant)?;
+        if let Some(control) = self.time_control {
+            writeln!(writer, "time-control {}", control)?;
+        }
+        for seat in self.seats.iter() {
+            let player = match seat.player {
+                Player::Human => "human".to_owned(),
+                Player::Bot(level) => format!("bot {}", level)
+            };
+            if seat.name.is_empty() {
+                writeln!(writer, "seat {} {}", seat.color.name(), player)?;
+            } else {
+                writeln!(writer, "seat {} {} {}", seat.color.name(), player, seat.name)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+// Lines are like "variant standard", "time-control 300+5" and
+// "seat blue bot hard Name", where the name may have spaces or be left out
+fn parse_line(setup: &mut Setup, line: &str) -> bool {
+    let mut words = line.splitn(2, ' ');
+    let key = words.next().unwrap();
+    let rest = words.next().unwrap_or("").trim();
+    match key {
+        "variant" => setup.variant = rest.to_owned(),
+        "time-control" => match rest.parse() {
+            Ok(control) => setup.time_control = Some(control),
+            Err(_) => return false
+        },
+        "seat" => match parse_seat(rest) {
+            Some(seat) => setup.seats.push(seat),
+            None => return false
+        },
+        _ => return false
+    }
+    true
+}
+
+fn parse_seat(text: &str) -> Option<Seat> {
+    let mut words = text.splitn(2, ' ');
+    let color = words.next()?.parse().ok()?;
+    let rest = words.next().unwrap_or("").trim();
+    let (player, name) = if rest == "human" || rest.starts_with("human ") {
+        (Player::Human, &rest[5..])
+    } else if rest.starts_with("bot ") {
+        let mut words = rest[4..].trim().splitn(2, ' ');
+        let level = words.next()?.parse().ok()?;
+        (Player::Bot(level), words.next().unwrap_or(""))
+    } else {
+        return None;
+    };
+    Some(Seat {
+        color: color,
+        name: name.trim().to_owned(),
+        player: player
+    })
+}
+
+// Tests
+
+#[test]
+fn test_setup_file() {
+    let setup = Setup {
+        seats: vec![
+            Seat { color: Color::Red, name: "Ann Lee".to_owned(), player: Player::Human },
+            Seat { color: Color::Blue, name: String::new(), player: Player::Bot(Level::Hard) },
+            Seat { color: Color::Green, name: "Max".to_owned(), player: Player::Bot(Level::Easy) }
+        ],
+        variant: STANDARD_VARIANT.to_owned(),
+        time_control: Some("300+5".parse().unwrap())
+    };
+    let mut text = vec![];
+    setup.write(&mut text).unwrap();
+    assert!(String::from_utf8(text.clone()).unwrap().contains("seat blue bot hard\n"));
+    assert_eq!(Setup::read(&text[..]).unwrap(), setup);
+    assert_eq!(setup.players(), vec![Color::Red, Color::Blue, Color::Green]);
+    assert_eq!(setup.bots(), vec![(Color::Blue, Level::Hard), (Color::Green, Level::Easy)]);
+    assert_eq!(setup.seat(Color::Red).unwrap().name, "Ann Lee");
+
+    assert!(Setup::read(&b"seat blue human\n"[..]).is_err());
+    assert!(Setup::read(&b"seat blue human\nseat blue human\n"[..]).is_err());
+    assert!(Setup::read(&b"seat blue robot\nseat green human\n"[..]).is_err());
+    assert!(Setup::read(&b"variant chess\nseat blue human\nseat green human\n"[..]).is_err());
+    assert!(Setup::default().check().is_ok());
+}
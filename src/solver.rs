@@ -0,0 +1,448 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+
+use model::Color;
+use game::{Game, Move, Status};
+use paths;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Value {
+    Win(u32),
+    Draw,
+    Loss(u32)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Value::Win(plies) => write!(f, "win in {} plies", plies),
+            &Value::Draw => write!(f, "draw"),
+            &Value::Loss(plies) => write!(f, "loss in {} plies", plies)
+        }
+    }
+}
+
+// Proof and disproof numbers: how many more positions at least have to be
+// decided to show that a color can force a win, or that it can't. Anything
+// from INFINITY up means it never will be.
+const INFINITY: u32 = 1 << 30;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+struct Numbers {
+    proof: u32,
+    disproof: u32
+}
+
+const UNKNOWN: Numbers = Numbers { proof: 1, disproof: 1 };
+const PROVEN: Numbers = Numbers { proof: 0, disproof: INFINITY };
+const DISPROVEN: Numbers = Numbers { proof: INFINITY, disproof: 0 };
+
+// Canonical hash of the position, plies left to win in and whether the
+// color trying to win is the one to move
+type Key = (u64, u8, bool);
+
+fn rings_left(game: &Game) -> u8 {
+    game.players().iter().map(|color| game.supply(*color).total()).sum()
+}
+
+fn decided(game: &Game, target: Color, plies: u8) -> Option<Numbers> {
+    match game.status() {
+        Status::Won(color) if color == target => Some(PROVEN),
+        Status::Won(_) | Status::Draw => Some(DISPROVEN),
+        Status::Playing(_) if plies == 0 => Some(DISPROVEN),
+        Status::Playing(_) => None
+    }
+}
+
+// Depth-first proof-number search for two player games. Each question the
+// search answers is whether a color can force a win within a number of
+// plies, so the value of a position is found by first asking without a
+// limit and then for the shortest limit that still wins. Positions are
+// cached under their canonical hash so symmetric and color swapped positions
+// share their numbers.
+pub struct Solver {
+    table: HashMap<Key, Numbers>,
+    // Canonical hashes by plain hash, every position is looked at often
+    canonical: HashMap<u64, u64>,
+    capacity: usize,
+    nodes: u64
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver::with_capacity(1 << 20)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Solver {
+        Solver {
+            table: HashMap::new(),
+            canonical: HashMap::new(),
+            capacity: capacity,
+            nodes: 0
+        }
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // Game theoretic value for the player to move
+    pub fn solve(&mut self, game: &Game) -> Value {
+        assert_eq!(game.players().len(), 2, "the solver only handles two player games");
+        let mut game = game.clone();
+        let me = game.current();
+        self.value_for(&mut game, me)
+    }
+
+    pub fn best_move(&mut self, game: &Game) -> Option<(Move, Value)> {
+        assert_eq!(game.players().len(), 2, "the solver only handles two player games");
+        let mut game = game.clone();
+        self.best_move_in(&mut game)
+    }
+
+    // The first move that keeps the value of the position
+    fn best_move_in(&mut self, game: &mut Game) -> Option<(Move, Value)> {
+        let me = game.current();
+        let other = *game.players().iter().find(|c| **c != me).unwrap();
+        let value = self.value_for(game, me);
+        let mut best = None;
+        for mv in game.legal_moves() {
+            game.place(mv).unwrap();
+            let keeps = match value {
+                Value::Win(plies) => self.proves(game, me, plies as u8 - 1),
+                Value::Draw => {
+                    let left = rings_left(game);
+                    !self.proves(game, other, left)
+                },
+                Value::Loss(plies) => plies < 2 || !self.proves(game, other, plies as u8 - 2)
+            };
+            game.undo();
+            if keeps {
+                best = Some((mv, value));
+                break;
+            }
+        }
+        best
+    }
+
+    fn value_for(&mut self, game: &mut Game, color: Color) -> Value {
+        let other = *game.players().iter().find(|c| **c != color).unwrap();
+        match game.status() {
+            Status::Won(winner) if winner == color => Value::Win(0),
+            Status::Won(_) => Value::Loss(0),
+            Status::Draw => Value::Draw,
+            Status::Playing(_) => {
+                if let Some(plies) = self.fastest_win(game, color) {
+                    Value::Win(plies)
+                } else if let Some(plies) = self.fastest_win(game, other) {
+                    Value::Loss(plies)
+                } else {
+                    Value::Draw
+                }
+            }
+        }
+    }
+
+    // Fewest plies in which the color can force a win against any defence
+    fn fastest_win(&mut self, game: &mut Game, color: Color) -> Option<u32> {
+        let left = rings_left(game);
+        if !self.proves(game, color, left) {
+            return None;
+        }
+        (1..left + 1).find(|plies| self.proves(game, color, *plies)).map(|plies| plies as u32)
+    }
+
+    // Limits longer than the game can last are all the same
+    fn key(&mut self, game: &Game, target: Color, plies: u8) -> Key {
+        let hash = *self.canonical.entry(game.hash()).or_insert_with(|| game.canonical_hash().0);
+        (hash, plies.min(rings_left(game)), game.current() == target)
+    }
+
+    fn proves(&mut self, game: &mut Game, target: Color, plies: u8) -> bool {
+        let numbers = match decided(game, target, plies) {
+            Some(numbers) => numbers,
+            None => self.search(game, target, plies, Numbers { proof: INFINITY, disproof: INFINITY })
+        };
+        numbers.proof == 0
+    }
+
+    // Expands the most proving position under this one until its numbers
+    // reach either limit
+    fn search(&mut self, game: &mut Game, target: Color, plies: u8, limits: Numbers) -> Numbers {
+        self.nodes += 1;
+        let attacking = game.current() == target;
+        let children: Vec<(Move, Key, Option<Numbers>)> = game.legal_moves().into_iter()
+            .map(|mv| {
+                game.place(mv).unwrap();
+                let child = (mv, self.key(game, target, plies - 1), decided(game, target, plies - 1));
+                game.undo();
+                child
+            })
+            .collect();
+
+        loop {
+            let numbers: Vec<Numbers> = children.iter()
+                .map(|&(_, key, decided)| decided
+                     .or_else(|| self.table.get(&key).cloned())
+                     .unwrap_or(UNKNOWN))
+                .collect();
+            // The attacker needs one winning move, the defender has to be
+            // beaten after all of them
+            let (ours, theirs): (Vec<u32>, Vec<u32>) = if attacking {
+                numbers.iter().map(|n| (n.proof, n.disproof)).unzip()
+            } else {
+                numbers.iter().map(|n| (n.disproof, n.proof)).unzip()
+            };
+            let least = *ours.iter().min().unwrap();
+            let total = theirs.iter().fold(0, |sum, n| (sum + n).min(INFINITY));
+            let (least, total) = match (least, total) {
+                (0, _) => (0, INFINITY),
+                (_, 0) => (INFINITY, 0),
+                numbers => numbers
+            };
+            let current = if attacking {
+                Numbers { proof: least, disproof: total }
+            } else {
+                Numbers { proof: total, disproof: least }
+            };
+            let (least_limit, total_limit) = if attacking {
+                (limits.proof, limits.disproof)
+            } else {
+                (limits.disproof, limits.proof)
+            };
+            if least >= least_limit || total >= total_limit {
+                let key = self.key(game, target, plies);
+                self.store(key, current);
+                return current;
+            }
+
+            let best = (0..ours.len()).min_by_key(|i| ours[*i]).unwrap();
+            let second = (0..ours.len()).filter(|i| *i != best).map(|i| ours[i]).min()
+                .unwrap_or(INFINITY);
+            let child_least = least_limit.min(second + 1);
+            let child_total = total_limit - total + theirs[best];
+            let child_limits = if attacking {
+                Numbers { proof: child_least, disproof: child_total }
+            } else {
+                Numbers { proof: child_total, disproof: child_least }
+            };
+            game.place(children[best].0).unwrap();
+            self.search(game, target, plies - 1, child_limits);
+            game.undo();
+        }
+    }
+
+    // A full table keeps what was decided and starts over on the rest
+    fn store(&mut self, key: Key, numbers: Numbers) {
+        if self.table.len() >= self.capacity {
+            self.table.retain(|_, numbers| numbers.proof == 0 || numbers.disproof == 0);
+            if self.table.len() >= self.capacity / 2 {
+                self.table.clear();
+            }
+            self.canonical.clear();
+        }
+        self.table.insert(key, numbers);
+    }
+
+    // Builds a table of moves that secure the game theoretic value for every
+    // player who isn't lost, against anything the opponent might try
+    pub fn strategy(&mut self, game: &Game) -> Strategy {
+        let value = self.solve(game);
+        let mover = game.current();
+        let other = *game.players().iter().find(|c| **c != mover).unwrap();
+        let sides = match value {
+            Value::Win(_) => vec![mover],
+            Value::Loss(_) => vec![other],
+            Value::Draw => vec![mover, other]
+        };
+        let mut strategy = Strategy::new();
+        let mut game = game.clone();
+        for side in sides {
+            let mut seen = HashSet::new();
+            self.collect(&mut game, side, &mut strategy, &mut seen);
+        }
+        strategy
+    }
+
+    fn collect(&mut self,
+               game: &mut Game,
+               side: Color,
+               strategy: &mut Strategy,
+               seen: &mut HashSet<u64>) {
+        if game.is_over() || !seen.insert(game.canonical_hash().0) {
+            return;
+        }
+        if game.current() == side {
+            let (mv, _) = self.best_move_in(game).unwrap();
+            strategy.insert(game, mv);
+            game.place(mv).unwrap();
+            self.collect(game, side, strategy, seen);
+            game.undo();
+        } else {
+            for mv in game.legal_moves() {
+                game.place(mv).unwrap();
+                self.collect(game, side, strategy, seen);
+                game.undo();
+            }
+        }
+    }
+}
+
+// Perfect play moves keyed by canonical position. Moves are stored relative
+// to the canonical orientation of the board.
+#[derive(Debug,Clone,Default)]
+pub struct Strategy {
+    moves: HashMap<u64, Move>
+}
+
+const STRATEGY_HEADER: &'static str = "# three strategy table";
+
+impl Strategy {
+    pub fn new() -> Strategy {
+        Strategy {
+            moves: HashMap::new()
+        }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("strategy.txt"))
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    pub fn insert(&mut self, game: &Game, mv: Move) {
+        let (key, transform) = game.canonical_hash();
+        self.moves.insert(key, transform.apply_move(mv));
+    }
+
+    pub fn lookup(&self, game: &Game) -> Option<Move> {
+        let (key, transform) = game.canonical_hash();
+        self.moves.get(&key).map(|mv| transform.restore_move(*mv))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Strategy> {
+        let file = File::open(path)?;
+        Strategy::read(BufReader::new(file))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Strategy> {
+        let mut strategy = Strategy::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData,
+                                            format!("invalid strategy entry: {}", line));
+            let mut fields = line.split_whitespace();
+            let key = fields.next()
+                .and_then(|key| u64::from_str_radix(key, 16).ok())
+                .ok_or_else(&invalid)?;
+            let mv = fields.next()
+                .and_then(|mv| mv.parse().ok())
+                .ok_or_else(&invalid)?;
+            strategy.moves.insert(key, mv);
+        }
+        Ok(strategy)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", STRATEGY_HEADER)?;
+        let mut keys: Vec<&u64> = self.moves.keys().collect();
+        keys.sort();
+        for key in keys {
+            writeln!(writer, "{:016x} {}", key, self.moves[key])?;
+        }
+        writer.flush()
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+fn game_after(moves: &[&str]) -> Game {
+    let mut game = Game::two_player();
+    for mv in moves {
+        game.place(mv.parse().unwrap()).unwrap();
+    }
+    game
+}
+
+#[test]
+fn test_solver_immediate_win() {
+    let game = game_after(&["a1S", "a2S", "b1S", "b2S"]);
+    let mut solver = Solver::new();
+    assert_eq!(solver.solve(&game), Value::Win(1));
+    let (mv, value) = solver.best_move(&game).unwrap();
+    assert_eq!(mv, "c1S".parse().unwrap());
+    assert_eq!(value, Value::Win(1));
+}
+
+#[test]
+fn test_solver_forced_loss() {
+    let game = game_after(&["b2M", "a1S", "c1S"]);
+    let mut solver = Solver::new();
+    assert_eq!(solver.solve(&game), Value::Loss(6));
+    let (_, value) = solver.best_move(&game).unwrap();
+    assert_eq!(value, Value::Loss(6));
+    // Blocking the top row only walks into another threat
+    let game = game_after(&["b2M", "a1S", "c1S", "b1S"]);
+    assert_eq!(solver.solve(&game), Value::Win(1));
+}
+
+#[test]
+fn test_solver_win_limits() {
+    let mut game = game_after(&["b2M", "a1S", "c1S"]);
+    let mut solver = Solver::new();
+    let left = rings_left(&game);
+    assert!(solver.proves(&mut game, Color::Blue, left));
+    assert!(solver.proves(&mut game, Color::Blue, 6));
+    assert!(!solver.proves(&mut game, Color::Blue, 5));
+    assert!(!solver.proves(&mut game, Color::Green, left));
+    // Mirrored along the diagonal the position is the same
+    let mut mirrored = game_after(&["b2M", "a1S", "a3S"]);
+    assert!(solver.proves(&mut mirrored, Color::Blue, 6));
+    assert!(!solver.proves(&mut mirrored, Color::Blue, 5));
+}
+
+#[test]
+fn test_strategy_round_trip() {
+    let game = game_after(&["b2M", "a1S", "c1S"]);
+    let mut solver = Solver::new();
+    let strategy = solver.strategy(&game);
+    assert!(!strategy.is_empty());
+
+    let mut buffer = vec![];
+    strategy.write(&mut buffer).unwrap();
+    let loaded = Strategy::read(&buffer[..]).unwrap();
+    assert_eq!(loaded.len(), strategy.len());
+
+    // Green is lost, so the table holds Blue's replies to everything
+    let mut game = game;
+    for mv in game.legal_moves() {
+        game.place(mv).unwrap();
+        let reply = loaded.lookup(&game).unwrap();
+        game.place(reply).unwrap();
+        match solver.solve(&game) {
+            Value::Loss(_) => {},
+            value => panic!("Blue let go of the win with {} after {}: {}", reply, mv, value)
+        }
+        game.undo();
+        game.undo();
+    }
+}
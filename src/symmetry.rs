@@ -0,0 +1,289 @@
+use model::{Board, Color, Location, COLORS, RINGS, index_to_location};
+use game::{Game, Move, Supply};
+use zobrist;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Symmetry {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    Transpose,
+    AntiTranspose
+}
+
+pub const SYMMETRIES: [Symmetry; 8] = [
+    Symmetry::Identity, Symmetry::Rotate90, Symmetry::Rotate180,
+    Symmetry::Rotate270, Symmetry::FlipHorizontal, Symmetry::FlipVertical,
+    Symmetry::Transpose, Symmetry::AntiTranspose
+];
+
+impl Symmetry {
+    pub fn apply(&self, (x, y): Location) -> Location {
+        match self {
+            &Symmetry::Identity => (x, y),
+            &Symmetry::Rotate90 => (2 - y, x),
+            &Symmetry::Rotate180 => (2 - x, 2 - y),
+            &Symmetry::Rotate270 => (y, 2 - x),
+            &Symmetry::FlipHorizontal => (2 - x, y),
+            &Symmetry::FlipVertical => (x, 2 - y),
+            &Symmetry::Transpose => (y, x),
+            &Symmetry::AntiTranspose => (2 - y, 2 - x)
+        }
+    }
+
+    pub fn inverse(&self) -> Symmetry {
+        match self {
+            &Symmetry::Rotate90 => Symmetry::Rotate270,
+            &Symmetry::Rotate270 => Symmetry::Rotate90,
+            other => *other
+        }
+    }
+}
+
+// A board symmetry combined with a relabeling of the colors. Applying a
+// transform takes things from the original position to the canonical one,
+// restoring goes the other way.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Transform {
+    pub symmetry: Symmetry,
+    colors: [Color; 4]
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform::new(Symmetry::Identity, COLORS)
+    }
+
+    // colors[c.index()] is the color c is relabeled as
+    pub fn new(symmetry: Symmetry, colors: [Color; 4]) -> Transform {
+        Transform {
+            symmetry: symmetry,
+            colors: colors
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        let mut colors = COLORS;
+        for color in COLORS.iter() {
+            colors[self.apply_color(*color).index()] = *color;
+        }
+        Transform::new(self.symmetry.inverse(), colors)
+    }
+
+    pub fn apply_location(&self, location: Location) -> Location {
+        self.symmetry.apply(location)
+    }
+
+    pub fn apply_color(&self, color: Color) -> Color {
+        self.colors[color.index()]
+    }
+
+    pub fn apply_move(&self, mv: Move) -> Move {
+        Move::new(self.apply_location(mv.location), mv.ring)
+    }
+
+    pub fn restore_move(&self, mv: Move) -> Move {
+        self.inverse().apply_move(mv)
+    }
+
+    pub fn apply_moves(&self, moves: &[Move]) -> Vec<Move> {
+        moves.iter().map(|mv| self.apply_move(*mv)).collect()
+    }
+
+    pub fn restore_moves(&self, moves: &[Move]) -> Vec<Move> {
+        let inverse = self.inverse();
+        moves.iter().map(|mv| inverse.apply_move(*mv)).collect()
+    }
+
+    pub fn apply_board(&self, board: &Board) -> Board {
+        let mut result = Board::empty();
+        for i in 0..9 {
+            let location = index_to_location(3, i);
+            for ring in RINGS.iter() {
+                if let Some(color) = board.get_ring(location, *ring) {
+                    result.set_ring(self.apply_location(location), *ring, self.apply_color(color));
+                }
+            }
+        }
+        result
+    }
+
+    pub fn apply_supplies(&self, supplies: &[Supply; 4]) -> [Supply; 4] {
+        let mut result = *supplies;
+        for color in COLORS.iter() {
+            result[self.apply_color(*color).index()] = supplies[color.index()];
+        }
+        result
+    }
+}
+
+// Ordering used to pick the representative among equivalent boards
+fn board_key(board: &Board) -> [u8; 27] {
+    let mut key = [0; 27];
+    for i in 0..9 {
+        let location = index_to_location(3, i);
+        for ring in RINGS.iter() {
+            key[i * 3 + ring.index()] = match board.get_ring(location, *ring) {
+                Some(color) => color.index() as u8 + 1,
+                None => 0
+            };
+        }
+    }
+    key
+}
+
+// Relabels colors in the given order, the rest keep their relative order
+fn relabeling(order: &[Color]) -> [Color; 4] {
+    let mut colors = COLORS;
+    let rest = COLORS.iter().filter(|c| !order.contains(c));
+    for (n, color) in order.iter().chain(rest).enumerate() {
+        colors[color.index()] = COLORS[n];
+    }
+    colors
+}
+
+// All transforms that take the board to its canonical form. There is more
+// than one whenever the board is itself symmetric.
+fn canonical_transforms<F>(board: &Board, colors_for: F) -> (Board, Vec<Transform>)
+    where F: Fn(Symmetry) -> [Color; 4] {
+    let candidates: Vec<(Board, Transform)> = SYMMETRIES.iter()
+        .map(|symmetry| {
+            let transform = Transform::new(*symmetry, colors_for(*symmetry));
+            (transform.apply_board(board), transform)
+        })
+        .collect();
+    let canonical = candidates.iter()
+        .min_by_key(|&&(ref board, _)| board_key(board))
+        .map(|&(ref board, _)| board.clone())
+        .unwrap();
+    let transforms = candidates.into_iter()
+        .filter(|&(ref board, _)| *board == canonical)
+        .map(|(_, transform)| transform)
+        .collect();
+    (canonical, transforms)
+}
+
+impl Board {
+    pub fn transformed(&self, transform: &Transform) -> Board {
+        transform.apply_board(self)
+    }
+
+    // Canonical representative of the board under the symmetries of the
+    // grid, with colors renamed in the order they first appear
+    pub fn canonical(&self) -> (Board, Transform) {
+        let (board, transforms) = canonical_transforms(self, |symmetry| {
+            let mut seen = vec![];
+            for i in 0..9 {
+                let location = index_to_location(3, i);
+                for ring in RINGS.iter() {
+                    let original = symmetry.inverse().apply(location);
+                    if let Some(color) = self.get_ring(original, *ring) {
+                        if !seen.contains(&color) {
+                            seen.push(color);
+                        }
+                    }
+                }
+            }
+            relabeling(&seen)
+        });
+        (board, transforms[0])
+    }
+}
+
+impl Game {
+    // Key shared by every position that plays out the same way. Colors are
+    // renamed in turn order starting from the player to move, so this also
+    // identifies positions where the players have swapped colors.
+    pub fn canonical_hash(&self) -> (u64, Transform) {
+        let (hash, transforms) = self.canonical_transforms();
+        (hash, transforms[0])
+    }
+
+    pub fn canonical_transforms(&self) -> (u64, Vec<Transform>) {
+        let players = self.players();
+        let turn = players.iter().position(|c| *c == self.current()).unwrap();
+        let order: Vec<Color> = (0..players.len())
+            .map(|n| players[(turn + n) % players.len()])
+            .collect();
+        let colors = relabeling(&order);
+        let (board, transforms) = canonical_transforms(self.board(), |_| colors);
+        let transform = transforms[0];
+        let hash = zobrist::hash(&board,
+                                 transform.apply_color(self.current()),
+                                 &transform.apply_supplies(self.supplies()));
+        (hash, transforms)
+    }
+}
+
+// Tests
+
+#[test]
+fn test_symmetry_inverse() {
+    for symmetry in SYMMETRIES.iter() {
+        for i in 0..9 {
+            let location = index_to_location(3, i);
+            assert_eq!(symmetry.inverse().apply(symmetry.apply(location)), location);
+        }
+    }
+}
+
+#[test]
+fn test_transform_restore_moves() {
+    use model::Ring;
+    let moves = vec![Move::new((0, 1), Ring::Top), Move::new((2, 2), Ring::Bottom)];
+    for symmetry in SYMMETRIES.iter() {
+        let transform = Transform::new(*symmetry, [Color::Red, Color::Blue, Color::Yellow, Color::Green]);
+        let applied = transform.apply_moves(&moves);
+        assert_eq!(transform.restore_moves(&applied), moves);
+        assert_eq!(transform.inverse().apply_color(Color::Blue), Color::Green);
+    }
+}
+
+#[test]
+fn test_board_canonical() {
+    use model::Ring;
+    let mut board = Board::empty();
+    board.set_ring((0, 0), Ring::Top, Color::Red);
+    board.set_ring((1, 0), Ring::Middle, Color::Yellow);
+    board.set_ring((1, 1), Ring::Bottom, Color::Red);
+    let (canonical, transform) = board.canonical();
+    assert_eq!(transform.inverse().apply_board(&canonical), board);
+    for symmetry in SYMMETRIES.iter() {
+        let other = Transform::new(*symmetry, [Color::Green, Color::Blue, Color::Yellow, Color::Red]);
+        let (other_canonical, _) = other.apply_board(&board).canonical();
+        assert_eq!(other_canonical, canonical);
+    }
+}
+
+#[test]
+fn test_game_canonical_hash() {
+    use model::Ring;
+    let mut a = Game::two_player();
+    a.place(Move::new((0, 0), Ring::Top)).unwrap();
+    a.place(Move::new((1, 0), Ring::Middle)).unwrap();
+    let mut b = Game::new(&[Color::Green, Color::Blue]);
+    b.place(Move::new((2, 2), Ring::Top)).unwrap();
+    b.place(Move::new((2, 1), Ring::Middle)).unwrap();
+    let (hash_a, transform_a) = a.canonical_hash();
+    let (hash_b, transform_b) = b.canonical_hash();
+    assert_eq!(hash_a, hash_b);
+    assert_eq!(transform_a.apply_board(a.board()), transform_b.apply_board(b.board()));
+    let mut c = Game::two_player();
+    c.place(Move::new((0, 0), Ring::Top)).unwrap();
+    c.place(Move::new((1, 1), Ring::Middle)).unwrap();
+    assert!(c.canonical_hash().0 != hash_a);
+}
+
+#[test]
+fn test_symmetric_position_transforms() {
+    let game = Game::two_player();
+    assert_eq!(game.canonical_transforms().1.len(), 8);
+    let mut game = game;
+    game.place("b2M".parse().unwrap()).unwrap();
+    game.place("a1S".parse().unwrap()).unwrap();
+    // only the diagonal through a1 maps the board onto itself
+    assert_eq!(game.canonical_transforms().1.len(), 2);
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use model::{Color, COLORS};
+use game::{Game, Status};
+use engine::{Bot, Level, Knowledge};
+use random::Random;
+
+// Bot settings taking part in a tournament, written as the level optionally
+// followed by a thinking time per move, like "hard" or "hard@200"
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Entrant {
+    pub level: Level,
+    pub movetime: Option<Duration>
+}
+
+impl Entrant {
+    pub fn new(level: Level) -> Entrant {
+        Entrant {
+            level: level,
+            movetime: None
+        }
+    }
+
+    fn bot(&self, random: &mut Random, knowledge: &Knowledge) -> Bot {
+        Bot::with_knowledge(self.level, Random::new(random.next_u64()), knowledge)
+    }
+}
+
+impl fmt::Display for Entrant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.movetime {
+            Some(limit) => {
+                let ms = limit.as_secs() * 1000 + limit.subsec_nanos() as u64 / 1000000;
+                write!(f, "{}@{}", self.level, ms)
+            },
+            None => write!(f, "{}", self.level)
+        }
+    }
+}
+
+impl FromStr for Entrant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Entrant, String> {
+        let mut parts = s.splitn(2, '@');
+        let level = parts.next().unwrap().parse()?;
+        let movetime = match parts.next() {
+            Some(ms) => {
+                let ms = ms.parse().map_err(|_| format!("invalid move time \"{}\"", ms))?;
+                Some(Duration::from_millis(ms))
+            },
+            None => None
+        };
+        Ok(Entrant {
+            level: level,
+            movetime: movetime
+        })
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct Record {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32
+}
+
+impl Record {
+    pub fn add(&mut self, result: Option<bool>) {
+        self.games += 1;
+        match result {
+            Some(true) => self.wins += 1,
+            Some(false) => self.losses += 1,
+            None => self.draws += 1
+        }
+    }
+
+    pub fn win_rate(&self) -> f64 {
+        rate(self.wins, self.games)
+    }
+
+    pub fn draw_rate(&self) -> f64 {
+        rate(self.draws, self.games)
+    }
+}
+
+fn rate(count: u32, games: u32) -> f64 {
+    if games == 0 { 0. } else { count as f64 / games as f64 }
+}
+
+// Rating difference that makes the expected score come out as given
+pub fn elo(score: f64) -> f64 {
+    -400. * (1. / score - 1.).log10()
+}
+
+// Elo estimate against the rest of the field with a 95% confidence
+// interval. Multiplayer games count as one pairing against every opponent
+// at the table: the winner beats everyone and a draw is a draw for all.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Rating {
+    pub elo: f64,
+    pub low: f64,
+    pub high: f64
+}
+
+impl Rating {
+    fn from_scores(scores: &[f64]) -> Rating {
+        if scores.is_empty() {
+            return Rating { elo: 0., low: 0., high: 0. };
+        }
+        let n = scores.len() as f64;
+        let mean = scores.iter().sum::<f64>() / n;
+        let variance = scores.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / n;
+        let margin = 1.96 * (variance / n).sqrt();
+        // Winning or losing everything would make the rating infinite, so
+        // it counts as half a game short of that
+        let clamp = |score: f64| score.max(0.5 / n).min(1. - 0.5 / n);
+        Rating {
+            elo: elo(clamp(mean)),
+            low: elo(clamp(mean - margin)),
+            high: elo(clamp(mean + margin))
+        }
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:+.0} ({:+.0} to {:+.0})", self.elo, self.low, self.high)
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Report {
+    pub entrants: Vec<Entrant>,
+    pub records: Vec<Record>,
+    pub ratings: Vec<Rating>,
+    // Results by turn order, the first seat moves first
+    pub seats: Vec<Record>,
+    pub colors: Vec<(Color, Record)>,
+    pub games: u32,
+    pub draws: u32,
+    pub average_length: f64
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} games, {:.1}% drawn, {:.1} plies on average",
+                 self.games, 100. * rate(self.draws, self.games), self.average_length)?;
+        writeln!(f)?;
+        writeln!(f, "{:<16} {:>6} {:>6} {:>6}  {}", "Entrant", "Won", "Drawn", "Lost", "Elo (95%)")?;
+        for (n, (entrant, record)) in self.entrants.iter().zip(self.records.iter()).enumerate() {
+            writeln!(f, "{:<16} {:>6} {:>6} {:>6}  {}",
+                     format!("{}. {}", n + 1, entrant),
+                     record.wins, record.draws, record.losses, self.ratings[n])?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<16} {:>6} {:>6}", "Seat", "Won", "Drawn")?;
+        for (n, record) in self.seats.iter().enumerate() {
+            writeln!(f, "{:<16} {:>5.1}% {:>5.1}%",
+                     format!("{}.", n + 1), 100. * record.win_rate(), 100. * record.draw_rate())?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<16} {:>6} {:>6}", "Color", "Won", "Drawn")?;
+        for &(color, ref record) in self.colors.iter() {
+            writeln!(f, "{:<16} {:>5.1}% {:>5.1}%",
+                     color.name(), 100. * record.win_rate(), 100. * record.draw_rate())?;
+        }
+        Ok(())
+    }
+}
+
+// Plays games with every entrant seated at the same table. Between games
+// both the seating and the colors rotate so that nobody keeps the first
+// move or a favourite color.
+pub fn run(entrants: &[Entrant], games: u32, seed: u64, knowledge: &Knowledge) -> Report {
+    let n = entrants.len();
+    assert!(n >= 2 && n <= COLORS.len(), "tournaments need two to four entrants");
+    let mut random = Random::new(seed);
+    let mut records = vec![Record::default(); n];
+    let mut scores = vec![vec![]; n];
+    let mut seats = vec![Record::default(); n];
+    let mut colors: Vec<(Color, Record)> = COLORS.iter().map(|c| (*c, Record::default())).collect();
+    let mut draws = 0;
+    let mut plies = 0;
+
+    for round in 0..games as usize {
+        // seating[s] is the entrant moving s:th in this game
+        let seating: Vec<usize> = (0..n).map(|s| (s + round) % n).collect();
+        let first = (round / n) % COLORS.len();
+        let players: Vec<Color> = (0..n).map(|s| COLORS[(first + s) % COLORS.len()]).collect();
+        let mut bots: Vec<Bot> = seating.iter()
+            .map(|e| entrants[*e].bot(&mut random, knowledge))
+            .collect();
+
+        let mut game = Game::new(&players);
+        while let Status::Playing(color) = game.status() {
+            let seat = players.iter().position(|c| *c == color).unwrap();
+            let mv = match entrants[seating[seat]].movetime {
+                Some(limit) => bots[seat].choose_within(&game, limit),
+                None => bots[seat].choose(&game)
+            };
+            game.place(mv.unwrap()).unwrap();
+        }
+        plies += game.history().len();
+
+        let winner = match game.status() {
+            Status::Won(color) => players.iter().position(|c| *c == color),
+            _ => None
+        };
+        if winner.is_none() {
+            draws += 1;
+        }
+        for seat in 0..n {
+            let result = winner.map(|w| w == seat);
+            let entrant = seating[seat];
+            records[entrant].add(result);
+            seats[seat].add(result);
+            colors[players[seat].index()].1.add(result);
+            for other in (0..n).filter(|o| *o != seat) {
+                scores[entrant].push(match winner {
+                    Some(w) if w == seat => 1.,
+                    Some(w) if w == other => 0.,
+                    Some(_) => 0.5,
+                    None => 0.5
+                });
+            }
+        }
+    }
+
+    colors.retain(|&(_, ref record)| record.games > 0);
+    Report {
+        entrants: entrants.to_vec(),
+        records: records,
+        ratings: scores.iter().map(|scores| Rating::from_scores(scores)).collect(),
+        seats: seats,
+        colors: colors,
+        games: games,
+        draws: draws,
+        average_length: if games == 0 { 0. } else { plies as f64 / games as f64 }
+    }
+}
+
+// Tests
+
+#[test]
+fn test_elo() {
+    assert_eq!(elo(0.5), 0.);
+    assert!((elo(0.75) - 190.8).abs() < 0.1);
+    assert!((elo(0.25) + elo(0.75)).abs() < 1e-9);
+    let rating = Rating::from_scores(&[1., 0., 1., 0.5]);
+    assert!(rating.low < rating.elo && rating.elo < rating.high);
+
+    let perfect = Rating::from_scores(&[1.; 20]);
+    assert!(perfect.elo.is_finite() && perfect.elo > 400.);
+    assert_eq!((perfect.low, perfect.high), (perfect.elo, perfect.elo));
+    assert!((Rating::from_scores(&[0.; 20]).elo + perfect.elo).abs() < 1e-9);
+    assert!(!perfect.to_string().contains("inf"));
+}
+
+#[test]
+fn test_entrant_names() {
+    let entrant: Entrant = "hard@250".parse().unwrap();
+    assert_eq!(entrant.level, Level::Hard);
+    assert_eq!(entrant.movetime, Some(Duration::from_millis(250)));
+    assert_eq!(entrant.to_string(), "hard@250");
+    assert_eq!("easy".parse(), Ok(Entrant::new(Level::Easy)));
+    assert!("easy@".parse::<Entrant>().is_err());
+}
+
+#[test]
+fn test_tournament_rotation() {
+    let entrants = [Entrant::new(Level::Easy), Entrant::new(Level::Medium)];
+    let report = run(&entrants, 8, 3, &Knowledge::default());
+    assert_eq!(report.games, 8);
+    // every color gets used equally often
+    assert_eq!(report.seats[0].games, 8);
+    assert_eq!(report.colors.len(), 4);
+    assert!(report.colors.iter().all(|&(_, ref record)| record.games == 4));
+    let wins: u32 = report.records.iter().map(|record| record.wins).sum();
+    assert_eq!(wins + report.draws, 8);
+    assert_eq!(report.seats[0].wins + report.seats[1].wins, wins);
+    assert!(report.average_length >= 5.);
+}
@@ -0,0 +1,117 @@
+use game::Move;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: u8,
+    pub score: i32,
+    pub bound: Bound,
+    pub best: Option<Move>
+}
+
+// Fixed size hash table indexed by the low bits of a position's Zobrist
+// hash. Colliding positions simply replace each other, except that a deeper
+// result for the same position is never overwritten by a shallower one.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    used: usize
+}
+
+impl TranspositionTable {
+    pub fn new(capacity: usize) -> TranspositionTable {
+        let mut size = 1;
+        while size * 2 <= capacity {
+            size *= 2;
+        }
+        TranspositionTable {
+            entries: vec![None; size],
+            used: 0
+        }
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        (key as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.used
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used == 0
+    }
+
+    pub fn probe(&self, key: u64) -> Option<&Entry> {
+        match self.entries[self.slot(key)] {
+            Some(ref entry) if entry.key == key => Some(entry),
+            _ => None
+        }
+    }
+
+    pub fn store(&mut self, entry: Entry) {
+        let slot = self.slot(entry.key);
+        let existing = &mut self.entries[slot];
+        match *existing {
+            Some(ref old) if old.key == entry.key && old.depth > entry.depth => return,
+            Some(_) => {},
+            None => self.used += 1
+        }
+        *existing = Some(entry);
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+        self.used = 0;
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+fn entry(key: u64, depth: u8, score: i32) -> Entry {
+    Entry {
+        key: key,
+        depth: depth,
+        score: score,
+        bound: Bound::Exact,
+        best: None
+    }
+}
+
+#[test]
+fn test_table_capacity() {
+    assert_eq!(TranspositionTable::new(0).capacity(), 1);
+    assert_eq!(TranspositionTable::new(1000).capacity(), 512);
+    assert_eq!(TranspositionTable::new(1024).capacity(), 1024);
+}
+
+#[test]
+fn test_table_store_and_probe() {
+    let mut table = TranspositionTable::new(16);
+    table.store(entry(3, 2, 10));
+    assert_eq!(table.probe(3).map(|e| e.score), Some(10));
+    assert!(table.probe(19).is_none());
+    // a shallower result for the same position doesn't replace a deeper one
+    table.store(entry(3, 1, 20));
+    assert_eq!(table.probe(3).map(|e| e.score), Some(10));
+    // but a colliding position does
+    table.store(entry(19, 0, 30));
+    assert!(table.probe(3).is_none());
+    assert_eq!(table.probe(19).map(|e| e.score), Some(30));
+    assert_eq!(table.len(), 1);
+    table.clear();
+    assert!(table.is_empty());
+}
@@ -0,0 +1,267 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use model::{Board, Color, Ring, Location};
+use game::{Game, Move, Status};
+use engine::Bot;
+use series::Start;
+use render::RingColor;
+use archive;
+use cli::{supply_text, status_text, archive_game};
+
+const RESET: &'static str = "\x1b[0m";
+const BOLD: &'static str = "\x1b[1m";
+const EMPTY: &'static str = "\x1b[90m";
+
+const HELP: &'static str = "Arrow keys move, s m l choose a size, enter or space places it,
+u undoes, n starts a new game and q quits";
+
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Place,
+    Size(Ring),
+    Undo,
+    New,
+    Quit,
+    Other
+}
+
+// Puts the terminal in raw mode on the alternate screen and restores it
+// when dropped, even if a bot panics
+struct Terminal {
+    saved: String
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "min", "1"])?;
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Terminal { saved: saved.trim().to_owned() })
+    }
+
+    fn read_key(&self) -> io::Result<Key> {
+        let mut stdin = io::stdin();
+        let mut byte = [0u8];
+        stdin.read_exact(&mut byte)?;
+        Ok(match byte[0] {
+            b'\x1b' => {
+                let mut sequence = [0u8; 2];
+                stdin.read_exact(&mut sequence)?;
+                match &sequence {
+                    b"[A" => Key::Up,
+                    b"[B" => Key::Down,
+                    b"[C" => Key::Right,
+                    b"[D" => Key::Left,
+                    _ => Key::Other
+                }
+            },
+            b'\n' | b'\r' | b' ' => Key::Place,
+            b's' | b'S' | b'1' => Key::Size(Ring::Top),
+            b'm' | b'M' | b'2' => Key::Size(Ring::Middle),
+            b'l' | b'L' | b'3' => Key::Size(Ring::Bottom),
+            b'u' => Key::Undo,
+            b'n' => Key::New,
+            b'q' | b'\x03' | b'\x04' => Key::Quit,
+            _ => Key::Other
+        })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved[..]]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, "stty failed, is this a terminal?"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// The same colors the window uses for the rings
+fn paint(color: Option<Color>) -> String {
+    match color {
+        Some(_) => {
+            let (r, g, b) = RingColor::from(color).fill;
+            format!("\x1b[38;2;{};{};{}m",
+                    (r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8)
+        },
+        None => EMPTY.to_owned()
+    }
+}
+
+struct Screen<'a> {
+    game: &'a Game,
+    cursor: Location,
+    size: Ring,
+    message: &'a str
+}
+
+impl<'a> Screen<'a> {
+    // Empty rings are gray, the ring that would be placed is shown bold in
+    // the color of the player to move
+    fn ring_color(&self, location: Location, ring: Ring) -> String {
+        let board: &Board = self.game.board();
+        match (board.get_ring(location, ring), self.game.status()) {
+            (None, Status::Playing(color)) if location == self.cursor && ring == self.size =>
+                format!("{}{}", BOLD, paint(Some(color))),
+            (owner, _) => paint(owner)
+        }
+    }
+
+    // Every cell is 11 columns wide and 5 rows high with the rings nested
+    // inside each other
+    fn cell_lines(&self, location: Location) -> Vec<String> {
+        let large = self.ring_color(location, Ring::Bottom);
+        let medium = self.ring_color(location, Ring::Middle);
+        let small = self.ring_color(location, Ring::Top);
+        let dot = match self.game.board().get_ring(location, Ring::Top) {
+            Some(_) => "●",
+            None if location == self.cursor && self.size == Ring::Top => "○",
+            None => "·"
+        };
+        let (left, right) = if location == self.cursor { ("▶", "◀") } else { (" ", " ") };
+        vec![
+            format!(" {}╭───────╮{} ", large, RESET),
+            format!(" {}│ {}╭───╮ {}│{} ", large, medium, large, RESET),
+            format!("{}{}│ {}│ {}{}{} {}│ {}│{}{}",
+                    left, large, medium, small, dot, RESET, medium, large, RESET, right),
+            format!(" {}│ {}╰───╯ {}│{} ", large, medium, large, RESET),
+            format!(" {}╰───────╯{} ", large, RESET)
+        ]
+    }
+
+    fn draw(&self) -> String {
+        let mut lines = vec![format!("       a{}b{}c", " ".repeat(10), " ".repeat(10)), String::new()];
+        for y in 0..3 {
+            let cells: Vec<Vec<String>> = (0..3).map(|x| self.cell_lines((x, y))).collect();
+            for row in 0..5 {
+                let label = if row == 2 { format!("{} ", y + 1) } else { "  ".to_owned() };
+                lines.push(cells.iter().fold(label, |line, cell| line + &cell[row]));
+            }
+        }
+        lines.push(String::new());
+
+        let status = self.game.status();
+        let size = match self.size {
+            Ring::Top => "small",
+            Ring::Middle => "medium",
+            Ring::Bottom => "large"
+        };
+        match status {
+            Status::Playing(color) => lines.push(format!("{}{}{}{}, placing a {} ring",
+                                                         BOLD, paint(Some(color)),
+                                                         status_text(status), RESET, size)),
+            _ => lines.push(format!("{}{}{}", BOLD, status_text(status), RESET))
+        }
+        for &color in self.game.players() {
+            let marker = if status == Status::Playing(color) { "▶" } else { " " };
+            lines.push(format!("{} {}{}{}", marker, paint(Some(color)),
+                               supply_text(self.game, color), RESET));
+        }
+        lines.push(String::new());
+        lines.push(self.message.to_owned());
+        lines.extend(HELP.lines().map(|line| line.to_owned()));
+
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        screen.push_str(&lines.join("\r\n"));
+        screen
+    }
+}
+
+pub fn run(players: &[Color], start: Start, mut bots: Vec<(Color, Bot)>) {
+    let terminal = match Terminal::open() {
+        Ok(terminal) => terminal,
+        Err(err) => {
+            eprintln!("Could not set up the terminal: {}", err);
+            return;
+        }
+    };
+    let mut games = 0;
+    let mut game = Game::new(&start.players(players, games));
+    let mut cursor = (1, 1);
+    let mut size = Ring::Middle;
+    let mut message = String::new();
+    let mut archived = false;
+    loop {
+        // Finished games go to the archive once
+        if game.is_over() && !archived {
+            archived = true;
+            let mut record = archive::record(&game);
+            record.set_tag("Start", &start.to_string());
+            if let Some(err) = archive_game(&record) {
+                message = err;
+            }
+        }
+        {
+            let screen = Screen {
+                game: &game,
+                cursor: cursor,
+                size: size,
+                message: &message
+            };
+            print!("{}", screen.draw());
+            io::stdout().flush().unwrap();
+        }
+
+        if let Status::Playing(color) = game.status() {
+            if let Some(&mut (_, ref mut bot)) = bots.iter_mut().find(|&&mut (c, _)| c == color) {
+                let mv = bot.choose(&game).unwrap();
+                game.place(mv).unwrap();
+                message = format!("{:?} played {}", color, mv);
+                continue;
+            }
+        }
+
+        let key = match terminal.read_key() {
+            Ok(key) => key,
+            Err(_) => return
+        };
+        message.clear();
+        let (x, y) = cursor;
+        match key {
+            Key::Up => cursor = (x, (y + 2) % 3),
+            Key::Down => cursor = (x, (y + 1) % 3),
+            Key::Left => cursor = ((x + 2) % 3, y),
+            Key::Right => cursor = ((x + 1) % 3, y),
+            Key::Size(ring) => size = ring,
+            Key::Place => {
+                if game.is_over() {
+                    continue;
+                }
+                if let Err(err) = game.place(Move::new(cursor, size)) {
+                    message = err.to_string();
+                }
+            },
+            Key::Undo => {
+                // Take back the bots' replies too
+                game.undo();
+                while !game.history().is_empty() &&
+                    bots.iter().any(|&(c, _)| c == game.current()) {
+                    game.undo();
+                }
+                archived = archived && game.is_over();
+            },
+            Key::New => {
+                games += 1;
+                game = Game::new(&start.players(players, games));
+                archived = false;
+            },
+            Key::Quit => return,
+            Key::Other => {}
+        }
+    }
+}
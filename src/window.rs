@@ -1,5 +1,8 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::io;
+use std::fs;
+use std::time::{Duration, Instant};
 
 use slog::Logger;
 
@@ -8,10 +11,31 @@ use gtk;
 use gio::ApplicationExt;
 use gtk::{Application, Window, WindowType, HeaderBar, Grid,
           MessageDialog, DialogExt, WindowExt, WidgetExt,
-          ContainerExt};
+          ContainerExt, ToggleButton, ToggleButtonExt, Continue,
+          Button, ButtonExt, FileChooserDialog, FileChooserAction,
+          FileChooserExt, ResponseType, Dialog, Label, ListBox,
+          ListBoxRow, ScrolledWindow, ScrolledWindowExt, PolicyType,
+          CheckButton, ComboBoxText, ComboBoxExt, Entry, EntryExt};
+use gtk::prelude::DialogExtManual;
 
-use model::{Board, Color, Ring, Location, location_to_index, index_to_location};
+use model::{Color, Ring, Location, COLORS, RINGS, location_to_index, index_to_location};
+use game::{Game, Move, MoveError, Status};
+use engine::LEVELS;
+use book::Book;
+use external::{ExternalEngine, Poll};
+use net::{Connection, Client, Event};
+use discovery::{Browser, Found};
+use clock::{Clock, TimeControl};
+use series::{Series, Length, Start};
+use setup::{Setup, Seat, Player, VARIANTS};
+use profile::{Profile, Profiles};
+use protocol::Command;
 use cell::Cell;
+use render::{self, Marks};
+use archive::{self, Statistics};
+use journal;
+use record::Record;
+use cli::{self, clock_text};
 
 const APPLICATION_TITLE: &'static str = "Three";
 const APPLICATION_ID: &'static str = "org.three";
@@ -21,15 +45,77 @@ pub struct MainWindow {
     window: Rc<Window>,
     header_bar: HeaderBar,
     cells: Vec<Rc<Cell>>,
-    board: RefCell<Board>,
-    current_color: RefCell<Color>,
+    game: RefCell<Game>,
+    book: Option<Book>,
+    book_button: ToggleButton,
+    new_game_button: Button,
+    resign_button: Button,
+    draw_button: Button,
+    engines: RefCell<Vec<(Color, ExternalEngine)>>,
+    // Colors played by built-in bots of the setup rather than engines
+    // given on the command line
+    bots: RefCell<Vec<Color>>,
+    setup: RefCell<Setup>,
+    profiles: RefCell<Profiles>,
+    start: Start,
+    // Games started so far, which tells whose turn it is to start the next
+    games: std::cell::Cell<usize>,
+    // Changes with every game so that answers meant for an earlier one are
+    // thrown away
+    game_id: std::cell::Cell<usize>,
+    connection: RefCell<Option<Connection>>,
+    // Whether the connection is being polled
+    watching: std::cell::Cell<bool>,
+    clock: RefCell<Option<Clock>>,
+    // Whether the clock is being counted down
+    clock_watched: std::cell::Cell<bool>,
+    series: RefCell<Option<Series>>,
+    // Starting a new game starts a new match of the same length
+    series_length: Option<Length>,
+    score_label: Label,
     log: Logger
 }
 
-const STARTING_COLOR: Color = Color::Blue;
+// Thinking time given to external engines for every move
+const ENGINE_MOVE_TIME: u64 = 1000;
+
+// How often networked games check for moves from other machines
+const NETWORK_POLL_INTERVAL: u32 = 50;
+
+// How often the clocks are redrawn and checked for time running out
+const CLOCK_INTERVAL: u32 = 100;
+
+// How long the end of one game of a match stays on the board before the
+// next one starts
+const NEXT_GAME_DELAY: u32 = 3000;
+
+// Width and height of exported images in pixels
+const EXPORT_SIZE: i32 = 480;
+
+// How often and how many times a dropped connection is tried again, about
+// a minute in all
+const RECONNECT_INTERVAL: u32 = 2000;
+const RECONNECT_ATTEMPTS: u32 = 30;
+
+// How often the list of games on the network is refreshed
+const DISCOVERY_REFRESH_INTERVAL: u32 = 500;
+
+// Response of the join dialog's Watch button
+const WATCH_RESPONSE: i32 = 1;
+
+// Response of the new game dialog's Players button
+const PLAYERS_RESPONSE: i32 = 2;
+
+// What players without a profile are called in the new game dialog
+const GUEST: &'static str = "Guest";
 
 impl MainWindow {
-    pub fn new(log: Logger) -> Rc<MainWindow> {
+    pub fn new(log: Logger,
+               engines: Vec<(Color, ExternalEngine)>,
+               connection: Option<Connection>,
+               time_control: Option<TimeControl>,
+               series: Option<Length>,
+               start: Start) -> Rc<MainWindow> {
         let app = Application::new(Some(APPLICATION_ID),
                                    gio::APPLICATION_FLAGS_NONE)
             .unwrap();
@@ -56,23 +142,102 @@ impl MainWindow {
 
         win.add(&grid);
 
+        let book = Book::default_path().and_then(|path| Book::load(path).ok());
+        let book_button = ToggleButton::new_with_label("Book");
+        book_button.set_tooltip_text(Some("Show moves from the opening book"));
+        if book.is_some() {
+            header.pack_end(&book_button);
+        }
+
+        let new_game_button = Button::new_with_label("New game…");
+        new_game_button.set_tooltip_text(Some("Choose the players and rules and start again"));
+        header.pack_start(&new_game_button);
+
+        let export_button = Button::new_with_label("Export image…");
+        export_button.set_tooltip_text(Some("Save the board as a PNG or SVG image"));
+        header.pack_start(&export_button);
+
+        let join_button = Button::new_with_label("Join game…");
+        join_button.set_tooltip_text(Some("Join a game hosted on the local network"));
+        header.pack_start(&join_button);
+
+        let statistics_button = Button::new_with_label("Statistics");
+        statistics_button.set_tooltip_text(Some("Show how the archived games went"));
+        header.pack_start(&statistics_button);
+
+        let resign_button = Button::new_with_label("Resign");
+        resign_button.set_tooltip_text(Some("Give up the game"));
+        header.pack_end(&resign_button);
+
+        let draw_button = Button::new_with_label("Offer draw");
+        draw_button.set_tooltip_text(Some("Ask the other players to end the game in a draw"));
+        header.pack_end(&draw_button);
+
+        let score_label = Label::new(None);
+        if series.is_some() {
+            header.pack_start(&score_label);
+        }
+
+        // The last settings, with the command line taking precedence
+        let mut setup = Setup::default_path()
+            .and_then(|path| Setup::load(path).ok())
+            .unwrap_or_default();
+        if time_control.is_some() {
+            setup.time_control = time_control;
+        }
+        // Everybody with a name has a profile
+        let mut profiles = Profiles::default_path()
+            .and_then(|path| Profiles::load(path).ok())
+            .unwrap_or_default();
+        for seat in setup.seats.iter().filter(|seat| !seat.name.is_empty()) {
+            if profiles.get(&seat.name).is_none() {
+                let _ = profiles.add(Profile::new(&seat.name, Some(seat.color)));
+            }
+        }
+
         let win1 = win.clone();
         app.connect_activate(move |app| {
             app.add_window(&*win1);
             win1.show_all();
         });
 
+        // A joined game may already be under way. Games on this machine
+        // begin once the window is showing.
+        let game = match connection {
+            Some(ref connection) => connection.game().clone(),
+            None => Game::new(&setup.players())
+        };
+
         let main_win = MainWindow {
             application: app,
             window: win,
             header_bar: header,
             cells: cells,
-            board: RefCell::new(Board::empty()),
-            current_color: RefCell::new(STARTING_COLOR),
+            game: RefCell::new(game),
+            book: book,
+            book_button: book_button,
+            new_game_button: new_game_button,
+            resign_button: resign_button,
+            draw_button: draw_button,
+            engines: RefCell::new(engines),
+            bots: RefCell::new(vec![]),
+            setup: RefCell::new(setup),
+            profiles: RefCell::new(profiles),
+            start: start,
+            games: std::cell::Cell::new(0),
+            game_id: std::cell::Cell::new(0),
+            connection: RefCell::new(connection),
+            watching: std::cell::Cell::new(false),
+            clock: RefCell::new(None),
+            clock_watched: std::cell::Cell::new(false),
+            series: RefCell::new(None),
+            series_length: series,
+            score_label: score_label,
             log: log
         };
 
         let main_win = Rc::new(main_win);
+        main_win.show_board();
 
         for (i, cell) in main_win.cells.iter().enumerate() {
             // I think this creates a reference cycle
@@ -81,10 +246,48 @@ impl MainWindow {
             let mut callback = cell.callback.borrow_mut();
             let pos = index_to_location(3, i);
             *callback = Some(Box::new(
-                move |ring| MainWindow::ring_pressed_handler(&*main_win1, pos, ring)));
+                move |ring| MainWindow::ring_pressed_handler(&main_win1, pos, ring)));
         }
 
+        let main_win1 = main_win.clone();
+        main_win.book_button.connect_toggled(
+            move |_| MainWindow::update_hints(&*main_win1));
+
+        let main_win1 = main_win.clone();
+        export_button.connect_clicked(
+            move |_| MainWindow::export_image(&*main_win1));
+
+        let main_win1 = main_win.clone();
+        join_button.connect_clicked(
+            move |_| MainWindow::join_game(&main_win1));
+
+        let main_win1 = main_win.clone();
+        statistics_button.connect_clicked(
+            move |_| MainWindow::show_statistics(&*main_win1));
+
+        let main_win1 = main_win.clone();
+        main_win.resign_button.connect_clicked(
+            move |_| MainWindow::resign(&main_win1));
+
+        let main_win1 = main_win.clone();
+        main_win.draw_button.connect_clicked(
+            move |_| MainWindow::offer_draw(&main_win1));
+
+        let main_win1 = main_win.clone();
+        main_win.new_game_button.connect_clicked(
+            move |_| MainWindow::new_game(&main_win1, false));
+
         MainWindow::update_turn_indicator(&*main_win);
+        if main_win.connection.borrow().is_some() {
+            MainWindow::request_engine_move(&main_win);
+            MainWindow::watch_connection(&main_win);
+        } else {
+            let main_win1 = main_win.clone();
+            let first = std::cell::Cell::new(true);
+            main_win.application.connect_activate(move |_| if first.replace(false) {
+                MainWindow::launch(&main_win1);
+            });
+        }
 
         main_win
     }
@@ -94,63 +297,1219 @@ impl MainWindow {
         self.application.run(0, &[]);
     }
 
-    fn ring_pressed_handler(&self, (x, y): Location, ring: Ring) {
-        debug!(self.log, "ring pressed event"; "x" => x, "y" => y, "ring" => format!("{:?}", ring));
-        if self.board.borrow().get_ring((x as usize, y as usize), ring).is_none() {
-            let current_color = *self.current_color.borrow();
-            self.board.borrow_mut().set_ring((x as usize, y as usize), ring, current_color);
-            let cell = self.cell_at((x, y));
-            cell.set_ring(ring, Some(current_color));
-            self.change_turn();
-            self.check_state();
+    fn ring_pressed_handler(this: &Rc<MainWindow>, (x, y): Location, ring: Ring) {
+        debug!(this.log, "ring pressed event"; "x" => x, "y" => y, "ring" => format!("{:?}", ring),
+               "board" => format!("\n{:#}", this.game.borrow().board()));
+        let current_color = this.game.borrow().current();
+        if this.is_engine(current_color) {
+            debug!(this.log, "waiting for engine"; "color" => format!("{:?}", current_color));
+            return;
+        }
+        if !this.is_local(current_color) {
+            debug!(this.log, "waiting for remote player"; "color" => format!("{:?}", current_color));
+            return;
+        }
+        match MainWindow::commit_move(this, Move::new((x, y), ring)) {
+            Ok(true) => MainWindow::request_engine_move(this),
+            Ok(false) => {},
+            Err(err) => {
+                debug!(this.log, "move rejected"; "reason" => err);
+            }
+        }
+    }
+
+    // Plays a move made on this machine. In networked games the host is told
+    // about it first, and clients only place it once the host has accepted
+    // it, in which case this returns false.
+    fn commit_move(this: &Rc<MainWindow>, mv: Move) -> Result<bool, String> {
+        match *this.connection.borrow_mut() {
+            Some(Connection::Host(ref mut host)) => host.play(mv).map_err(|err| err.to_string())?,
+            Some(Connection::Client(ref mut client)) =>
+                return client.send_move(mv).map(|_| false).map_err(|err| err.to_string()),
+            None => {}
+        }
+        MainWindow::play_move(this, mv).map(|_| true).map_err(|err| err.to_string())
+    }
+
+    fn play_move(this: &Rc<MainWindow>, mv: Move) -> Result<(), MoveError> {
+        let current_color = this.game.borrow().current();
+        this.game.borrow_mut().place(mv)?;
+        if let Some(ref mut clock) = *this.clock.borrow_mut() {
+            clock.moved(Instant::now());
+        }
+        this.run_clock();
+        let cell = this.cell_at(mv.location);
+        cell.set_ring(mv.ring, Some(current_color));
+        this.update_turn_indicator();
+        this.update_hints();
+        MainWindow::check_state(this);
+        Ok(())
+    }
+
+    fn is_engine(&self, color: Color) -> bool {
+        self.engines.borrow().iter().any(|&(c, _)| c == color)
+    }
+
+    // Whether the color is played on this machine rather than over the
+    // network
+    fn is_local(&self, color: Color) -> bool {
+        self.connection.borrow().as_ref().map_or(true, |connection| connection.is_local(color))
+    }
+
+    // Applies moves from the other machines as they arrive
+    fn watch_connection(this: &Rc<MainWindow>) {
+        if this.connection.borrow().is_none() || this.watching.get() {
+            return;
+        }
+        this.watching.set(true);
+        let this = this.clone();
+        gtk::timeout_add(NETWORK_POLL_INTERVAL, move || {
+            let events = match *this.connection.borrow_mut() {
+                Some(ref mut connection) => connection.poll(),
+                None => {
+                    this.watching.set(false);
+                    return Continue(false);
+                }
+            };
+            for event in events {
+                debug!(this.log, "network event"; "event" => format!("{:?}", event));
+                match event {
+                    Event::Placed(color, mv) => {
+                        if let Err(err) = MainWindow::play_move(&this, mv) {
+                            this.show_message(&format!("{:?} played {}: {}", color, mv, err));
+                        }
+                        MainWindow::request_engine_move(&this);
+                    },
+                    Event::Rejected(mv, reason) =>
+                        this.show_message(&format!("The host refused {}: {}", mv, reason)),
+                    Event::Joined(_) | Event::Paused(_) | Event::Resumed(_) =>
+                        this.update_turn_indicator(),
+                    Event::Watching | Event::Status(_) => {},
+                    Event::Left(color) => {
+                        this.update_turn_indicator();
+                        this.show_message(&format!("{:?} left the game", color));
+                    },
+                    Event::Replaced(color) => this.show_message(
+                        &format!("{:?} did not come back, the computer plays for them", color)),
+                    Event::Resigned(color) => MainWindow::player_resigned(&this, color),
+                    Event::Forfeited(color) => {
+                        if let Err(err) = this.game.borrow_mut().forfeit(color) {
+                            debug!(this.log, "forfeit failed"; "reason" => err.to_string());
+                        }
+                        this.update_turn_indicator();
+                        this.show_message(&format!("{:?} did not come back and forfeits", color));
+                        MainWindow::check_state(&this);
+                        MainWindow::request_engine_move(&this);
+                    },
+                    Event::Disconnected => {
+                        this.watching.set(false);
+                        MainWindow::reconnect(&this);
+                        return Continue(false);
+                    }
+                }
+            }
+            Continue(true)
+        });
+    }
+
+    // Tries to get back into the game after the connection to the host
+    // dropped, the seat is kept for a while
+    fn reconnect(this: &Rc<MainWindow>) {
+        this.header_bar.set_subtitle(Some("Connection lost, reconnecting…"));
+        let this = this.clone();
+        let mut attempts = 0;
+        gtk::timeout_add(RECONNECT_INTERVAL, move || {
+            attempts += 1;
+            let result = match *this.connection.borrow() {
+                Some(Connection::Client(ref client)) => client.reconnect(),
+                _ => return Continue(false)
+            };
+            match result {
+                Ok(client) => {
+                    debug!(this.log, "reconnected"; "attempts" => attempts);
+                    MainWindow::start_connection(&this, Connection::Client(client));
+                },
+                Err(ref err) if err.kind() != io::ErrorKind::PermissionDenied &&
+                    attempts < RECONNECT_ATTEMPTS => return Continue(true),
+                Err(err) => {
+                    this.update_turn_indicator();
+                    this.show_message(&format!("Lost the connection to the host: {}", err));
+                }
+            }
+            Continue(false)
+        });
+    }
+
+    // Asks the engine playing the next color for a move and keeps polling
+    // for the answer from the main loop
+    fn request_engine_move(this: &Rc<MainWindow>) {
+        let color = match this.game.borrow().status() {
+            Status::Playing(color) if this.is_local(color) => color,
+            _ => return
+        };
+        let result = {
+            let game = this.game.borrow();
+            let mut engines = this.engines.borrow_mut();
+            match engines.iter_mut().find(|&&mut (c, _)| c == color) {
+                Some(&mut (_, ref mut engine)) => engine.request_move(&game, this.engine_move_time(color)),
+                None => return
+            }
+        };
+        if let Err(err) = result {
+            this.show_message(&format!("The engine playing {:?} failed: {}", color, err));
+            return;
+        }
+        let game_id = this.game_id.get();
+        let this = this.clone();
+        gtk::timeout_add(50, move || {
+            if this.game_id.get() != game_id {
+                return Continue(false);
+            }
+            let poll = {
+                let mut engines = this.engines.borrow_mut();
+                match engines.iter_mut().find(|&&mut (c, _)| c == color) {
+                    Some(&mut (_, ref mut engine)) => engine.poll_move(),
+                    None => Poll::Disconnected
+                }
+            };
+            match poll {
+                Poll::Waiting => return Continue(true),
+                // The engine's color ran out of time while it was thinking
+                Poll::Move(Some(_)) if this.game.borrow().status() != Status::Playing(color) => {},
+                Poll::Move(Some(mv)) => {
+                    debug!(this.log, "engine move"; "color" => format!("{:?}", color), "move" => format!("{}", mv));
+                    match MainWindow::commit_move(&this, mv) {
+                        Ok(true) => MainWindow::request_engine_move(&this),
+                        Ok(false) => {},
+                        Err(err) => this.show_message(
+                            &format!("The engine playing {:?} tried {}: {}", color, mv, err))
+                    }
+                },
+                Poll::Move(None) => this.show_message(
+                    &format!("The engine playing {:?} found no move", color)),
+                Poll::Disconnected => this.show_message(
+                    &format!("The engine playing {:?} quit", color))
+            }
+            Continue(false)
+        });
+    }
+
+    // The color the buttons act for: the player to move, or while an engine
+    // or someone on another machine is thinking, whoever plays here
+    fn acting_color(&self) -> Option<Color> {
+        let game = self.game.borrow();
+        if game.is_over() {
+            return None;
+        }
+        let humans: Vec<Color> = game.players().iter()
+            .cloned()
+            .filter(|&color| game.is_active(color) && self.is_local(color) && !self.is_engine(color))
+            .collect();
+        if humans.contains(&game.current()) {
+            Some(game.current())
+        } else {
+            humans.first().cloned()
+        }
+    }
+
+    fn resign(this: &Rc<MainWindow>) {
+        let color = match this.acting_color() {
+            Some(color) => color,
+            None => return
+        };
+        if !this.ask(&format!("Resign as {:?}?", color)) {
+            return;
+        }
+        debug!(this.log, "resign"; "color" => format!("{:?}", color));
+        // Clients leave the game once the host has heard about it
+        let result = match *this.connection.borrow_mut() {
+            Some(Connection::Host(ref mut host)) =>
+                host.resign(color).map(|_| true).map_err(|err| err.to_string()),
+            Some(Connection::Client(ref mut client)) =>
+                client.resign().map(|_| false).map_err(|err| err.to_string()),
+            None => Ok(true)
+        };
+        match result {
+            Ok(true) => MainWindow::player_resigned(this, color),
+            Ok(false) => {},
+            Err(err) => this.show_message(&format!("Could not resign: {}", err))
+        }
+    }
+
+    // Takes a color out of the game after it gave up here or elsewhere
+    fn player_resigned(this: &Rc<MainWindow>, color: Color) {
+        if let Err(err) = this.game.borrow_mut().resign(color) {
+            debug!(this.log, "resign failed"; "reason" => err.to_string());
+        }
+        this.run_clock();
+        this.update_turn_indicator();
+        if !this.game.borrow().is_over() {
+            this.show_message(&format!("{:?} resigns", color));
+        }
+        MainWindow::check_state(this);
+        MainWindow::request_engine_move(this);
+    }
+
+    // Everyone else still in the game has to agree. Engines can't be asked
+    // and always decline.
+    fn offer_draw(this: &Rc<MainWindow>) {
+        let color = match this.acting_color() {
+            Some(color) => color,
+            None => return
+        };
+        let others: Vec<Color> = {
+            let game = this.game.borrow();
+            game.players().iter().cloned().filter(|&c| c != color && game.is_active(c)).collect()
+        };
+        if let Some(&engine) = others.iter().find(|&&other| this.is_engine(other)) {
+            this.show_message(&format!("The engine playing {:?} declines the draw", engine));
+            return;
+        }
+        if let Some(ref mut clock) = *this.clock.borrow_mut() {
+            clock.pause(Instant::now());
+        }
+        let declined = others.iter().cloned()
+            .find(|&other| !this.ask(&format!("{:?} offers a draw. Does {:?} accept?", color, other)));
+        match declined {
+            Some(other) => this.show_message(&format!("{:?} declines the draw", other)),
+            None => {
+                debug!(this.log, "draw agreed");
+                if let Err(err) = this.game.borrow_mut().agree_draw() {
+                    debug!(this.log, "draw failed"; "reason" => err.to_string());
+                }
+            }
+        }
+        this.run_clock();
+        this.update_turn_indicator();
+        MainWindow::check_state(this);
+    }
+
+    // Engines think for less when their clock runs low
+    fn engine_move_time(&self, color: Color) -> Duration {
+        let time = Duration::from_millis(ENGINE_MOVE_TIME);
+        match *self.clock.borrow() {
+            Some(ref clock) => ::std::cmp::min(time, clock.remaining(color, Instant::now()) / 4),
+            None => time
+        }
+    }
+
+    // Runs the time of whoever is to move, or stops the clock once the game
+    // is over
+    fn run_clock(&self) {
+        let status = self.game.borrow().status();
+        if let Some(ref mut clock) = *self.clock.borrow_mut() {
+            match status {
+                Status::Playing(color) => clock.start(color, Instant::now()),
+                _ => clock.stop()
+            }
         }
     }
 
-    fn change_turn(&self) {
-        let current = *self.current_color.borrow();
-        *self.current_color.borrow_mut() = next_color(current);
-        self.update_turn_indicator();
+    // Counts the clocks down in the header bar and takes players whose time
+    // runs out out of the game
+    fn watch_clock(this: &Rc<MainWindow>) {
+        if this.clock.borrow().is_none() || this.clock_watched.get() {
+            return;
+        }
+        this.clock_watched.set(true);
+        let this = this.clone();
+        gtk::timeout_add(CLOCK_INTERVAL, move || {
+            let expired = match *this.clock.borrow() {
+                Some(ref clock) => clock.expired(Instant::now()),
+                None => {
+                    this.clock_watched.set(false);
+                    return Continue(false);
+                }
+            };
+            if let Some(color) = expired {
+                debug!(this.log, "out of time"; "color" => format!("{:?}", color));
+                if let Some(ref mut clock) = *this.clock.borrow_mut() {
+                    clock.stop();
+                }
+                if let Err(err) = this.game.borrow_mut().forfeit(color) {
+                    debug!(this.log, "forfeit failed"; "reason" => err.to_string());
+                }
+                this.run_clock();
+                this.show_message(&format!("{:?} ran out of time", color));
+                MainWindow::check_state(&this);
+                MainWindow::request_engine_move(&this);
+            }
+            this.update_turn_indicator();
+            Continue(true)
+        });
     }
 
     fn update_turn_indicator(&self) {
-        self.header_bar.set_subtitle(Some(
-            &format!("{:?}'s turn", self.current_color.borrow())));
-    }
-
-    fn check_state(&self) {
-        let winner = self.board.borrow().winner();
-        if let Some(color) = winner {
-            debug!(self.log, "winner"; "color" => format!("{:?}", winner));
-            let mut flags = gtk::DIALOG_MODAL;
-            flags.insert(gtk::DIALOG_DESTROY_WITH_PARENT);
-            flags.insert(gtk::DIALOG_USE_HEADER_BAR);
-            let type_ = gtk::MessageType::Info;
-            let buttons = gtk::ButtonsType::Ok;
-            let dialog = MessageDialog::new::<Window>(Some(&*self.window),
-                                            flags,
-                                            type_,
-                                            buttons,
-                                            &format!("{:?} wins!", color));
-            dialog.connect_response(move |dialog, _| {
-                dialog.destroy();
+        let status = self.game.borrow().status();
+        let text = match status {
+            Status::Playing(color) => format!("{}'s turn", self.player_title(color)),
+            Status::Won(color) => format!("{} won", self.player_title(color)),
+            Status::Draw => "Draw".to_owned()
+        };
+        let text = match *self.connection.borrow() {
+            Some(Connection::Host(ref host)) if !host.paused_seats().is_empty() => {
+                let colors: Vec<String> = host.paused_seats().iter()
+                    .map(|color| format!("{:?}", color))
+                    .collect();
+                format!("{}, waiting for {} to reconnect", text, colors.join(" and "))
+            },
+            Some(Connection::Host(ref host)) if !host.open_seats().is_empty() =>
+                format!("{}, waiting for players on port {}", text, host.address().port()),
+            Some(Connection::Client(ref client)) => match client.color() {
+                Some(color) => format!("{}, you play {:?}", text, color),
+                None => format!("{}, watching", text)
+            },
+            _ => text
+        };
+        let text = match *self.clock.borrow() {
+            Some(ref clock) => format!("{}    {}", text, clock_text(clock, &self.game.borrow(), Instant::now())),
+            None => text
+        };
+        self.header_bar.set_subtitle(Some(&text));
+
+        // What can be done depends on whose turn it is as well
+        let acting = self.acting_color().is_some();
+        let local = self.connection.borrow().is_none();
+        self.resign_button.set_sensitive(acting);
+        self.draw_button.set_sensitive(acting && local);
+        self.new_game_button.set_sensitive(local);
+        // The book only knows two player games
+        self.book_button.set_sensitive(self.game.borrow().players().len() == 2);
+    }
+
+    // Players go by the name they were given for the game, or by their
+    // color in networked games and when they have none
+    fn player_name(&self, color: Color) -> String {
+        let setup = self.setup.borrow();
+        match setup.seat(color) {
+            Some(seat) if !seat.name.is_empty() && self.connection.borrow().is_none() => seat.name.clone(),
+            _ => format!("{:?}", color)
+        }
+    }
+
+    fn player_profile(&self, color: Color) -> Option<Profile> {
+        if self.connection.borrow().is_some() {
+            return None;
+        }
+        let setup = self.setup.borrow();
+        setup.seat(color).and_then(|seat| self.profiles.borrow().get(&seat.name).cloned())
+    }
+
+    // The name with the initial in front, like "[A] Ann"
+    fn player_title(&self, color: Color) -> String {
+        match self.player_profile(color) {
+            Some(profile) => format!("[{}] {}", profile.initial, profile.name),
+            None => self.player_name(color)
+        }
+    }
+
+    fn update_hints(&self) {
+        let game = self.game.borrow();
+        let moves = match self.book {
+            Some(ref book) if self.book_button.get_active() => book.moves(&game),
+            _ => vec![]
+        };
+        for (i, cell) in self.cells.iter().enumerate() {
+            let location = index_to_location(3, i);
+            cell.set_hints(moves.iter()
+                           .filter(|entry| entry.mv.location == location)
+                           .map(|entry| entry.mv.ring)
+                           .collect());
+        }
+    }
+
+    // Announces the end of a game. Games of a match go on to the next one
+    // and only the end of the whole match is announced.
+    fn check_state(this: &Rc<MainWindow>) {
+        this.journal_game();
+        let status = this.game.borrow().status();
+        let resigned: Vec<String> = this.game.borrow().resigned().iter()
+            .map(|&color| this.player_name(color))
+            .collect();
+        let text = match status {
+            Status::Won(color) if !resigned.is_empty() => {
+                debug!(this.log, "winner"; "color" => format!("{:?}", color), "resigned" => resigned.join(" "));
+                format!("{} wins, {} resigned!", this.player_title(color), resigned.join(" and "))
+            },
+            Status::Won(color) => {
+                debug!(this.log, "winner"; "color" => format!("{:?}", color));
+                format!("{} wins!", this.player_title(color))
+            },
+            Status::Draw if this.game.borrow().is_agreed_draw() => {
+                debug!(this.log, "agreed draw");
+                "The players agreed to a draw".to_owned()
+            },
+            Status::Draw => {
+                debug!(this.log, "draw");
+                "It's a draw!".to_owned()
+            },
+            Status::Playing(_) => return
+        };
+        for &mut (_, ref mut engine) in this.engines.borrow_mut().iter_mut() {
+            let _ = engine.report_result(status);
+        }
+        let records = this.record_result(status);
+        this.archive_game();
+
+        let first = this.game.borrow().players()[0];
+        let match_over = match *this.series.borrow_mut() {
+            Some(ref mut series) => {
+                series.add(first, status);
+                Some(series.is_over())
+            },
+            None => None
+        };
+        this.update_scoreboard();
+        match match_over {
+            None => this.show_message(&format!("{}{}", text, records)),
+            Some(false) => {
+                let game_id = this.game_id.get();
+                let this = this.clone();
+                gtk::timeout_add(NEXT_GAME_DELAY, move || {
+                    // Unless a new game was started in the meantime
+                    if this.game_id.get() == game_id {
+                        MainWindow::next_game(&this);
+                    }
+                    Continue(false)
+                });
+            },
+            Some(true) => {
+                let summary = this.series.borrow().as_ref().map(Series::summary).unwrap_or(text);
+                debug!(this.log, "match over");
+                this.show_message(&format!("{}{}", summary, records));
+            }
+        }
+    }
+
+    // Counts the game for the players with a profile and returns how they
+    // have done so far, to go under the result
+    fn record_result(&self, status: Status) -> String {
+        let players: Vec<(Color, String)> = self.game.borrow().players().iter()
+            .filter_map(|&color| self.player_profile(color).map(|profile| (color, profile.name)))
+            .collect();
+        if players.is_empty() {
+            return String::new();
+        }
+        self.profiles.borrow_mut().record_game(&players, status);
+        self.save_profiles();
+        let lines: Vec<String> = players.iter()
+            .filter_map(|&(color, _)| self.player_profile(color))
+            .map(|profile| format!("{}: {}", profile.name, profile.record()))
+            .collect();
+        format!("\n\n{}", lines.join("\n"))
+    }
+
+    fn archive_game(&self) {
+        let mut record = archive::record(&self.game.borrow());
+        if self.connection.borrow().is_none() {
+            record.set_tag("Start", &self.start.to_string());
+        }
+        if let Some(ref clock) = *self.clock.borrow() {
+            clock.save(&mut record);
+        }
+        if let Some(err) = cli::archive_game(&record) {
+            debug!(self.log, "archiving failed"; "error" => err);
+        }
+    }
+
+    // Win rates, winning patterns and game lengths over all archived games
+    fn show_statistics(&self) {
+        let records = match archive::default_path().map(archive::load) {
+            Some(Ok(records)) => records,
+            Some(Err(err)) => {
+                self.show_message(&format!("Could not read the archive: {}", err));
+                return;
+            },
+            None => vec![]
+        };
+        let mut flags = gtk::DIALOG_MODAL;
+        flags.insert(gtk::DIALOG_DESTROY_WITH_PARENT);
+        flags.insert(gtk::DIALOG_USE_HEADER_BAR);
+        let dialog = Dialog::new_with_buttons(Some("Statistics"), Some(&*self.window), flags,
+                                              &[("Close", ResponseType::Close.into())]);
+        let label = Label::new(None);
+        label.set_markup(&format!("<tt>{}</tt>", Statistics::new(&records)));
+        label.set_selectable(true);
+        label.set_xalign(0.);
+        let content = dialog.get_content_area();
+        content.set_border_width(12);
+        content.add(&label);
+        dialog.show_all();
+        dialog.run();
+        dialog.destroy();
+    }
+
+    // Starts the next game of the match with the next player moving first
+    fn next_game(this: &Rc<MainWindow>) {
+        let players = match *this.series.borrow() {
+            Some(ref series) => series.next_players(),
+            None => return
+        };
+        debug!(this.log, "next game"; "first" => format!("{:?}", players[0]));
+        for &mut (_, ref mut engine) in this.engines.borrow_mut().iter_mut() {
+            let _ = engine.send(&Command::NewGame);
+        }
+        this.game_id.set(this.game_id.get() + 1);
+        *this.game.borrow_mut() = Game::new(&players);
+        *this.clock.borrow_mut() = this.setup.borrow().time_control.map(Clock::new);
+        this.journal_game();
+        this.show_board();
+        this.run_clock();
+        this.update_turn_indicator();
+        this.update_hints();
+        MainWindow::request_engine_move(this);
+    }
+
+    // Asks how the next game is played and starts it. The first game starts
+    // with the last settings when the dialog is cancelled.
+    fn new_game(this: &Rc<MainWindow>, first: bool) {
+        if this.connection.borrow().is_some() {
+            return;
+        }
+        if let Some(ref mut clock) = *this.clock.borrow_mut() {
+            clock.pause(Instant::now());
+        }
+        match this.choose_setup() {
+            Some(setup) => MainWindow::start_game(this, setup),
+            None if first => {
+                let setup = this.setup.borrow().clone();
+                MainWindow::start_game(this, setup);
+            },
+            None => this.run_clock()
+        }
+    }
+
+    fn start_game(this: &Rc<MainWindow>, setup: Setup) {
+        debug!(this.log, "new game"; "players" => format!("{:?}", setup.players()),
+               "bots" => format!("{:?}", setup.bots()));
+        this.game_id.set(this.game_id.get() + 1);
+        let failed = this.start_bots(&setup);
+
+        let players = setup.players();
+        let series = this.series_length.map(|length| Series::new(length, &players, this.start));
+        let players = match series {
+            Some(ref series) => series.next_players(),
+            None => this.start.players(&players, this.games.get())
+        };
+        this.games.set(this.games.get() + 1);
+        *this.series.borrow_mut() = series;
+        *this.game.borrow_mut() = Game::new(&players);
+        *this.clock.borrow_mut() = setup.time_control.map(Clock::new);
+        *this.setup.borrow_mut() = setup;
+        this.save_setup();
+        this.journal_game();
+        MainWindow::game_started(this, failed);
+    }
+
+    // Goes on with a game that was left unfinished, played by the same
+    // players and bots as before. It no longer counts towards a match.
+    fn resume_game(this: &Rc<MainWindow>, record: Record, game: Game) {
+        debug!(this.log, "resume game"; "players" => format!("{:?}", game.players()),
+               "moves" => record.moves.len());
+        this.game_id.set(this.game_id.get() + 1);
+        let mut setup = this.setup.borrow().clone();
+        setup.seats = game.players().iter()
+            .map(|&color| setup.seat(color).cloned()
+                 .unwrap_or(Seat { color: color, name: String::new(), player: Player::Human }))
+            .collect();
+        let failed = this.start_bots(&setup);
+
+        let clock = Clock::load(&record, &game).unwrap_or_else(|err| {
+            debug!(this.log, "could not restore the clock"; "reason" => err);
+            None
+        });
+        this.games.set(this.games.get() + 1);
+        *this.series.borrow_mut() = None;
+        *this.game.borrow_mut() = game;
+        *this.clock.borrow_mut() = clock;
+        *this.setup.borrow_mut() = setup;
+        MainWindow::game_started(this, failed);
+    }
+
+    // Bots of the last game make way for the new ones, engines from the
+    // command line keep their colors. Returns what went wrong starting them.
+    fn start_bots(&self, setup: &Setup) -> Vec<String> {
+        let mut failed = vec![];
+        let mut engines = self.engines.borrow_mut();
+        let mut bots = self.bots.borrow_mut();
+        engines.retain(|&(color, _)| !bots.contains(&color));
+        bots.clear();
+        for &mut (_, ref mut engine) in engines.iter_mut() {
+            let _ = engine.send(&Command::NewGame);
+        }
+        for (color, level) in setup.bots() {
+            if engines.iter().any(|&(c, _)| c == color) {
+                continue;
+            }
+            match ExternalEngine::built_in(level) {
+                Ok(engine) => {
+                    engines.push((color, engine));
+                    bots.push(color);
+                },
+                Err(err) => failed.push(format!("Could not start the bot for {:?}: {}", color, err))
+            }
+        }
+        failed
+    }
+
+    // Shows the game that just replaced the last one and gets it going
+    fn game_started(this: &Rc<MainWindow>, failed: Vec<String>) {
+        this.show_board();
+        this.run_clock();
+        this.update_scoreboard();
+        this.update_turn_indicator();
+        this.update_hints();
+        MainWindow::watch_clock(this);
+        for message in failed {
+            this.show_message(&message);
+        }
+        MainWindow::request_engine_move(this);
+    }
+
+    // Offers to go on with a game left unfinished when the window was
+    // closed, otherwise asks who plays
+    fn launch(this: &Rc<MainWindow>) {
+        let unfinished = match journal::default_path().map(journal::load) {
+            Some(Ok(unfinished)) => unfinished,
+            Some(Err(err)) => {
+                debug!(this.log, "could not read the journal"; "reason" => err.to_string());
+                None
+            },
+            None => None
+        };
+        if let Some((record, game)) = unfinished {
+            let question = format!("A game was left unfinished after {} moves. Resume it?",
+                                   record.moves.len());
+            if this.ask(&question) {
+                MainWindow::resume_game(this, record, game);
+                return;
+            }
+        }
+        MainWindow::new_game(this, true);
+    }
+
+    // Keeps the game in progress on disk after every change. Only games
+    // played on this machine are kept and only until they end.
+    fn journal_game(&self) {
+        let path = match journal::default_path() {
+            Some(path) => path,
+            None => return
+        };
+        let game = self.game.borrow();
+        let result = if self.connection.borrow().is_some() || game.is_over() || game.history().is_empty() {
+            journal::clear(&path)
+        } else {
+            let mut record = Record::from_game(&game);
+            record.set_tag("Start", &self.start.to_string());
+            if let Some(ref clock) = *self.clock.borrow() {
+                clock.save(&mut record);
+            }
+            journal::save(&path, &record)
+        };
+        if let Err(err) = result {
+            debug!(self.log, "could not write the journal"; "reason" => err.to_string());
+        }
+    }
+
+    fn save_setup(&self) {
+        let path = match Setup::default_path() {
+            Some(path) => path,
+            None => return
+        };
+        let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| self.setup.borrow().save(&path));
+        if let Err(err) = result {
+            debug!(self.log, "could not save the setup"; "reason" => err.to_string());
+        }
+    }
+
+    fn save_profiles(&self) {
+        let path = match Profiles::default_path() {
+            Some(path) => path,
+            None => return
+        };
+        let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| self.profiles.borrow().save(&path));
+        if let Err(err) = result {
+            self.show_message(&format!("Could not save the players: {}", err));
+        }
+    }
+
+    // The new game dialog, filled in with the last settings. Every color
+    // has a row saying whether and by whom it's played. Colors nobody was
+    // named for go to whoever prefers them.
+    fn choose_setup(&self) -> Option<Setup> {
+        let setup = self.setup.borrow().clone();
+        let names: Vec<String> = COLORS.iter()
+            .map(|&color| match setup.seat(color) {
+                Some(seat) if !seat.name.is_empty() => seat.name.clone(),
+                _ => self.profiles.borrow().preferring(color)
+                    .map(|profile| profile.name.clone())
+                    .filter(|name| setup.seats.iter().all(|seat| seat.name != *name))
+                    .unwrap_or_default()
+            })
+            .collect();
+        let mut flags = gtk::DIALOG_MODAL;
+        flags.insert(gtk::DIALOG_DESTROY_WITH_PARENT);
+        flags.insert(gtk::DIALOG_USE_HEADER_BAR);
+        let accepted: i32 = ResponseType::Accept.into();
+        let dialog = Dialog::new_with_buttons(Some("New game"), Some(&*self.window), flags,
+                                              &[("Cancel", ResponseType::Cancel.into()),
+                                                ("Players…", PLAYERS_RESPONSE),
+                                                ("Start", accepted)]);
+        dialog.set_default_response(accepted);
+
+        let grid = Grid::new();
+        grid.set_border_width(12);
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        for (i, title) in ["Color", "Name", "Played by"].iter().enumerate() {
+            let label = Label::new(Some(*title));
+            label.set_xalign(0.);
+            grid.attach(&label, i as i32, 0, 1, 1);
+        }
+        let rows: Vec<(Color, CheckButton, ComboBoxText, ComboBoxText)> = COLORS.iter().enumerate()
+            .map(|(i, &color)| {
+                let seat = setup.seat(color);
+                let playing = CheckButton::new_with_label(&format!("{:?}", color));
+                playing.set_active(seat.is_some());
+                let name = ComboBoxText::new();
+                fill_names(&name, &self.profiles.borrow(), &names[i]);
+                let player = ComboBoxText::new();
+                player.append_text("Human");
+                for level in LEVELS.iter() {
+                    player.append_text(&format!("Bot, {}", level));
+                }
+                player.set_active(match seat.map(|seat| seat.player) {
+                    Some(Player::Bot(level)) => LEVELS.iter().position(|&l| l == level).unwrap() as i32 + 1,
+                    _ => 0
+                });
+                let row = i as i32 + 1;
+                grid.attach(&playing, 0, row, 1, 1);
+                grid.attach(&name, 1, row, 1, 1);
+                grid.attach(&player, 2, row, 1, 1);
+                (color, playing, name, player)
+            })
+            .collect();
+
+        // The number of players follows the colors ticked
+        let count = Label::new(Some(&format!("{} players", setup.seats.len())[..]));
+        count.set_xalign(0.);
+        grid.attach(&count, 0, 5, 3, 1);
+        let buttons: Vec<CheckButton> = rows.iter().map(|row| row.1.clone()).collect();
+        for button in buttons.iter() {
+            let (buttons, count) = (buttons.clone(), count.clone());
+            button.connect_toggled(move |_| {
+                let players = buttons.iter().filter(|button| button.get_active()).count();
+                count.set_text(&format!("{} players", players));
+            });
+        }
+
+        let rules_label = Label::new(Some("Rules"));
+        rules_label.set_xalign(0.);
+        grid.attach(&rules_label, 0, 6, 1, 1);
+        let variant = ComboBoxText::new();
+        for (i, name) in VARIANTS.iter().enumerate() {
+            variant.append_text(name);
+            if *name == setup.variant {
+                variant.set_active(i as i32);
+            }
+        }
+        grid.attach(&variant, 1, 6, 2, 1);
+
+        let clock_label = Label::new(Some("Time control"));
+        clock_label.set_xalign(0.);
+        grid.attach(&clock_label, 0, 7, 1, 1);
+        let time_control = Entry::new();
+        time_control.set_placeholder_text(Some("None, or like 300+5 or 10/move"));
+        time_control.set_text(&setup.time_control.map_or(String::new(), |control| control.to_string()));
+        grid.attach(&time_control, 1, 7, 2, 1);
+
+        let error = Label::new(None);
+        error.set_xalign(0.);
+        grid.attach(&error, 0, 8, 3, 1);
+
+        dialog.get_content_area().add(&grid);
+        dialog.show_all();
+
+        // Asks again until the setup makes sense or the dialog is cancelled
+        let chosen = loop {
+            let response = dialog.run();
+            if response == PLAYERS_RESPONSE {
+                self.edit_profiles();
+                for &(_, _, ref name, _) in rows.iter() {
+                    let chosen = chosen_name(name);
+                    fill_names(name, &self.profiles.borrow(), &chosen);
+                }
+                continue;
+            }
+            if response != accepted {
+                break None;
+            }
+            let seats: Vec<Seat> = rows.iter()
+                .filter(|&&(_, ref playing, _, _)| playing.get_active())
+                .map(|&(color, _, ref name, ref player)| Seat {
+                    color: color,
+                    name: chosen_name(name),
+                    player: match player.get_active() {
+                        n if n > 0 => Player::Bot(LEVELS[n as usize - 1]),
+                        _ => Player::Human
+                    }
+                })
+                .collect();
+            let control = match time_control.get_text().unwrap_or_default().trim() {
+                "" => Ok(None),
+                text => text.parse().map(Some)
+            };
+            let twice = seats.iter().enumerate()
+                .find(|&(i, seat)| !seat.name.is_empty() && seats[..i].iter().any(|other| other.name == seat.name));
+            if let Some((_, seat)) = twice {
+                error.set_text(&format!("{} can only play one color", seat.name));
+                continue;
+            }
+            let result = control.and_then(|control| {
+                let setup = Setup {
+                    seats: seats,
+                    variant: VARIANTS[::std::cmp::max(variant.get_active(), 0) as usize].to_owned(),
+                    time_control: control
+                };
+                setup.check().map(|_| setup)
             });
-            dialog.show();
+            match result {
+                Ok(setup) => break Some(setup),
+                Err(err) => error.set_text(&err)
+            }
+        };
+        dialog.destroy();
+        chosen
+    }
+
+    // Lets players be added, renamed in their initial, given a preferred
+    // color or removed, and shows how they have done
+    fn edit_profiles(&self) {
+        let mut flags = gtk::DIALOG_MODAL;
+        flags.insert(gtk::DIALOG_DESTROY_WITH_PARENT);
+        flags.insert(gtk::DIALOG_USE_HEADER_BAR);
+        let accepted: i32 = ResponseType::Accept.into();
+        let dialog = Dialog::new_with_buttons(Some("Players"), Some(&*self.window), flags,
+                                              &[("Cancel", ResponseType::Cancel.into()),
+                                                ("Save", accepted)]);
+        dialog.set_default_response(accepted);
+
+        let grid = Grid::new();
+        grid.set_border_width(12);
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        for (i, title) in ["Name", "Initial", "Preferred color", "Results", "Remove"].iter().enumerate() {
+            let label = Label::new(Some(*title));
+            label.set_xalign(0.);
+            grid.attach(&label, i as i32, 0, 1, 1);
+        }
+        let color_choice = |color: Option<Color>| {
+            let choice = ComboBoxText::new();
+            choice.append_text("None");
+            for color in COLORS.iter() {
+                choice.append_text(&format!("{:?}", color));
+            }
+            choice.set_active(color.map_or(0, |color| color.index() as i32 + 1));
+            choice
+        };
+        let initial_entry = |initial: &str| {
+            let entry = Entry::new();
+            entry.set_max_length(1);
+            entry.set_width_chars(2);
+            entry.set_text(initial);
+            entry
+        };
+
+        let profiles = self.profiles.borrow().all().to_vec();
+        let rows: Vec<(String, Entry, ComboBoxText, CheckButton)> = profiles.iter().enumerate()
+            .map(|(i, profile)| {
+                let row = i as i32 + 1;
+                let name = Label::new(Some(&profile.name[..]));
+                name.set_xalign(0.);
+                let initial = initial_entry(&profile.initial.to_string());
+                let color = color_choice(profile.color);
+                let results = Label::new(Some(&profile.record()[..]));
+                results.set_xalign(0.);
+                let remove = CheckButton::new();
+                grid.attach(&name, 0, row, 1, 1);
+                grid.attach(&initial, 1, row, 1, 1);
+                grid.attach(&color, 2, row, 1, 1);
+                grid.attach(&results, 3, row, 1, 1);
+                grid.attach(&remove, 4, row, 1, 1);
+                (profile.name.clone(), initial, color, remove)
+            })
+            .collect();
+
+        // A new player can be added at the bottom
+        let row = rows.len() as i32 + 1;
+        let new_name = Entry::new();
+        new_name.set_placeholder_text(Some("New player"));
+        let new_initial = initial_entry("");
+        let new_color = color_choice(None);
+        grid.attach(&new_name, 0, row, 1, 1);
+        grid.attach(&new_initial, 1, row, 1, 1);
+        grid.attach(&new_color, 2, row, 1, 1);
+        let error = Label::new(None);
+        error.set_xalign(0.);
+        grid.attach(&error, 0, row + 1, 5, 1);
+
+        dialog.get_content_area().add(&grid);
+        dialog.show_all();
+
+        let color_of = |choice: &ComboBoxText| match choice.get_active() {
+            n if n > 0 => Some(COLORS[n as usize - 1]),
+            _ => None
+        };
+        let initial_of = |entry: &Entry| entry.get_text().and_then(|text| text.trim().chars().next());
+        loop {
+            if dialog.run() != accepted {
+                break;
+            }
+            let mut edited = self.profiles.borrow().clone();
+            for &(ref name, ref initial, ref color, ref remove) in rows.iter() {
+                if remove.get_active() {
+                    edited.remove(name);
+                    continue;
+                }
+                if let Some(profile) = edited.get_mut(name) {
+                    profile.initial = initial_of(initial).unwrap_or(profile.initial);
+                    profile.color = color_of(color);
+                }
+            }
+            let name = new_name.get_text().unwrap_or_default().trim().to_owned();
+            let added: Result<(), String> = if name.is_empty() {
+                Ok(())
+            } else {
+                let mut profile = Profile::new(&name, color_of(&new_color));
+                profile.initial = initial_of(&new_initial).unwrap_or(profile.initial);
+                edited.add(profile)
+            };
+            match added {
+                Ok(()) => {
+                    *self.profiles.borrow_mut() = edited;
+                    self.save_profiles();
+                    break;
+                },
+                Err(err) => error.set_text(&err)
+            }
+        }
+        dialog.destroy();
+    }
+
+    fn update_scoreboard(&self) {
+        match *self.series.borrow() {
+            Some(ref series) if series.is_over() => self.score_label.set_text("Match over"),
+            Some(ref series) => self.score_label.set_text(&series.scoreboard()),
+            None => {}
+        }
+    }
+
+    fn export_image(&self) {
+        let dialog = FileChooserDialog::new(Some("Export image"), Some(&*self.window),
+                                            FileChooserAction::Save);
+        dialog.add_buttons(&[("Cancel", ResponseType::Cancel.into()),
+                             ("Export", ResponseType::Accept.into())]);
+        dialog.set_current_name("three.png");
+        dialog.set_do_overwrite_confirmation(true);
+        let accepted: i32 = ResponseType::Accept.into();
+        let path = if dialog.run() == accepted { dialog.get_filename() } else { None };
+        dialog.destroy();
+        if let Some(path) = path {
+            debug!(self.log, "export image"; "path" => format!("{}", path.display()));
+            let game = self.game.borrow();
+            if let Err(err) = render::save(game.board(), &Marks::from_game(&game), EXPORT_SIZE, &path) {
+                self.show_message(&format!("Could not write {}: {}", path.display(), err));
+            }
+        }
+    }
+
+    // Lists the games announced on the local network and joins or watches
+    // the chosen one
+    fn join_game(this: &Rc<MainWindow>) {
+        let mut browser = match Browser::open() {
+            Ok(browser) => browser,
+            Err(err) => {
+                this.show_message(&format!("Could not look for games: {}", err));
+                return;
+            }
+        };
+        let mut flags = gtk::DIALOG_MODAL;
+        flags.insert(gtk::DIALOG_DESTROY_WITH_PARENT);
+        flags.insert(gtk::DIALOG_USE_HEADER_BAR);
+        let accepted: i32 = ResponseType::Accept.into();
+        let dialog = Dialog::new_with_buttons(Some("Join game"), Some(&*this.window), flags,
+                                              &[("Cancel", ResponseType::Cancel.into()),
+                                                ("Watch", WATCH_RESPONSE),
+                                                ("Join", accepted)]);
+        dialog.set_default_size(400, 300);
+
+        let list = ListBox::new();
+        let placeholder = Label::new(Some("Looking for games on the local network…"));
+        placeholder.show();
+        list.set_placeholder(Some(&placeholder));
+        let scrolled = ScrolledWindow::new(None, None);
+        scrolled.set_policy(PolicyType::Never, PolicyType::Automatic);
+        scrolled.set_vexpand(true);
+        scrolled.add(&list);
+        dialog.get_content_area().add(&scrolled);
+        dialog.show_all();
+
+        let shown: Rc<RefCell<Vec<Found>>> = Rc::new(RefCell::new(vec![]));
+        MainWindow::refresh_games(&list, &mut browser, &mut shown.borrow_mut());
+        // The browser is dropped with the timeout, which stops once the
+        // dialog is closed
+        let open = Rc::new(std::cell::Cell::new(true));
+        let (list1, shown1, open1) = (list.clone(), shown.clone(), open.clone());
+        gtk::timeout_add(DISCOVERY_REFRESH_INTERVAL, move || {
+            if !open1.get() {
+                return Continue(false);
+            }
+            MainWindow::refresh_games(&list1, &mut browser, &mut shown1.borrow_mut());
+            Continue(true)
+        });
+
+        let response = dialog.run();
+        let chosen = list.get_selected_row()
+            .and_then(|row| shown.borrow().get(row.get_index() as usize).cloned());
+        open.set(false);
+        dialog.destroy();
+
+        let found = match chosen {
+            Some(found) if response == accepted || response == WATCH_RESPONSE => found,
+            _ => return
+        };
+        debug!(this.log, "join game"; "address" => format!("{}", found.address),
+               "name" => found.announcement.name.clone());
+        let client = if response == WATCH_RESPONSE {
+            Client::watch(found.address)
+        } else {
+            Client::connect(found.address)
+        };
+        match client {
+            Ok(client) => MainWindow::start_connection(this, Connection::Client(client)),
+            Err(err) => this.show_message(
+                &format!("Could not join {}: {}", found.announcement.name, err))
+        }
+    }
+
+    // Rebuilds the list of games when it has changed, keeping the selection
+    fn refresh_games(list: &ListBox, browser: &mut Browser, shown: &mut Vec<Found>) {
+        let hosts = browser.hosts();
+        if hosts == *shown {
+            return;
+        }
+        let selected = list.get_selected_row()
+            .and_then(|row| shown.get(row.get_index() as usize).map(|found| found.address));
+        for child in list.get_children() {
+            list.remove(&child);
+        }
+        for (i, found) in hosts.iter().enumerate() {
+            let announcement = &found.announcement;
+            let text = format!("{} ({}, {} players, {} open) at {}",
+                               announcement.name, announcement.variant, announcement.players.len(),
+                               announcement.open_seats.len(), found.address);
+            let label = Label::new(Some(&text[..]));
+            label.set_xalign(0.);
+            let row = ListBoxRow::new();
+            row.add(&label);
+            row.show_all();
+            list.insert(&row, i as i32);
+            if selected == Some(found.address) {
+                list.select_row(Some(&row));
+            }
+        }
+        *shown = hosts;
+    }
+
+    // Replaces the game in the window with a networked one
+    fn start_connection(this: &Rc<MainWindow>, connection: Connection) {
+        this.game_id.set(this.game_id.get() + 1);
+        // Bots from the new game dialog don't play in games on other
+        // machines, engines from the command line still do
+        {
+            let mut bots = this.bots.borrow_mut();
+            this.engines.borrow_mut().retain(|&(color, _)| !bots.contains(&color));
+            bots.clear();
+        }
+        *this.game.borrow_mut() = connection.game().clone();
+        this.show_board();
+        *this.connection.borrow_mut() = Some(connection);
+        this.journal_game();
+        // Clocks and matches are only kept on one machine
+        *this.clock.borrow_mut() = None;
+        *this.series.borrow_mut() = None;
+        this.score_label.hide();
+        this.update_turn_indicator();
+        this.update_hints();
+        MainWindow::watch_connection(this);
+        MainWindow::request_engine_move(this);
+    }
+
+    // Draws every ring of the game from scratch
+    fn show_board(&self) {
+        let game = self.game.borrow();
+        for (i, cell) in self.cells.iter().enumerate() {
+            for ring in RINGS.iter() {
+                cell.set_ring(*ring, game.board().get_ring(index_to_location(3, i), *ring));
+            }
         }
     }
 
+    fn show_message(&self, text: &str) {
+        let mut flags = gtk::DIALOG_MODAL;
+        flags.insert(gtk::DIALOG_DESTROY_WITH_PARENT);
+        flags.insert(gtk::DIALOG_USE_HEADER_BAR);
+        let type_ = gtk::MessageType::Info;
+        let buttons = gtk::ButtonsType::Ok;
+        let dialog = MessageDialog::new::<Window>(Some(&*self.window),
+                                        flags,
+                                        type_,
+                                        buttons,
+                                        text);
+        dialog.connect_response(move |dialog, _| {
+            dialog.destroy();
+        });
+        dialog.show();
+    }
+
+    fn ask(&self, text: &str) -> bool {
+        let mut flags = gtk::DIALOG_MODAL;
+        flags.insert(gtk::DIALOG_DESTROY_WITH_PARENT);
+        flags.insert(gtk::DIALOG_USE_HEADER_BAR);
+        let dialog = MessageDialog::new::<Window>(Some(&*self.window),
+                                        flags,
+                                        gtk::MessageType::Question,
+                                        gtk::ButtonsType::YesNo,
+                                        text);
+        let yes: i32 = ResponseType::Yes.into();
+        let answer = dialog.run() == yes;
+        dialog.destroy();
+        answer
+    }
+
     fn cell_at(&self, (x, y): Location) -> Rc<Cell> {
         self.cells[location_to_index(3, (x as usize, y as usize))].clone()
     }
 
 }
 
-fn next_color(color: Color) -> Color {
-    match color {
-        Color::Blue => Color::Green,
-        Color::Green => Color::Red,
-        Color::Red => Color::Yellow,
-        Color::Yellow => Color::Blue
+// Fills a name list with the profiles, choosing the given one, or Guest for
+// players who go by their color
+fn fill_names(names: &ComboBoxText, profiles: &Profiles, chosen: &str) {
+    names.remove_all();
+    names.append_text(GUEST);
+    for profile in profiles.all() {
+        names.append_text(&profile.name);
     }
+    let index = profiles.all().iter().position(|profile| profile.name == chosen);
+    names.set_active(index.map_or(0, |index| index as i32 + 1));
 }
 
-
+fn chosen_name(names: &ComboBoxText) -> String {
+    match names.get_active() {
+        n if n > 0 => names.get_active_text().unwrap_or_default(),
+        _ => String::new()
+    }
+}
@@ -0,0 +1,77 @@
+use model::{Board, Color, Ring, Location, COLORS, RINGS, location_to_index,
+            index_to_location};
+use game::{Supply, RINGS_PER_SIZE};
+
+// Keys are laid out as one flat sequence and derived on demand from the key
+// index. This keeps them identical between runs, which matters for anything
+// that ends up storing hashes on disk.
+const RING_KEYS: usize = 0;
+const TURN_KEYS: usize = RING_KEYS + 4 * 3 * 9;
+const SUPPLY_KEYS: usize = TURN_KEYS + 4;
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn key(index: usize) -> u64 {
+    splitmix64(index as u64)
+}
+
+pub fn ring_key(color: Color, location: Location, ring: Ring) -> u64 {
+    let cell = location_to_index(3, location);
+    key(RING_KEYS + (color.index() * 3 + ring.index()) * 9 + cell)
+}
+
+pub fn turn_key(color: Color) -> u64 {
+    key(TURN_KEYS + color.index())
+}
+
+pub fn supply_key(color: Color, ring: Ring, remaining: u8) -> u64 {
+    let counts = RINGS_PER_SIZE as usize + 1;
+    key(SUPPLY_KEYS + (color.index() * 3 + ring.index()) * counts + remaining as usize)
+}
+
+// Computes the hash of a position from scratch. Games keep their hash up to
+// date incrementally, this is mostly useful for checking that they do.
+pub fn hash(board: &Board, to_move: Color, supplies: &[Supply; 4]) -> u64 {
+    let mut hash = turn_key(to_move);
+    for i in 0..9 {
+        let location = index_to_location(3, i);
+        for ring in RINGS.iter() {
+            if let Some(color) = board.get_ring(location, *ring) {
+                hash ^= ring_key(color, location, *ring);
+            }
+        }
+    }
+    for color in COLORS.iter() {
+        for ring in RINGS.iter() {
+            hash ^= supply_key(*color, *ring, supplies[color.index()].remaining(*ring));
+        }
+    }
+    hash
+}
+
+// Tests
+
+#[test]
+fn test_keys_are_distinct() {
+    let mut keys = vec![];
+    for color in COLORS.iter() {
+        keys.push(turn_key(*color));
+        for ring in RINGS.iter() {
+            for i in 0..9 {
+                keys.push(ring_key(*color, index_to_location(3, i), *ring));
+            }
+            for n in 0..RINGS_PER_SIZE + 1 {
+                keys.push(supply_key(*color, *ring, n));
+            }
+        }
+    }
+    let count = keys.len();
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), count);
+}